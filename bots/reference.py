@@ -0,0 +1,71 @@
+#!/usr/bin/env python3
+"""Reference bot for the external bot protocol, see src/external.rs.
+
+Run it with `popongong --bot-process right=bots/reference.py` or pit two
+bots against each other with `popongong bot-match bots/reference.py OTHER`.
+
+It reads one JSON message per line on stdin and, for every tick, moves each
+of its paddles towards the nearest ball coming its way, or back to the
+middle when nothing is.
+"""
+
+import json
+import sys
+
+# Don't chase the ball closer than this, it only makes the paddle jitter.
+SLACK = 8
+
+
+def coming(side, ball):
+    return {
+        "left": ball["vx"] < 0,
+        "right": ball["vx"] > 0,
+        "top": ball["vy"] < 0,
+        "bottom": ball["vy"] > 0,
+    }[side]
+
+
+def move(game, paddle):
+    side = paddle["side"]
+    # Top and bottom paddles move along the x axis.
+    axis, size = ("x", "width") if side in ("top", "bottom") else ("y", "height")
+    center = paddle[axis] + paddle[size] / 2
+
+    balls = [ball for ball in game["balls"] if coming(side, ball)]
+    if balls:
+        other = "y" if axis == "x" else "x"
+        nearest = min(balls, key=lambda ball: abs(ball[other] - paddle[other]))
+        target = nearest[axis] + nearest[size] / 2
+    else:
+        target = game[size] / 2
+
+    if target < center - SLACK:
+        return "up"
+    if target > center + SLACK:
+        return "down"
+    return "stay"
+
+
+def main():
+    sides = []
+
+    for line in sys.stdin:
+        message = json.loads(line)
+
+        if message["type"] == "hello":
+            sides = message["sides"]
+        elif message["type"] == "tick":
+            moves = [
+                {"path": game["path"], "side": paddle["side"], "move": move(game, paddle)}
+                for game in message["games"]
+                for paddle in game["paddles"]
+                if paddle["side"] in sides
+            ]
+            print(json.dumps({"tick": message["tick"], "moves": moves}), flush=True)
+        elif message["type"] == "end":
+            print("reference bot: final score", message["points"], file=sys.stderr)
+            break
+
+
+if __name__ == "__main__":
+    main()
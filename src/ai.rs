@@ -0,0 +1,150 @@
+use rand::{thread_rng, Rng};
+
+use std::collections::HashMap;
+
+use component::{Ball, Side};
+
+use game::{Action, Game};
+
+use nest::{Nest};
+
+// How well the computer plays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difficulty {
+    // How often it looks at the ball again, in ms.
+    pub reaction_ms: u64,
+    // How far off it aims, as a fraction of the paddle length either way.
+    pub error: f64,
+    // Whether it heads back to the middle while the ball is going away.
+    pub recenter: bool,
+}
+
+impl Difficulty {
+    pub fn preset(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty { reaction_ms: 400, error: 0.6, recenter: false }),
+            "normal" => Some(Difficulty { reaction_ms: 200, error: 0.35, recenter: true }),
+            "hard" => Some(Difficulty { reaction_ms: 100, error: 0.2, recenter: true }),
+            "expert" => Some(Difficulty { reaction_ms: 40, error: 0.05, recenter: true }),
+            _ => None,
+        }
+    }
+
+    pub fn names() -> &'static [&'static str] {
+        &["easy", "normal", "hard", "expert"]
+    }
+}
+
+// A computer player for one side, in every game in the nest.
+#[derive(Debug)]
+pub struct Ai {
+    pub side: Side,
+    pub difficulty: Difficulty,
+    // Per game, keyed by its path in the nest, the time since the ball was
+    // last looked at and where the paddle is heading.
+    targets: HashMap<Vec<u32>, (u64, Option<f64>)>,
+}
+
+impl Ai {
+    pub fn new(side: Side, difficulty: Difficulty) -> Ai {
+        Ai { side: side, difficulty: difficulty, targets: HashMap::new() }
+    }
+
+    pub fn control(&mut self, nest: &mut Nest, delta_ms: u64) -> () {
+        let games = nest.games_mut();
+
+        // Forget games that are gone along with their ball.
+        self.targets.retain(|path, _| games.iter().any(|(other, _)| other == path));
+
+        for (path, game) in games {
+            let reaction_ms = self.difficulty.reaction_ms;
+            let aim = {
+                let target = self.targets.entry(path.clone()).or_insert((reaction_ms, None));
+
+                target.0 = target.0 + delta_ms;
+                target.0 >= reaction_ms
+            };
+
+            if aim {
+                let target = self.aim(game);
+                self.targets.insert(path.clone(), (0, target));
+            }
+
+            let action = match self.targets[&path].1 {
+                Some(target) => self.follow(game, target),
+                None => 0,
+            };
+
+            game.bot_actions.insert(self.side, action);
+        }
+    }
+
+    // Where along its wall the paddle should be, or nothing to stay put.
+    fn aim(&self, game: &Game) -> Option<f64> {
+        let paddle = game.paddle(self.side)?;
+        let size = game.size();
+        let horizontal = self.side.is_horizontal();
+
+        let nearest = game.balls.iter()
+            .filter(|ball| self.approaching(ball))
+            .min_by(|a, b| self.distance(a, game).partial_cmp(&self.distance(b, game)).unwrap());
+
+        match nearest {
+            Some(ball) => {
+                let center = ball.center();
+                let along = if horizontal { center.x } else { center.y };
+                let error = if self.difficulty.error > 0.0 {
+                    thread_rng().gen_range(-self.difficulty.error, self.difficulty.error)
+                } else {
+                    0.0
+                };
+
+                Some(along + error * paddle.length() as f64)
+            },
+            None if self.difficulty.recenter => Some(if horizontal { size.x / 2.0 } else { size.y / 2.0 }),
+            None => None,
+        }
+    }
+
+    fn approaching(&self, ball: &Ball) -> bool {
+        match self.side {
+            Side::One => ball.is_moving_left(),
+            Side::Two => ball.is_moving_right(),
+            Side::Three => ball.is_moving_up(),
+            Side::Four => ball.is_moving_down(),
+        }
+    }
+
+    // How far the ball has to go to reach this side's wall.
+    fn distance(&self, ball: &Ball, game: &Game) -> f64 {
+        let center = ball.center();
+        let size = game.size();
+
+        match self.side {
+            Side::One => center.x,
+            Side::Two => size.x - center.x,
+            Side::Three => center.y,
+            Side::Four => size.y - center.y,
+        }
+    }
+
+    // Move towards `target`, stopping once the paddle's middle is close
+    // enough that it wouldn't just jitter around it.
+    fn follow(&self, game: &Game, target: f64) -> Action {
+        let paddle = match game.paddle(self.side) {
+            Some(paddle) => paddle,
+            None => return 0,
+        };
+        let center = paddle.center();
+        let position = if self.side.is_horizontal() { center.x } else { center.y };
+        let dead_zone = paddle.length() as f64 / 8.0;
+
+        if target < position - dead_zone {
+            -1
+        } else if target > position + dead_zone {
+            1
+        } else {
+            0
+        }
+    }
+}
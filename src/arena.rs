@@ -0,0 +1,281 @@
+use sdl2::rect::Rect;
+
+use std::borrow::Cow;
+use std::fs;
+
+use toml;
+
+use vector::Vector;
+
+use component::{Component, FRect, Part};
+
+use constants::{Color};
+
+// An arena layout, either built in or loaded from a toml file. Layouts are
+// plain data; a `Game` builds its `Obstacle`s and `Portal`s from one.
+//
+//     name = "pillars"
+//     goal_height = 400
+//
+//     [[obstacles]]
+//     x = 580
+//     y = 0
+//     width = 40
+//     height = 120
+//     to = { x = 580, y = 300 }
+//     speed = 0.05
+//
+//     [[portals]]
+//     a = { x = 300, y = 20, width = 40, height = 40 }
+//     b = { x = 860, y = 740, width = 40, height = 40 }
+#[derive(Debug, Clone, Deserialize)]
+pub struct Arena {
+    #[serde(default)]
+    pub name: Cow<'static, str>,
+    // Height of the goal opening in the middle of the left and right walls.
+    // The rest of those walls reflect the ball without costing a hit. The
+    // whole wall is the goal when this is not set.
+    #[serde(default)]
+    pub goal_height: Option<u32>,
+    #[serde(default)]
+    pub obstacles: Cow<'static, [ObstacleLayout]>,
+    #[serde(default)]
+    pub portals: Cow<'static, [PortalLayout]>,
+}
+
+pub const EMPTY_ARENA: Arena = Arena {
+    name: Cow::Borrowed("empty"),
+    goal_height: None,
+    obstacles: Cow::Borrowed(&[]),
+    portals: Cow::Borrowed(&[]),
+};
+
+impl Arena {
+    pub fn load(path: &str) -> Result<Arena, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        toml::from_str(&contents).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Area {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Area {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ObstacleLayout {
+    pub x: f64,
+    pub y: f64,
+    pub width: u32,
+    pub height: u32,
+    // Moving obstacles travel back and forth between (x, y) and `to` at
+    // `speed` pixels per ms.
+    #[serde(default)]
+    pub to: Option<Vector>,
+    #[serde(default)]
+    pub speed: f64,
+}
+
+// A ball entering either end of a portal comes out of the other end with
+// the same velocity.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PortalLayout {
+    pub a: Area,
+    pub b: Area,
+}
+
+#[derive(Debug, Clone)]
+pub struct Obstacle {
+    pub pos: Vector,
+    pub width: u32,
+    pub height: u32,
+    pub rect: FRect,
+    pub color: Color,
+    start: Vector,
+    end: Vector,
+    speed: f64,
+    // How far along the path from `start` to `end` the obstacle is, 0 to 1.
+    progress: f64,
+    forwards: bool,
+}
+
+impl Obstacle {
+    pub fn new(layout: &ObstacleLayout) -> Obstacle {
+        let start = Vector { x: layout.x, y: layout.y };
+
+        Obstacle {
+            pos: start.clone(),
+            width: layout.width,
+            height: layout.height,
+            rect: FRect::new(layout.x, layout.y, layout.width as f64, layout.height as f64),
+            color: Color::Border,
+            end: layout.to.clone().unwrap_or(start.clone()),
+            start: start,
+            speed: layout.speed,
+            progress: 0.0,
+            forwards: true,
+        }
+    }
+
+    pub fn update(&mut self, delta_ms: u64) -> () {
+        let path = self.end.subtract(&self.start);
+        let length = (path.x * path.x + path.y * path.y).sqrt();
+
+        if length == 0.0 || self.speed == 0.0 { return; }
+
+        let step = self.speed * delta_ms as f64 / length;
+
+        if self.forwards {
+            self.progress = self.progress + step;
+        } else {
+            self.progress = self.progress - step;
+        }
+
+        if self.progress >= 1.0 {
+            self.progress = 1.0;
+            self.forwards = false;
+        } else if self.progress <= 0.0 {
+            self.progress = 0.0;
+            self.forwards = true;
+        }
+
+        self.pos = Vector {
+            x: self.start.x + path.x * self.progress,
+            y: self.start.y + path.y * self.progress,
+        };
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(self.pos.x as i32, self.pos.y as i32, self.width, self.height)
+    }
+
+    pub fn components(&mut self, origin: &Vector) -> Vec<Component<'_>> {
+        self.rect.set_x(self.pos.x + origin.x);
+        self.rect.set_y(self.pos.y + origin.y);
+
+        return vec![
+            (&self.rect, &self.color, Part::Plain),
+        ];
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Portal {
+    pub a: Rect,
+    pub b: Rect,
+    pub rect_a: FRect,
+    pub rect_b: FRect,
+    pub color: Color,
+}
+
+impl Portal {
+    pub fn new(layout: &PortalLayout) -> Portal {
+        Portal {
+            a: layout.a.rect(),
+            b: layout.b.rect(),
+            rect_a: FRect::from(&layout.a.rect()),
+            rect_b: FRect::from(&layout.b.rect()),
+            color: Color::Portal,
+        }
+    }
+
+    // Where a ball overlapping `area` should be sent, if anywhere.
+    pub fn exit_for(&self, area: Rect) -> Option<Rect> {
+        if self.a.has_intersection(area) {
+            Some(self.b)
+        } else if self.b.has_intersection(area) {
+            Some(self.a)
+        } else {
+            None
+        }
+    }
+
+    pub fn components(&mut self, origin: &Vector) -> Vec<Component<'_>> {
+        self.rect_a.set_x(self.a.x() as f64 + origin.x);
+        self.rect_a.set_y(self.a.y() as f64 + origin.y);
+        self.rect_b.set_x(self.b.x() as f64 + origin.x);
+        self.rect_b.set_y(self.b.y() as f64 + origin.y);
+
+        return vec![
+            (&self.rect_a, &self.color, Part::Plain),
+            (&self.rect_b, &self.color, Part::Plain),
+        ];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_the_shipped_arenas() {
+        for path in ["arenas/pillars.toml", "arenas/portals.toml"].iter() {
+            let arena = Arena::load(path).unwrap();
+
+            assert!(!arena.obstacles.is_empty() || !arena.portals.is_empty(), "{} is empty", path);
+        }
+
+        assert!(Arena::load("arenas/missing.toml").is_err());
+    }
+
+    #[test]
+    fn rejects_incomplete_layouts() {
+        assert!(toml::from_str::<Arena>("[[obstacles]]\nx = 1.0\ny = 2.0\n").is_err());
+        assert!(toml::from_str::<Arena>("[[portals]]\na = { x = 0, y = 0, width = 10, height = 10 }\n").is_err());
+        assert!(toml::from_str::<Arena>("goal_height = -5\n").is_err());
+
+        let arena: Arena = toml::from_str("").unwrap();
+
+        assert!(arena.goal_height.is_none() && arena.obstacles.is_empty() && arena.portals.is_empty());
+    }
+
+    #[test]
+    fn moving_obstacles_go_back_and_forth() {
+        let mut obstacle = Obstacle::new(&ObstacleLayout {
+            x: 0.0,
+            y: 0.0,
+            width: 10,
+            height: 10,
+            to: Some(Vector { x: 0.0, y: 100.0 }),
+            speed: 0.1,
+        });
+
+        obstacle.update(500);
+        assert_eq!(obstacle.pos.y, 50.0);
+
+        obstacle.update(1000);
+        assert_eq!(obstacle.pos.y, 100.0);
+
+        obstacle.update(250);
+        assert_eq!(obstacle.pos.y, 75.0);
+
+        // Without anywhere to go it stays put.
+        let mut still = Obstacle::new(&ObstacleLayout { x: 5.0, y: 5.0, width: 1, height: 1, to: None, speed: 1.0 });
+
+        still.update(1000);
+        assert_eq!((still.pos.x, still.pos.y), (5.0, 5.0));
+    }
+
+    #[test]
+    fn portals_send_balls_to_the_other_end() {
+        let portal = Portal::new(&PortalLayout {
+            a: Area { x: 0, y: 0, width: 10, height: 10 },
+            b: Area { x: 100, y: 100, width: 10, height: 10 },
+        });
+
+        assert_eq!(portal.exit_for(Rect::new(5, 5, 4, 4)), Some(portal.b));
+        assert_eq!(portal.exit_for(Rect::new(105, 95, 4, 8)), Some(portal.a));
+        assert_eq!(portal.exit_for(Rect::new(50, 50, 4, 4)), None);
+    }
+}
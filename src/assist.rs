@@ -0,0 +1,201 @@
+use sdl2::keyboard::Keycode;
+
+use std::collections::HashMap;
+
+use component::{Ball, Side};
+
+use game::{Game, PADDLE_KEYS};
+
+use nest::{Nest};
+
+// Evens out a match between players of different skill. Each value is a
+// multiple of the usual, so 1.0 changes nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handicap {
+    pub paddle_length: f64,
+    pub paddle_speed: f64,
+    // How fast the balls go while they are in this player's half.
+    pub ball_speed: f64,
+}
+
+pub const NO_HANDICAP: Handicap = Handicap {
+    paddle_length: 1.0,
+    paddle_speed: 1.0,
+    ball_speed: 1.0,
+};
+
+impl Handicap {
+    // Parses handicaps like `paddle:1.5,speed:1.2,ball:0.8`, anything left
+    // out staying as it is.
+    pub fn parse(spec: &str) -> Result<Handicap, String> {
+        let mut handicap = NO_HANDICAP;
+
+        for part in spec.split(',') {
+            let (name, value) = part.split_once(':')
+                .ok_or_else(|| format!("{}: expected NAME:VALUE", part))?;
+            let value: f64 = value.parse()
+                .map_err(|_| format!("{}: {} is not a number", part, value))?;
+
+            if value <= 0.0 {
+                return Err(format!("{}: has to be more than 0", part));
+            }
+
+            match name {
+                "paddle" => handicap.paddle_length = value,
+                "speed" => handicap.paddle_speed = value,
+                "ball" => handicap.ball_speed = value,
+                _ => return Err(format!("{}: unknown handicap, try paddle, speed or ball", name)),
+            }
+        }
+
+        Ok(handicap)
+    }
+}
+
+// Plays a paddle with a single key: holding either of its keys moves it up
+// (or left) and letting go moves it down (or right).
+#[derive(Debug)]
+pub struct OneButton {
+    pub sides: Vec<Side>,
+}
+
+impl OneButton {
+    pub fn control(&self, nest: &mut Nest, keys_pressed: &HashMap<&Keycode, bool>) -> () {
+        for side in self.sides.iter() {
+            let (up, down) = &PADDLE_KEYS[*side as usize];
+            let held = keys_pressed.contains_key(up) || keys_pressed.contains_key(down);
+
+            for (_, game) in nest.games_mut() {
+                game.bot_actions.insert(*side, if held { -1 } else { 1 });
+            }
+        }
+    }
+}
+
+// Gently moves a player's paddle towards where the nearest ball is going to
+// reach its wall, on top of whatever the player does.
+#[derive(Debug)]
+pub struct AimAssist {
+    pub side: Side,
+    // How fast it moves the paddle, as a fraction of the paddle's speed.
+    pub strength: f64,
+}
+
+impl AimAssist {
+    pub fn control(&self, nest: &mut Nest, delta_ms: u64) -> () {
+        for (_, game) in nest.games_mut() {
+            let nudge = {
+                let paddle = match game.paddle(self.side) {
+                    Some(paddle) => paddle,
+                    None => continue,
+                };
+
+                let target = game.balls.iter()
+                    .filter_map(|ball| intercept(game, self.side, ball).map(|along| (ball, along)))
+                    .min_by(|a, b| arrival(game, self.side, a.0).partial_cmp(&arrival(game, self.side, b.0)).unwrap())
+                    .map(|(_, along)| along);
+
+                let target = match target {
+                    Some(target) => target,
+                    None => continue,
+                };
+
+                let center = paddle.center();
+                let (position, speed) = if paddle.horizontal {
+                    (center.x, paddle.velocity.x)
+                } else {
+                    (center.y, paddle.velocity.y)
+                };
+                let max_step = speed * self.strength * delta_ms as f64;
+
+                (target - position).max(-max_step).min(max_step)
+            };
+
+            game.nudge_paddle(self.side, nudge);
+        }
+    }
+}
+
+// How long until `ball` reaches the wall on `side`, in ms.
+fn arrival(game: &Game, side: Side, ball: &Ball) -> f64 {
+    let center = ball.center();
+    let size = game.size();
+
+    match side {
+        Side::One => center.x / -ball.velocity.x,
+        Side::Two => (size.x - center.x) / ball.velocity.x,
+        Side::Three => center.y / -ball.velocity.y,
+        Side::Four => (size.y - center.y) / ball.velocity.y,
+    }
+}
+
+// Where along the wall on `side` the center of `ball` will be when it
+// reaches the paddle, counting bounces off the walls either side but not
+// obstacles. Nothing if it is heading away.
+pub fn intercept(game: &Game, side: Side, ball: &Ball) -> Option<f64> {
+    let paddle = game.paddle(side)?;
+    let size = game.size();
+    let center = ball.center();
+
+    // `across` is towards the paddle's wall and `along` the way the paddle
+    // moves.
+    let (across, across_speed, along, along_speed, length, half) = if side.is_horizontal() {
+        (center.y, ball.velocity.y, center.x, ball.velocity.x, size.x, ball.width as f64 / 2.0)
+    } else {
+        (center.x, ball.velocity.x, center.y, ball.velocity.y, size.y, ball.height as f64 / 2.0)
+    };
+
+    let face = match side {
+        Side::One => paddle.right_edge() as f64 + ball.width as f64 / 2.0,
+        Side::Two => paddle.left_edge() as f64 - ball.width as f64 / 2.0,
+        Side::Three => paddle.bottom_edge() as f64 + ball.height as f64 / 2.0,
+        Side::Four => paddle.top_edge() as f64 - ball.height as f64 / 2.0,
+    };
+
+    if across_speed == 0.0 { return None; }
+
+    let time = (face - across) / across_speed;
+
+    if time < 0.0 { return None; }
+
+    let span = length - half * 2.0;
+
+    if span <= 0.0 { return Some(along); }
+
+    // Unfold the bounces, the ball going back and forth over `span`.
+    let mut offset = (along - half + along_speed * time).rem_euclid(span * 2.0);
+
+    if offset > span {
+        offset = span * 2.0 - offset;
+    }
+
+    Some(half + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_handicaps() {
+        assert_eq!(Handicap::parse("paddle:1.5").unwrap(), Handicap { paddle_length: 1.5, ..NO_HANDICAP });
+        assert_eq!(
+            Handicap::parse("paddle:1.5,speed:1.2,ball:0.8").unwrap(),
+            Handicap { paddle_length: 1.5, paddle_speed: 1.2, ball_speed: 0.8 },
+        );
+        // The last one wins.
+        assert_eq!(Handicap::parse("speed:2,speed:0.5").unwrap().paddle_speed, 0.5);
+    }
+
+    #[test]
+    fn rejects_bad_handicaps() {
+        assert!(Handicap::parse("").is_err());
+        assert!(Handicap::parse("paddle").is_err());
+        assert!(Handicap::parse("paddle:").is_err());
+        assert!(Handicap::parse("paddle:big").is_err());
+        assert!(Handicap::parse("paddle:0").is_err());
+        assert!(Handicap::parse("ball:-1").is_err());
+        assert!(Handicap::parse("size:2").is_err());
+        assert!(Handicap::parse("paddle:1.5,").is_err());
+    }
+}
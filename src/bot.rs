@@ -0,0 +1,186 @@
+use std::fs;
+use std::time::{Duration};
+
+use rhai::{Dynamic, Scope};
+
+use vector::{Vector};
+
+use component::{Ball, Paddle, Side};
+
+use game::{Action, Game};
+
+use nest::{Nest};
+
+use script::{Limits, Script, count, map, number};
+
+// How much a bot gets to do deciding, every game every tick.
+const LIMITS: Limits = Limits {
+    max_operations: 100_000,
+    max_time: Duration::from_millis(2),
+    max_depth: (64, 32),
+    max_call_levels: 16,
+    max_string_size: 1024,
+    max_items: 256,
+};
+
+// A paddle played by a Rhai script, see `script.rs`. The script runs once
+// per game every tick and returns "up", "down" or "stay" ("left" and
+// "right" work too), or a number whose sign says which way to go. These
+// constants are set for it, positions in the game's own coordinates and
+// velocities in pixels per ms:
+//
+// * `paddle` - its own paddle, with `pos`, `center`, `width`, `height`,
+//   `hits`, `max_hits` and `side`
+// * `opponents` - an array of the other paddles
+// * `ball` - the nearest ball, or `()` if there isn't one, with `id`, `pos`,
+//   `center`, `velocity`, `width` and `height`
+// * `balls` - an array of every ball
+// * `field` - the `width` and `height` of the field
+// * `level` - the color level the game is on, from 0
+// * `depth` - 0 for the outer game, 1 for a game inside a ball and so on
+//
+// The file is read again whenever it changes, a broken edit keeps the last
+// version that worked.
+#[derive(Debug)]
+pub struct ScriptBot {
+    pub side: Side,
+    pub path: String,
+    script: Script,
+    // So the same error isn't printed every tick.
+    last_error: Option<String>,
+}
+
+impl ScriptBot {
+    pub fn load(side: Side, path: &str) -> Result<ScriptBot, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let script = Script::parse(&source, &LIMITS).map_err(|e| format!("{}: {}", path, e))?;
+
+        Ok(ScriptBot {
+            side: side,
+            path: path.to_string(),
+            script: script,
+            last_error: None,
+        })
+    }
+
+    // Read the script file again after it has been saved, keeping the last
+    // version if the new one doesn't load.
+    pub fn reload(&mut self) -> Result<(), String> {
+        let source = fs::read_to_string(&self.path).map_err(|e| format!("{}: {}", self.path, e))?;
+
+        self.script = Script::parse(&source, &LIMITS).map_err(|e| format!("{}: {}", self.path, e))?;
+        self.last_error = None;
+
+        Ok(())
+    }
+
+    // Decide how to move this bot's paddle in every game in the nest.
+    pub fn control(&mut self, nest: &mut Nest) -> () {
+        for (path, game) in nest.games_mut() {
+            let action = self.decide(game, path.len());
+
+            game.bot_actions.insert(self.side, action);
+        }
+    }
+
+    fn decide(&mut self, game: &Game, depth: usize) -> Action {
+        let mut scope = match state(game, self.side, depth) {
+            Some(scope) => scope,
+            None => return 0,
+        };
+
+        let action = self.script.run(&mut scope).and_then(|value| action(&value));
+
+        match action {
+            Ok(action) => action,
+            Err(e) => {
+                if self.last_error.as_ref() != Some(&e) {
+                    println!("Bot {}: {}", self.path, e);
+                    self.last_error = Some(e);
+                }
+
+                0
+            }
+        }
+    }
+}
+
+fn action(value: &Dynamic) -> Result<Action, String> {
+    if let Ok(direction) = value.clone().into_string() {
+        return match direction.as_str() {
+            "up" | "left" => Ok(-1),
+            "down" | "right" => Ok(1),
+            "stay" => Ok(0),
+            _ => Err(format!("unknown move {}", direction)),
+        };
+    }
+
+    let number = value.as_float().or_else(|_| value.as_int().map(|n| n as f64));
+
+    match number {
+        Ok(n) if n < 0.0 => Ok(-1),
+        Ok(n) if n > 0.0 => Ok(1),
+        Ok(_) => Ok(0),
+        Err(_) if value.is_unit() => Ok(0),
+        Err(_) => Err("expected up, down or stay".to_string()),
+    }
+}
+
+fn vector(vector: &Vector) -> Dynamic {
+    map(vec![("x", number(vector.x)), ("y", number(vector.y))])
+}
+
+fn paddle_value(paddle: &Paddle, side: Side) -> Dynamic {
+    map(vec![
+        ("pos", vector(&paddle.pos)),
+        ("center", vector(&paddle.center())),
+        ("width", number(paddle.width as f64)),
+        ("height", number(paddle.height as f64)),
+        ("hits", count(paddle.hits as u64)),
+        ("max_hits", count(paddle.max_hits as u64)),
+        ("side", Dynamic::from(side.name().to_string())),
+    ])
+}
+
+fn ball_value(ball: &Ball) -> Dynamic {
+    map(vec![
+        ("id", count(ball.id as u64)),
+        ("pos", vector(&ball.pos)),
+        ("center", vector(&ball.center())),
+        ("velocity", vector(&ball.velocity)),
+        ("width", number(ball.width as f64)),
+        ("height", number(ball.height as f64)),
+    ])
+}
+
+// What a bot playing `side` gets to see of `game`, or nothing if there is
+// no paddle on that side.
+fn state(game: &Game, side: Side, depth: usize) -> Option<Scope<'static>> {
+    let paddle = game.paddle(side)?;
+    let center = paddle.center();
+    let size = game.size();
+
+    let nearest = game.balls.iter().min_by(|a, b| {
+        let a = a.center().subtract(&center);
+        let b = b.center().subtract(&center);
+
+        (a.x * a.x + a.y * a.y).partial_cmp(&(b.x * b.x + b.y * b.y)).unwrap()
+    });
+
+    let opponents: Vec<Dynamic> = game.sides().into_iter()
+        .filter(|other| *other != side)
+        .filter_map(|other| game.paddle(other).map(|paddle| paddle_value(paddle, other)))
+        .collect();
+
+    let mut scope = Scope::new();
+
+    scope.push_constant("paddle", paddle_value(paddle, side));
+    scope.push_constant("opponents", Dynamic::from_array(opponents));
+    scope.push_constant("ball", nearest.map(ball_value).unwrap_or(Dynamic::UNIT));
+    scope.push_constant("balls", Dynamic::from_array(game.balls.iter().map(ball_value).collect()));
+    scope.push_constant("field", map(vec![("width", number(size.x)), ("height", number(size.y))]));
+    scope.push_constant("level", count(game.color_index as u64));
+    scope.push_constant("depth", count(depth as u64));
+
+    Some(scope)
+}
@@ -0,0 +1,184 @@
+use sdl2::rect::Rect;
+
+use vector::Vector;
+
+use component::{Ball, FRect};
+
+// A scale followed by a translation, taking a point in game coordinates to
+// where it is drawn: `point * scale + offset`.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    pub scale: f64,
+    pub offset: Vector,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform { scale: 1.0, offset: Vector { x: 0.0, y: 0.0 } }
+    }
+
+    // Scales the area at `pos` of `size` up or down to fit inside the view
+    // at `view_pos` of `view_size`, keeping its aspect ratio and centering
+    // it along the other axis.
+    pub fn fit(pos: &Vector, size: &Vector, view_pos: &Vector, view_size: &Vector) -> Transform {
+        let scale = (view_size.x / size.x).min(view_size.y / size.y);
+
+        Transform {
+            scale: scale,
+            offset: Vector {
+                x: view_pos.x + (view_size.x - size.x * scale) / 2.0 - pos.x * scale,
+                y: view_pos.y + (view_size.y - size.y * scale) / 2.0 - pos.y * scale,
+            },
+        }
+    }
+
+    // Part way between this transform and `other`, `t` going from 0 to 1.
+    pub fn lerp(&self, other: &Transform, t: f64) -> Transform {
+        Transform {
+            scale: self.scale + (other.scale - self.scale) * t,
+            offset: Vector {
+                x: self.offset.x + (other.offset.x - self.offset.x) * t,
+                y: self.offset.y + (other.offset.y - self.offset.y) * t,
+            },
+        }
+    }
+
+    // This transform followed by `other`.
+    pub fn then(&self, other: &Transform) -> Transform {
+        Transform {
+            scale: self.scale * other.scale,
+            offset: Vector {
+                x: self.offset.x * other.scale + other.offset.x,
+                y: self.offset.y * other.scale + other.offset.y,
+            },
+        }
+    }
+
+    // Where `rect` ends up in whole pixels. This is the only place
+    // positions get rounded, and both edges are rounded rather than the
+    // size so neighbouring rects still line up after scaling.
+    pub fn apply(&self, rect: &FRect) -> Rect {
+        let left = (rect.x() * self.scale + self.offset.x).round();
+        let top = (rect.y() * self.scale + self.offset.y).round();
+        let right = (rect.right() * self.scale + self.offset.x).round();
+        let bottom = (rect.bottom() * self.scale + self.offset.y).round();
+
+        Rect::new(
+            left as i32,
+            top as i32,
+            (right - left).max(1.0) as u32,
+            (bottom - top).max(1.0) as u32
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraMode {
+    // Just the outer game.
+    Outer,
+    // Zoomed in on the inner game, following its ball around.
+    Zoom,
+    // The outer game with a larger copy of the inner game in the corner.
+    PictureInPicture,
+}
+
+// How long zooming in or out takes.
+const TRANSITION_MS: f64 = 400.0;
+
+// Width of the picture in picture view as a share of the screen.
+const PICTURE_IN_PICTURE_SIZE: f64 = 0.4;
+const PICTURE_IN_PICTURE_MARGIN: f64 = 10.0;
+
+// Decides how the nest is laid out on screen. The camera always looks at
+// the inner game hosted by the first ball in play.
+#[derive(Debug)]
+pub struct Camera {
+    pub mode: CameraMode,
+    view: Vector,
+    // How far along the transitions are, from 0 (off) to 1 (on).
+    zoom: f64,
+    picture_in_picture: f64,
+}
+
+impl Camera {
+    // `view` is the size of the screen.
+    pub fn new(view: Vector) -> Camera {
+        Camera {
+            mode: CameraMode::Outer,
+            view: view,
+            zoom: 0.0,
+            picture_in_picture: 0.0,
+        }
+    }
+
+    pub fn next_mode(&mut self) -> () {
+        self.mode = match self.mode {
+            CameraMode::Outer => CameraMode::Zoom,
+            CameraMode::Zoom => CameraMode::PictureInPicture,
+            CameraMode::PictureInPicture => CameraMode::Outer,
+        };
+    }
+
+    pub fn update(&mut self, delta_ms: u64) -> () {
+        let step = delta_ms as f64 / TRANSITION_MS;
+
+        self.zoom = approach(self.zoom, self.mode == CameraMode::Zoom, step);
+        self.picture_in_picture = approach(
+            self.picture_in_picture,
+            self.mode == CameraMode::PictureInPicture,
+            step
+        );
+    }
+
+    // Where the inner game hosted by `ball` goes, centered on the ball, in
+    // the coordinates of the game the ball is in.
+    pub fn inner_origin(&self, ball: &Ball, inner_size: &Vector) -> Vector {
+        Vector {
+            x: ball.pos.x + (ball.width as f64 - inner_size.x) / 2.0,
+            y: ball.pos.y + (ball.height as f64 - inner_size.y) / 2.0,
+        }
+    }
+
+    // The transform for the whole nest. `focus` is the position and size of
+    // the inner game being looked at, in outer game coordinates.
+    pub fn view(&self, focus: Option<(Vector, Vector)>) -> Transform {
+        match focus {
+            Some((pos, size)) if self.zoom > 0.0 => {
+                let zoomed = Transform::fit(&pos, &size, &Vector { x: 0.0, y: 0.0 }, &self.view);
+
+                Transform::identity().lerp(&zoomed, ease(self.zoom))
+            },
+            _ => Transform::identity(),
+        }
+    }
+
+    // The transform for the copy of the inner game in the top right corner
+    // of the screen, if it is showing. It grows out of the corner as it
+    // opens.
+    pub fn picture_in_picture(&self, inner_size: &Vector) -> Option<Transform> {
+        if self.picture_in_picture <= 0.0 { return None; }
+
+        let width = self.view.x * PICTURE_IN_PICTURE_SIZE * ease(self.picture_in_picture);
+        let height = width * inner_size.y / inner_size.x;
+
+        Some(Transform::fit(
+            &Vector { x: 0.0, y: 0.0 },
+            inner_size,
+            &Vector { x: self.view.x - PICTURE_IN_PICTURE_MARGIN - width, y: PICTURE_IN_PICTURE_MARGIN },
+            &Vector { x: width, y: height }
+        ))
+    }
+}
+
+fn approach(value: f64, on: bool, step: f64) -> f64 {
+    if on {
+        (value + step).min(1.0)
+    } else {
+        (value - step).max(0.0)
+    }
+}
+
+// Smoothstep, so the camera eases in and out instead of moving linearly.
+fn ease(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
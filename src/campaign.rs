@@ -0,0 +1,322 @@
+use std::fs;
+
+use toml;
+
+use ai::{Difficulty};
+
+use arena::{Arena};
+
+use component::{Side};
+
+use constants::{Constants};
+
+use coupling::{CouplingSettings};
+
+use modes::{Mode, Rules};
+
+use stats::{MatchStats};
+
+// A campaign file, a list of stages played in order, each one unlocked by
+// clearing the one before it.
+//
+//     name = "down the rabbit hole"
+//
+//     [[stages]]
+//     name = "warm up"
+//     ai = "easy"
+//     mode = "classic"
+//     first_to = 3
+//
+//     # One table per nesting level, the outer game first. Anything left out
+//     # is the same as in a normal match.
+//     [[stages.levels]]
+//     arena = "arenas/pillars.toml"
+//     paddle_height = 200
+//
+//     [[stages.levels]]
+//     rules = "accelerate"
+//
+// `ai` is easy, normal, hard or expert. `mode`, `first_to`, `lives` and
+// `time` work as on the command line, except that classic stages have to
+// set `first_to`. The stage is won by beating the computer or, when
+// `target` is set, by scoring at least that much.
+#[derive(Debug, Deserialize)]
+struct CampaignFile {
+    name: String,
+    stages: Vec<StageFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StageFile {
+    name: String,
+    levels: Vec<LevelFile>,
+    #[serde(default = "default_ai")]
+    ai: String,
+    #[serde(default = "default_mode")]
+    mode: String,
+    first_to: Option<u32>,
+    lives: Option<u32>,
+    time: Option<u64>,
+    target: Option<u32>,
+}
+
+fn default_ai() -> String { "normal".to_string() }
+
+fn default_mode() -> String { "classic".to_string() }
+
+#[derive(Debug, Deserialize)]
+struct LevelFile {
+    arena: Option<String>,
+    // Coupling with the game outside, see `CouplingSettings::preset`.
+    rules: Option<String>,
+    paddle_width: Option<i32>,
+    paddle_height: Option<i32>,
+    max_paddle_speed: Option<f64>,
+    ball_width: Option<i32>,
+    ball_height: Option<i32>,
+    // Times the usual top ball speed.
+    ball_speed: Option<f64>,
+    max_balls: Option<usize>,
+    ball_spawn_interval_ms: Option<u64>,
+    powerups: Option<bool>,
+}
+
+impl LevelFile {
+    fn constants(&self, base: &Constants) -> Result<Constants, String> {
+        let mut constants = base.clone();
+
+        if let Some(ref path) = self.arena {
+            constants.arena = Arena::load(path)?;
+        }
+
+        if let Some(ref rules) = self.rules {
+            constants.coupling = CouplingSettings::preset(rules)
+                .ok_or_else(|| format!("unknown rules {}", rules))?;
+        }
+
+        constants.paddle_width = self.paddle_width.unwrap_or(constants.paddle_width);
+        constants.paddle_height = self.paddle_height.unwrap_or(constants.paddle_height);
+        constants.max_paddle_speed = self.max_paddle_speed.unwrap_or(constants.max_paddle_speed);
+        constants.ball_width = self.ball_width.unwrap_or(constants.ball_width);
+        constants.ball_height = self.ball_height.unwrap_or(constants.ball_height);
+        constants.max_balls = self.max_balls.unwrap_or(constants.max_balls);
+        constants.ball_spawn_interval_ms = self.ball_spawn_interval_ms.unwrap_or(constants.ball_spawn_interval_ms);
+
+        if let Some(scale) = self.ball_speed {
+            constants.max_ball_speed.x = constants.max_ball_speed.x * scale;
+            constants.max_ball_speed.y = constants.max_ball_speed.y * scale;
+        }
+
+        if self.powerups == Some(false) {
+            constants.powerups.spawn_interval_ms = 0;
+        }
+
+        // A speed scale can push the serve speed out of range, so the
+        // finished level is what gets checked.
+        constants.check()?;
+
+        Ok(constants)
+    }
+}
+
+// A stage ready to be played.
+#[derive(Debug)]
+pub struct Stage {
+    pub name: String,
+    // The constants for each nesting level, outer game first.
+    pub levels: Vec<Constants>,
+    pub mode: Mode,
+    pub ai: Difficulty,
+    pub target: Option<u32>,
+}
+
+impl Stage {
+    // Whether the player, on the left, has done enough to clear the stage.
+    pub fn won(&self, rules: &Rules, stats: &MatchStats, sides: &[Side]) -> bool {
+        let scores = rules.scores(stats, sides);
+
+        match self.target {
+            Some(target) => scores[0] >= target,
+            None => scores[1..].iter().all(|score| scores[0] > *score),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Campaign {
+    pub name: String,
+    pub stages: Vec<Stage>,
+}
+
+impl Campaign {
+    // `outer` and `inner` are what the outer game and the nested ones are
+    // built from before each stage's changes.
+    pub fn load(path: &str, outer: &Constants, inner: &Constants) -> Result<Campaign, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let file: CampaignFile = toml::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+
+        if file.stages.is_empty() {
+            return Err(format!("{}: there are no stages", path));
+        }
+
+        let mut stages = vec![];
+
+        for stage in file.stages {
+            let stage = Campaign::stage(stage, outer, inner)
+                .map_err(|e| format!("{}: {}", path, e))?;
+
+            stages.push(stage);
+        }
+
+        Ok(Campaign { name: file.name, stages: stages })
+    }
+
+    fn stage(stage: StageFile, outer: &Constants, inner: &Constants) -> Result<Stage, String> {
+        if stage.levels.is_empty() {
+            return Err(format!("stage {} has no levels", stage.name));
+        }
+
+        let mut levels = vec![];
+
+        for (depth, level) in stage.levels.iter().enumerate() {
+            let base = if depth == 0 { outer } else { inner };
+
+            levels.push(level.constants(base).map_err(|e| format!("stage {}: {}", stage.name, e))?);
+        }
+
+        let mode = Mode::parse(&stage.mode, stage.first_to, stage.lives, stage.time.map(|seconds| seconds * 1000))
+            .ok_or_else(|| format!("stage {}: unknown mode {}", stage.name, stage.mode))?;
+
+        if let Mode::Practice { .. } = mode {
+            return Err(format!("stage {}: practice can't be a stage", stage.name));
+        }
+
+        // Otherwise the match would never end.
+        if let Mode::Classic { first_to: None } = mode {
+            return Err(format!("stage {}: classic stages need first_to", stage.name));
+        }
+
+        if !mode.competitive() && stage.target.is_none() {
+            return Err(format!("stage {}: {} needs a target", stage.name, stage.mode));
+        }
+
+        let ai = Difficulty::preset(&stage.ai).ok_or_else(|| {
+            format!("stage {}: unknown ai {}, try one of: {}", stage.name, stage.ai, Difficulty::names().join(", "))
+        })?;
+
+        Ok(Stage {
+            name: stage.name,
+            levels: levels,
+            mode: mode,
+            ai: ai,
+            target: stage.target,
+        })
+    }
+
+    // Lines for the stage select, `cleared` being how many stages the
+    // player has cleared and `selected` the one picked.
+    pub fn lines(&self, cleared: usize, selected: usize) -> Vec<String> {
+        let mut lines = vec![];
+
+        for (i, stage) in self.stages.iter().enumerate() {
+            let progress = if i < cleared {
+                "cleared"
+            } else if i == cleared {
+                ""
+            } else {
+                "locked"
+            };
+
+            lines.push(format!(
+                "{} {}. {:<24} {:<13} {}",
+                if i == selected { ">" } else { " " },
+                i + 1,
+                stage.name,
+                format!("{} deep, {}", stage.levels.len(), stage.mode.name()),
+                progress
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push("up and down to pick, enter to play".to_string());
+
+        return lines;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use constants::{OUTER_CONSTANTS, INNER_CONSTANTS};
+
+    fn load(name: &str, contents: &str) -> Result<Campaign, String> {
+        let path = env::temp_dir().join(format!("popongong-{}-{}.toml", std::process::id(), name));
+
+        fs::write(&path, contents).unwrap();
+
+        let campaign = Campaign::load(path.to_str().unwrap(), &OUTER_CONSTANTS, &INNER_CONSTANTS);
+
+        fs::remove_file(&path).unwrap();
+
+        return campaign;
+    }
+
+    // A one stage campaign with `stage` added to the stage and `level` to
+    // its only level.
+    fn stage(name: &str, stage: &str, level: &str) -> Result<Campaign, String> {
+        load(name, &format!(
+            "name = \"test\"\n[[stages]]\nname = \"one\"\n{}\n[[stages.levels]]\n{}\n",
+            stage,
+            level,
+        ))
+    }
+
+    #[test]
+    fn loads_the_shipped_campaign() {
+        let campaign = Campaign::load("campaigns/nested.toml", &OUTER_CONSTANTS, &INNER_CONSTANTS).unwrap();
+
+        assert!(!campaign.stages.is_empty());
+    }
+
+    #[test]
+    fn scales_the_ball_speed() {
+        let campaign = stage("scaled", "first_to = 3", "ball_speed = 0.5").unwrap();
+        let speed = &campaign.stages[0].levels[0].max_ball_speed;
+
+        assert_eq!(speed.x, OUTER_CONSTANTS.max_ball_speed.x * 0.5);
+        assert_eq!(speed.y, OUTER_CONSTANTS.max_ball_speed.y * 0.5);
+    }
+
+    #[test]
+    fn rejects_stages_that_cant_end() {
+        assert!(stage("no-first-to", "", "").is_err());
+        assert!(stage("coop", "mode = \"coop\"", "").is_err());
+        assert!(stage("coop-target", "mode = \"coop\"\ntarget = 10", "").is_ok());
+        assert!(stage("practice", "mode = \"practice\"\ntarget = 10", "").is_err());
+        assert!(stage("unknown-mode", "mode = \"golf\"", "").is_err());
+        assert!(stage("unknown-ai", "first_to = 3\nai = \"godlike\"", "").is_err());
+        assert!(load("no-stages", "name = \"test\"\nstages = []\n").is_err());
+    }
+
+    #[test]
+    fn rejects_levels_that_break_the_game() {
+        let level = |name: &str, level: &str| stage(name, "first_to = 3", level);
+
+        assert!(level("narrow", "paddle_width = 10").is_err());
+        assert!(level("narrowest", "paddle_width = 11").is_ok());
+        assert!(level("flat", "paddle_height = 0").is_err());
+        assert!(level("no-ball", "ball_width = 0").is_err());
+        assert!(level("still", "max_paddle_speed = 0.0").is_err());
+        assert!(level("no-balls", "max_balls = 0").is_err());
+        assert!(level("stopped", "ball_speed = 0.0").is_err());
+        // Scaled down far enough, the top vertical speed leaves nothing to
+        // serve between.
+        assert!(level("slow", "ball_speed = 0.25").is_err());
+        assert!(level("slow-enough", "ball_speed = 0.3").is_ok());
+        assert!(level("unknown-rules", "rules = \"tangled\"").is_err());
+    }
+}
@@ -3,34 +3,140 @@ use sdl2::rect::Rect;
 
 use vector::Vector;
 
-use constants::{Color};
+use constants::{Color, MeterSettings};
+
+// What a component is part of. When a sprite set is loaded the renderer uses
+// this to pick a sprite, `Plain` components are always drawn as solid color.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Part {
+    Plain,
+    Field,
+    Ball,
+    PaddleBorder,
+    PaddleSegment,
+}
+
+pub type Component<'a> = (&'a FRect, &'a Color, Part);
+
+// A rectangle in float coordinates. Components are positioned with these so
+// nothing gets rounded to whole pixels until it is drawn, otherwise nested
+// games drift against the ball they are drawn on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl FRect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> FRect {
+        FRect { x: x, y: y, width: width, height: height }
+    }
+
+    pub fn x(&self) -> f64 { self.x }
+
+    pub fn y(&self) -> f64 { self.y }
+
+    pub fn width(&self) -> f64 { self.width }
+
+    pub fn height(&self) -> f64 { self.height }
+
+    pub fn right(&self) -> f64 { self.x + self.width }
+
+    pub fn bottom(&self) -> f64 { self.y + self.height }
+
+    pub fn set_x(&mut self, x: f64) -> () { self.x = x; }
+
+    pub fn set_y(&mut self, y: f64) -> () { self.y = y; }
+
+    pub fn set_width(&mut self, width: f64) -> () { self.width = width; }
+
+    pub fn set_height(&mut self, height: f64) -> () { self.height = height; }
+}
+
+impl<'a> From<&'a Rect> for FRect {
+    fn from(rect: &'a Rect) -> FRect {
+        FRect::new(rect.x() as f64, rect.y() as f64, rect.width() as f64, rect.height() as f64)
+    }
+}
+
+// Which paddle something belongs to. `One` is the left paddle and `Two` the
+// right, `Three` (top) and `Four` (bottom) only exist in four player mode.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize)]
+pub enum Side {
+    One,
+    Two,
+    Three,
+    Four,
+}
+
+impl Side {
+    pub fn opponent(&self) -> Side {
+        match self {
+            Side::One => Side::Two,
+            Side::Two => Side::One,
+            Side::Three => Side::Four,
+            Side::Four => Side::Three,
+        }
+    }
+
+    // In the order players are listed on the command line.
+    pub fn all() -> [Side; 4] {
+        [Side::One, Side::Two, Side::Three, Side::Four]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Side::One => "left",
+            Side::Two => "right",
+            Side::Three => "top",
+            Side::Four => "bottom",
+        }
+    }
 
-pub type Component<'a> = (&'a sdl2::rect::Rect, &'a Color);
+    pub fn from_name(name: &str) -> Option<Side> {
+        Side::all().iter().find(|side| side.name() == name).cloned()
+    }
+
+    pub fn is_horizontal(&self) -> bool {
+        *self == Side::Three || *self == Side::Four
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Ball {
+    pub id: u32,
     pub pos: Vector,
     pub width: u32,
     pub height: u32,
     pub velocity: Vector,
-    pub rect: Rect,
+    pub rect: FRect,
     pub color: Color,
+    // The paddle that last returned this ball.
+    pub last_hit: Option<Side>,
+    // Set while the ball is still inside the portal it came out of, so it
+    // doesn't bounce straight back through.
+    pub teleported: bool,
 }
 
 impl Ball {
-    pub fn new(pos: Vector, width: u32, height: u32, velocity: Vector, color: Color) -> Ball {
+    pub fn new(id: u32, pos: Vector, width: u32, height: u32, velocity: Vector, color: Color) -> Ball {
         return Ball {
+            id: id,
             pos: pos.clone(),
             width: width,
             height: height,
-            rect: Rect::new(
-                pos.x as i32,
-                pos.y as i32,
-                width,
-                height
+            rect: FRect::new(
+                pos.x,
+                pos.y,
+                width as f64,
+                height as f64
             ),
             color: color,
             velocity: velocity,
+            last_hit: None,
+            teleported: false,
         }
     }
 
@@ -42,12 +148,17 @@ impl Ball {
         Vector { x: self.pos.x + self.width as f64 / 2.0, y: self.pos.y + self.height as f64 / 2.0 }
     }
 
-    pub fn components(&mut self, origin: &Vector) -> Vec<Component> {
-        self.rect.set_x(self.pos.x as i32 + origin.x as i32);
-        self.rect.set_y(self.pos.y as i32 + origin.y as i32);
+    // In pixels per ms.
+    pub fn speed(&self) -> f64 {
+        (self.velocity.x * self.velocity.x + self.velocity.y * self.velocity.y).sqrt()
+    }
+
+    pub fn components(&mut self, origin: &Vector) -> Vec<Component<'_>> {
+        self.rect.set_x(self.pos.x + origin.x);
+        self.rect.set_y(self.pos.y + origin.y);
 
         return vec![
-            (&self.rect, &self.color),
+            (&self.rect, &self.color, Part::Ball),
         ];
     }
 
@@ -77,11 +188,95 @@ impl Ball {
         self.velocity.y = new_y
     }
 
+    pub fn set_velocity_x_magnitude(&mut self, new_x_mag: f64) -> () {
+        let mut new_x = new_x_mag;
+
+        if self.velocity.x.is_sign_negative() { new_x = new_x_mag * -1.0; }
+
+        self.velocity.x = new_x
+    }
+
     // TODO: This should be done with generics that impl the correct traits
     pub fn distance_to(&self, paddle: &Paddle) -> Vector {
         self.center().subtract(&paddle.center())
     }
 
+    pub fn overlaps(&self, other: &Ball) -> bool {
+        self.left_edge() < other.right_edge() &&
+            self.right_edge() > other.left_edge() &&
+            self.top_edge() < other.bottom_edge() &&
+            self.bottom_edge() > other.top_edge()
+    }
+
+    // Elastic collision between two balls of equal mass. Velocities are
+    // exchanged along whichever axis has the smallest overlap, which is the
+    // side the balls met on. Balls already moving apart are left alone so
+    // they don't get stuck inside each other.
+    pub fn bounce_off(&mut self, other: &mut Ball) -> bool {
+        let overlap_x = self.right_edge().min(other.right_edge()) -
+            self.left_edge().max(other.left_edge());
+        let overlap_y = self.bottom_edge().min(other.bottom_edge()) -
+            self.top_edge().max(other.top_edge());
+
+        let distance = other.center().subtract(&self.center());
+        let relative_velocity = other.velocity.subtract(&self.velocity);
+
+        if overlap_x < overlap_y {
+            if distance.x * relative_velocity.x >= 0.0 { return false; }
+
+            std::mem::swap(&mut self.velocity.x, &mut other.velocity.x);
+        } else {
+            if distance.y * relative_velocity.y >= 0.0 { return false; }
+
+            std::mem::swap(&mut self.velocity.y, &mut other.velocity.y);
+        }
+
+        return true;
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(self.x(), self.y(), self.width, self.height)
+    }
+
+    // Bounce off a solid rectangle, pushing the ball back out of it. Only the
+    // axis with the smallest overlap is reflected, and only when the ball is
+    // moving into the rectangle.
+    pub fn reflect_off(&mut self, rect: &Rect) -> bool {
+        let overlap_x = self.right_edge().min(rect.right()) - self.left_edge().max(rect.left());
+        let overlap_y = self.bottom_edge().min(rect.bottom()) - self.top_edge().max(rect.top());
+
+        if overlap_x <= 0 || overlap_y <= 0 { return false; }
+
+        let distance = Vector {
+            x: rect.center().x() as f64,
+            y: rect.center().y() as f64
+        }.subtract(&self.center());
+
+        if overlap_x < overlap_y {
+            if distance.x > 0.0 {
+                self.pos.x = self.pos.x - overlap_x as f64;
+                if self.is_moving_right() { self.flip_x(); }
+            } else {
+                self.pos.x = self.pos.x + overlap_x as f64;
+                if self.is_moving_left() { self.flip_x(); }
+            }
+        } else if distance.y > 0.0 {
+            self.pos.y = self.pos.y - overlap_y as f64;
+            if self.is_moving_down() { self.flip_y(); }
+        } else {
+            self.pos.y = self.pos.y + overlap_y as f64;
+            if self.is_moving_up() { self.flip_y(); }
+        }
+
+        return true;
+    }
+
+    // Move the ball so it is centered on `rect`.
+    pub fn center_on(&mut self, rect: &Rect) -> () {
+        self.pos.x = rect.center().x() as f64 - self.width as f64 / 2.0;
+        self.pos.y = rect.center().y() as f64 - self.height as f64 / 2.0;
+    }
+
     pub fn is_moving_up(&self) -> bool {
         self.velocity.y.is_sign_negative()
     }
@@ -123,45 +318,114 @@ impl Ball {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Paddle {
     pub pos: Vector,
     pub width: u32,
     pub height: u32,
     pub velocity: Vector,
-    pub background: Rect,
-    pub border: Rect,
+    pub background: FRect,
+    pub border: FRect,
     pub border_color: Color,
     pub background_color: Color,
-    pub segments: [(sdl2::rect::Rect, Color); 6],
+    pub segments: Vec<(FRect, Color)>,
     pub hits: u8,
+    pub max_hits: u8,
+    pub miss_decay: u8,
+    // Horizontal paddles run along the top and bottom of the field, move
+    // left and right, and fill their meter from left to right.
+    pub horizontal: bool,
 }
 
 impl Paddle {
-    pub fn new(pos: Vector, width: u32, height: u32, speed: f64, color: Color) -> Paddle {
-        let segment_height = (height - 10) / 5;
-
+    pub fn new(pos: Vector, width: u32, height: u32, speed: f64, color: Color, meter: &MeterSettings) -> Paddle {
         return Paddle {
             pos: pos.clone(),
             width: width,
             height: height,
-            background: Rect::new(pos.x as i32, pos.y as i32, width - 10, height - 10),
-            border: Rect::new(pos.x as i32, pos.y as i32, width, height),
+            background: FRect::new(pos.x, pos.y, (width - 10) as f64, (height - 10) as f64),
+            border: FRect::new(pos.x, pos.y, width as f64, height as f64),
             velocity: Vector { x: 0.0, y: speed },
             border_color: color.clone(),
-            background_color: Color::Black,
-            segments: [
-                (Rect::new(0, 0, width - 10, segment_height), color.clone()),
-                (Rect::new(0, 0, width - 10, segment_height), color.clone()),
-                (Rect::new(0, 0, width - 10, segment_height), color.clone()),
-                (Rect::new(0, 0, width - 10, segment_height), color.clone()),
-                (Rect::new(0, 0, width - 10, segment_height), color.clone()),
-                (Rect::new(0, 0, width - 10, segment_height), color.clone()),
-            ],
+            background_color: Color::Background,
+            segments: Paddle::build_segments(width, height, false, meter.segments, &color),
             hits: 0,
+            max_hits: meter.max_hits,
+            miss_decay: meter.miss_decay,
+            horizontal: false,
         };
     }
 
+    // `width` is the length of the paddle along the wall and `height` its
+    // thickness.
+    pub fn new_horizontal(pos: Vector, width: u32, height: u32, speed: f64, color: Color, meter: &MeterSettings) -> Paddle {
+        let mut paddle = Paddle::new(pos, width, height, speed, color, meter);
+
+        paddle.velocity = Vector { x: speed, y: 0.0 };
+        paddle.horizontal = true;
+        paddle.segments = Paddle::build_segments(width, height, true, meter.segments, &paddle.border_color);
+
+        return paddle;
+    }
+
+    // The meter is split into `count` equal segments along the paddle, one
+    // filled in per hit.
+    fn build_segments(width: u32, height: u32, horizontal: bool, count: u8, color: &Color) -> Vec<(FRect, Color)> {
+        let count = count.max(1) as u32;
+        let (segment_width, segment_height) = if horizontal {
+            (((width - 10) / count).max(1), height - 10)
+        } else {
+            (width - 10, ((height - 10) / count).max(1))
+        };
+
+        (0..count).map(|_| {
+            (FRect::new(0.0, 0.0, segment_width as f64, segment_height as f64), color.clone())
+        }).collect()
+    }
+
+    pub fn length(&self) -> u32 {
+        if self.horizontal { self.width } else { self.height }
+    }
+
+    // Resize the paddle along its length around its center, rebuilding the
+    // meter segments to fit.
+    pub fn set_length(&mut self, length: u32) -> () {
+        let center = self.center();
+
+        if self.horizontal {
+            self.width = length;
+            self.pos.x = center.x - length as f64 / 2.0;
+            self.border.set_width(length as f64);
+            self.background.set_width((length - 10) as f64);
+        } else {
+            self.height = length;
+            self.pos.y = center.y - length as f64 / 2.0;
+            self.border.set_height(length as f64);
+            self.background.set_height((length - 10) as f64);
+        }
+
+        let count = self.segments.len() as u8;
+
+        self.segments = Paddle::build_segments(self.width, self.height, self.horizontal, count, &self.border_color);
+    }
+
+    // Change how thick the paddle is. Moving it back against its wall is up
+    // to the caller.
+    pub fn set_thickness(&mut self, thickness: u32) -> () {
+        if self.horizontal {
+            self.height = thickness;
+            self.border.set_height(thickness as f64);
+            self.background.set_height((thickness - 10) as f64);
+        } else {
+            self.width = thickness;
+            self.border.set_width(thickness as f64);
+            self.background.set_width((thickness - 10) as f64);
+        }
+
+        let length = self.length();
+        self.set_length(length);
+    }
+
     pub fn x(&self) -> i32 {
         self.pos.x as i32
     }
@@ -174,16 +438,19 @@ impl Paddle {
         Vector { x: self.pos.x + self.width as f64 / 2.0, y: self.pos.y + self.height as f64 / 2.0 }
     }
 
+    // Where the paddle is on the field, in game coordinates.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(self.x(), self.y(), self.width, self.height)
+    }
+
     pub fn hit(&mut self) -> () {
-        if self.hits < 5 {
+        if self.hits < self.max_hits {
             self.hits = self.hits + 1;
         }
     }
 
     pub fn miss(&mut self) -> () {
-        if self.hits > 0 {
-            self.hits = self.hits - 1;
-        }
+        self.hits = self.hits.saturating_sub(self.miss_decay);
     }
 
     pub fn reset_hits(&mut self) -> () {
@@ -198,28 +465,40 @@ impl Paddle {
         }
     }
 
-    pub fn components(&mut self, origin: &Vector) -> Vec<Component> {
-        let segment_height = (self.height - 10) / 5;
+    pub fn components(&mut self, origin: &Vector) -> Vec<Component<'_>> {
+        // The meter holds `max_hits`, however many segments it is split into.
+        let filled = if self.max_hits == 0 {
+            0
+        } else {
+            (self.hits as usize * self.segments.len() / self.max_hits as usize).min(self.segments.len())
+        };
 
-        self.background.set_x((self.pos.x + origin.x + 5.0) as i32);
-        self.background.set_y((self.pos.y + origin.y + 5.0) as i32);
+        self.background.set_x(self.pos.x + origin.x + 5.0);
+        self.background.set_y(self.pos.y + origin.y + 5.0);
 
-        self.border.set_x((self.pos.x + origin.x) as i32);
-        self.border.set_y((self.pos.y + origin.y) as i32);
+        self.border.set_x(self.pos.x + origin.x);
+        self.border.set_y(self.pos.y + origin.y);
 
         let mut components = vec![
-            (&self.border, &self.border_color),
-            (&self.background, &self.background_color),
+            (&self.border, &self.border_color, Part::PaddleBorder),
+            (&self.background, &self.background_color, Part::Plain),
         ];
 
-        for i in 0..self.hits {
-            self.segments[i as usize].0.set_x((self.pos.x + origin.x + 5.0) as i32);
-            self.segments[i as usize].0.set_y((self.pos.y + (self.height - segment_height * (i as u32 + 1)) as f64 + origin.y - 5.0) as i32);
-
+        for i in 0..filled {
+            let segment_width = self.segments[i].0.width();
+            let segment_height = self.segments[i].0.height();
+
+            if self.horizontal {
+                self.segments[i].0.set_x(self.pos.x + segment_width * i as f64 + origin.x + 5.0);
+                self.segments[i].0.set_y(self.pos.y + origin.y + 5.0);
+            } else {
+                self.segments[i].0.set_x(self.pos.x + origin.x + 5.0);
+                self.segments[i].0.set_y(self.pos.y + self.height as f64 - segment_height * (i + 1) as f64 + origin.y - 5.0);
+            }
         }
 
-        for i in 0..self.hits {
-            components.push((&self.segments[i as usize].0, &self.segments[i as usize].1));
+        for i in 0..filled {
+            components.push((&self.segments[i].0, &self.segments[i].1, Part::PaddleSegment));
         }
 
         return components;
@@ -234,7 +513,7 @@ impl Paddle {
             // If distance to the edge is less than the step size the paddle
             // will never hit the edge. In this case just ignore speed and set
             // it manually.
-            self.pos.y = 0.0;
+            self.pos.y = limit;
         }
     }
 
@@ -248,9 +527,29 @@ impl Paddle {
         }
     }
 
-    //pub fn left_edge(&self) -> i32 {
-    //    self.x()
-    //}
+    pub fn left(&mut self, delta_ms: u64, limit: f64) -> () {
+        let step_size = delta_ms as f64 * self.velocity.x;
+
+        if (self.pos.x - step_size) >= limit {
+            self.pos.x = self.pos.x - step_size;
+        } else {
+            self.pos.x = limit;
+        }
+    }
+
+    pub fn right(&mut self, delta_ms: u64, limit: f64) -> () {
+        let step_size = delta_ms as f64 * self.velocity.x;
+
+        if (self.pos.x + self.width as f64 + step_size) <= limit {
+            self.pos.x = self.pos.x + step_size;
+        } else {
+            self.pos.x = limit - self.width as f64;
+        }
+    }
+
+    pub fn left_edge(&self) -> i32 {
+        self.x()
+    }
 
     pub fn right_edge(&self) -> i32 {
         self.x() + self.width as i32
@@ -264,3 +563,42 @@ impl Paddle {
         self.y() + self.height as i32
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use constants::{MeterSettings};
+
+    fn filled(paddle: &mut Paddle) -> usize {
+        let origin = Vector { x: 0.0, y: 0.0 };
+
+        paddle.components(&origin).iter().filter(|(_, _, part)| *part == Part::PaddleSegment).count()
+    }
+
+    #[test]
+    fn meter_fills_by_share_of_max_hits() {
+        let meter = MeterSettings {
+            segments: 5,
+            max_hits: 10,
+            miss_decay: 1,
+            level_up_hits: None,
+            level_up_together: true,
+        };
+        let mut paddle = Paddle::new(Vector { x: 0.0, y: 0.0 }, 35, 150, 0.15, Color::Level(0), &meter);
+
+        assert_eq!(filled(&mut paddle), 0);
+
+        paddle.hits = 5;
+        assert_eq!(filled(&mut paddle), 2);
+
+        paddle.hits = 9;
+        assert_eq!(filled(&mut paddle), 4);
+
+        paddle.hits = 10;
+        assert_eq!(filled(&mut paddle), 5);
+
+        paddle.max_hits = 0;
+        assert_eq!(filled(&mut paddle), 0);
+    }
+}
@@ -0,0 +1,338 @@
+use sdl2::keyboard::Keycode;
+
+use std::fs;
+
+use toml;
+
+use constants::{Constants};
+
+use game::{PADDLE_KEYS};
+
+use powerup::{PowerUpSettings};
+
+use vector::{Vector};
+
+// Changes to the constants profiles, loaded with `--config` and applied
+// again whenever the file is saved.
+//
+//     [outer]
+//     paddle_height = 200
+//     max_ball_speed = { x = 0.2, y = 0.8 }
+//
+//     [inner]
+//     ball_collisions = false
+//
+//     [inner.powerups]
+//     reverse_controls = false
+//     spawn_interval_ms = 0
+//
+// The inner table is used for every game nested inside the outer one.
+// Anything left out keeps its value, so taking a line out of the file
+// doesn't undo it until the game is restarted.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub outer: LevelConfig,
+    #[serde(default)]
+    pub inner: LevelConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LevelConfig {
+    paddle_width: Option<i32>,
+    paddle_height: Option<i32>,
+    max_paddle_speed: Option<f64>,
+    ball_width: Option<i32>,
+    ball_height: Option<i32>,
+    max_ball_speed: Option<Vector>,
+    max_balls: Option<usize>,
+    ball_spawn_interval_ms: Option<u64>,
+    spawn_ball_on_level_up: Option<bool>,
+    ball_collisions: Option<bool>,
+    #[serde(default)]
+    powerups: PowerUpConfig,
+}
+
+// Each kind of power up on or off, and how often they come. A
+// `spawn_interval_ms` of 0 turns them all off.
+#[derive(Debug, Default, Deserialize)]
+struct PowerUpConfig {
+    grow: Option<bool>,
+    shrink: Option<bool>,
+    speed_up: Option<bool>,
+    slow_down: Option<bool>,
+    extra_ball: Option<bool>,
+    reverse_controls: Option<bool>,
+    shield: Option<bool>,
+    spawn_interval_ms: Option<u64>,
+    duration_ms: Option<u64>,
+    max_on_field: Option<usize>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let config: Config = toml::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+
+        Ok(config)
+    }
+
+    // `levels` is ordered from the outermost game inwards. Nothing changes
+    // unless every level still passes `Constants::check` afterwards.
+    pub fn apply(&self, levels: &mut [Constants]) -> Result<(), String> {
+        let mut changed = levels.to_vec();
+
+        for (depth, constants) in changed.iter_mut().enumerate() {
+            let (name, level) = if depth == 0 { ("outer", &self.outer) } else { ("inner", &self.inner) };
+
+            level.apply(constants);
+            constants.check().map_err(|e| format!("{} {}", name, e))?;
+        }
+
+        levels.clone_from_slice(&changed);
+
+        Ok(())
+    }
+}
+
+impl LevelConfig {
+    fn apply(&self, constants: &mut Constants) -> () {
+        constants.paddle_width = self.paddle_width.unwrap_or(constants.paddle_width);
+        constants.paddle_height = self.paddle_height.unwrap_or(constants.paddle_height);
+        constants.max_paddle_speed = self.max_paddle_speed.unwrap_or(constants.max_paddle_speed);
+        constants.ball_width = self.ball_width.unwrap_or(constants.ball_width);
+        constants.ball_height = self.ball_height.unwrap_or(constants.ball_height);
+        constants.max_balls = self.max_balls.unwrap_or(constants.max_balls);
+        constants.ball_spawn_interval_ms = self.ball_spawn_interval_ms.unwrap_or(constants.ball_spawn_interval_ms);
+        constants.spawn_ball_on_level_up = self.spawn_ball_on_level_up.unwrap_or(constants.spawn_ball_on_level_up);
+        constants.ball_collisions = self.ball_collisions.unwrap_or(constants.ball_collisions);
+
+        if let Some(ref speed) = self.max_ball_speed {
+            constants.max_ball_speed = speed.clone();
+        }
+
+        self.powerups.apply(&mut constants.powerups);
+    }
+}
+
+impl PowerUpConfig {
+    fn apply(&self, settings: &mut PowerUpSettings) -> () {
+        settings.grow = self.grow.unwrap_or(settings.grow);
+        settings.shrink = self.shrink.unwrap_or(settings.shrink);
+        settings.speed_up = self.speed_up.unwrap_or(settings.speed_up);
+        settings.slow_down = self.slow_down.unwrap_or(settings.slow_down);
+        settings.extra_ball = self.extra_ball.unwrap_or(settings.extra_ball);
+        settings.reverse_controls = self.reverse_controls.unwrap_or(settings.reverse_controls);
+        settings.shield = self.shield.unwrap_or(settings.shield);
+        settings.spawn_interval_ms = self.spawn_interval_ms.unwrap_or(settings.spawn_interval_ms);
+        settings.duration_ms = self.duration_ms.unwrap_or(settings.duration_ms);
+        settings.max_on_field = self.max_on_field.unwrap_or(settings.max_on_field);
+    }
+}
+
+// Keys the game handles before paddle input, so binding a paddle to one
+// would do nothing. The developer keys are in here too, with or without
+// `--dev`. Up and Down also pick campaign stages, but only on that screen.
+const HOTKEYS: [Keycode; 17] = [
+    Keycode::Escape,
+    Keycode::Tab,
+    Keycode::Return,
+    Keycode::Space,
+    Keycode::L,
+    Keycode::T,
+    Keycode::C,
+    Keycode::F,
+    Keycode::F11,
+    Keycode::Minus,
+    Keycode::Equals,
+    Keycode::Backquote,
+    Keycode::LeftBracket,
+    Keycode::RightBracket,
+    Keycode::H,
+    Keycode::N,
+    Keycode::B,
+];
+
+// Which keys move each side's paddle, loaded with `--keys`. Key names are
+// the ones SDL uses, like "W", "Up" or "Keypad 8".
+//
+//     left = ["W", "S"]
+//     right = ["Keypad 8", "Keypad 2"]
+//
+// Sides left out keep their usual keys.
+#[derive(Debug, Deserialize)]
+struct BindingsFile {
+    left: Option<[String; 2]>,
+    right: Option<[String; 2]>,
+    top: Option<[String; 2]>,
+    bottom: Option<[String; 2]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    // Up (or left) and down (or right) for each side, in side order.
+    keys: [(Keycode, Keycode); 4],
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings { keys: PADDLE_KEYS }
+    }
+}
+
+impl Bindings {
+    pub fn load(path: &str) -> Result<Bindings, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let file: BindingsFile = toml::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut bindings = Bindings::default();
+        let sides = [&file.left, &file.right, &file.top, &file.bottom];
+
+        for (keys, names) in bindings.keys.iter_mut().zip(sides.iter()) {
+            if let Some([ref up, ref down]) = **names {
+                let up = key(up).map_err(|e| format!("{}: {}", path, e))?;
+                let down = key(down).map_err(|e| format!("{}: {}", path, e))?;
+
+                *keys = (up, down);
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    // The usual key for the same paddle and direction as `keycode`, which is
+    // what the games listen for.
+    pub fn translate(&self, keycode: Keycode) -> Option<&'static Keycode> {
+        for (i, (up, down)) in self.keys.iter().enumerate() {
+            if *up == keycode { return Some(&PADDLE_KEYS[i].0); }
+            if *down == keycode { return Some(&PADDLE_KEYS[i].1); }
+        }
+
+        None
+    }
+}
+
+fn key(name: &str) -> Result<Keycode, String> {
+    let key = Keycode::from_name(name).ok_or_else(|| format!("unknown key {}", name))?;
+
+    if HOTKEYS.contains(&key) {
+        return Err(format!("{} is already used by the game", name));
+    }
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use constants::{OUTER_CONSTANTS, INNER_CONSTANTS};
+
+    fn write(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("popongong-{}-{}.toml", std::process::id(), name));
+
+        fs::write(&path, contents).unwrap();
+
+        return path.to_str().unwrap().to_string();
+    }
+
+    fn apply(name: &str, contents: &str, levels: &mut [Constants]) -> Result<(), String> {
+        let path = write(name, contents);
+        let config = Config::load(&path);
+
+        fs::remove_file(&path).unwrap();
+
+        config?.apply(levels)
+    }
+
+    fn bindings(name: &str, contents: &str) -> Result<Bindings, String> {
+        let path = write(name, contents);
+        let bindings = Bindings::load(&path);
+
+        fs::remove_file(&path).unwrap();
+
+        return bindings;
+    }
+
+    #[test]
+    fn applies_the_shipped_config() {
+        let mut levels = vec![OUTER_CONSTANTS, INNER_CONSTANTS, INNER_CONSTANTS];
+        let config = Config::load("config/tuning.toml").unwrap();
+
+        config.apply(&mut levels).unwrap();
+
+        assert_eq!(levels[0].paddle_height, 180);
+        assert!(!levels[1].powerups.reverse_controls);
+        assert!(!levels[2].powerups.reverse_controls);
+        assert!(levels[0].powerups.reverse_controls);
+    }
+
+    #[test]
+    fn leaves_out_what_isnt_set() {
+        let mut levels = vec![OUTER_CONSTANTS, INNER_CONSTANTS];
+
+        apply("partial", "[inner]\nmax_balls = 3\n", &mut levels).unwrap();
+
+        assert_eq!(levels[0].max_balls, OUTER_CONSTANTS.max_balls);
+        assert_eq!(levels[1].max_balls, 3);
+        assert_eq!(levels[1].paddle_height, INNER_CONSTANTS.paddle_height);
+    }
+
+    #[test]
+    fn rejects_configs_that_break_the_game() {
+        let broken = [
+            "[outer]\npaddle_width = 10",
+            "[outer]\npaddle_height = 0",
+            "[inner]\nmax_balls = 0",
+            "[inner]\nmax_paddle_speed = 0.0",
+            // Serves go between 0.1 and half the top vertical speed.
+            "[outer]\nmax_ball_speed = { x = 0.2, y = 0.2 }",
+            "[outer]\nmax_ball_speed = { x = 0.0, y = 0.8 }",
+            "[outer",
+        ];
+
+        for (i, contents) in broken.iter().enumerate() {
+            let mut levels = vec![OUTER_CONSTANTS, INNER_CONSTANTS];
+
+            assert!(apply(&format!("broken-{}", i), contents, &mut levels).is_err(), "{}", contents);
+            // Nothing is applied when any level is broken.
+            assert_eq!(levels[0].paddle_width, OUTER_CONSTANTS.paddle_width);
+        }
+
+        let mut levels = vec![OUTER_CONSTANTS, INNER_CONSTANTS];
+        let contents = "[outer]\npaddle_width = 11\nmax_ball_speed = { x = 0.2, y = 0.21 }";
+
+        assert!(apply("fine", contents, &mut levels).is_ok());
+        assert_eq!(levels[0].paddle_width, 11);
+    }
+
+    #[test]
+    fn translates_bound_keys() {
+        let bindings = bindings("keys", "left = [\"A\", \"Z\"]\n").unwrap();
+
+        assert_eq!(bindings.translate(Keycode::A), Some(&Keycode::W));
+        assert_eq!(bindings.translate(Keycode::Z), Some(&Keycode::S));
+        // The old keys for that side do nothing and the other sides keep
+        // theirs.
+        assert_eq!(bindings.translate(Keycode::W), None);
+        assert_eq!(bindings.translate(Keycode::Up), Some(&Keycode::Up));
+        assert_eq!(bindings.translate(Keycode::Q), None);
+
+        let bindings = Bindings::load("config/keys.toml").unwrap();
+
+        assert_eq!(bindings.translate(Keycode::Kp8), Some(&Keycode::Up));
+        assert_eq!(bindings.translate(Keycode::Kp2), Some(&Keycode::Down));
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_hotkeys() {
+        assert!(bindings("unknown", "left = [\"W\", \"Nope\"]\n").is_err());
+        assert!(bindings("space", "left = [\"Space\", \"S\"]\n").is_err());
+        assert!(bindings("theme", "right = [\"T\", \"G\"]\n").is_err());
+        assert!(bindings("one", "left = [\"W\"]\n").is_err());
+    }
+}
@@ -0,0 +1,439 @@
+use sdl2::keyboard::Keycode;
+
+use std::fs;
+use std::str::FromStr;
+
+use ai::{Ai, Difficulty};
+
+use component::{Side};
+
+use constants::{Constants};
+
+use nest::{Nest};
+
+// How many lines of output stay on screen.
+const OUTPUT_LINES: usize = 12;
+// How many characters wide the console is, so it doesn't change size while
+// typing.
+const WIDTH: usize = 60;
+// Scripts can run other scripts, but not forever.
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+const COMMANDS: [&str; 9] = ["get", "set", "spawn", "hits", "next_color", "ai", "exec", "help", "clear"];
+
+// The `Constants` fields that can be read and set, see `field` and
+// `set_field`.
+const FIELDS: [&str; 9] = [
+    "paddle_speed",
+    "paddle_width",
+    "paddle_height",
+    "ball_width",
+    "ball_height",
+    "ball_speed_x",
+    "ball_speed_y",
+    "max_balls",
+    "spawn_interval_ms",
+];
+
+const SIDES: [&str; 4] = ["left", "right", "top", "bottom"];
+
+const HELP: [&str; 9] = [
+    "get FIELD [DEPTH]         show a constant",
+    "set FIELD VALUE [DEPTH]   change a constant",
+    "spawn [DEPTH]             serve another ball",
+    "hits SIDE N [DEPTH]       fill a paddle's meter",
+    "next_color [DEPTH]        level up now",
+    "ai SIDE DIFFICULTY|off    let the computer play",
+    "exec FILE                 run the commands in FILE",
+    "clear                     clear the console",
+    "DEPTH is 0 for the outer game, 1 for the one inside",
+];
+
+// A text console for changing the game while it runs, toggled with the key
+// left of 1 in dev mode. Commands can also be run from a startup script, one
+// per line with `#` starting a comment.
+#[derive(Debug, Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    history: Vec<String>,
+    // Where Up and Down have got to in `history`, nothing while typing a new
+    // line.
+    browsing: Option<usize>,
+    output: Vec<String>,
+    // How many scripts are running inside each other.
+    scripts: usize,
+}
+
+impl Console {
+    pub fn toggle(&mut self) -> () {
+        self.open = !self.open;
+    }
+
+    pub fn type_text(&mut self, text: &str) -> () {
+        // The toggle key comes through as text too.
+        self.input.extend(text.chars().filter(|c| *c != '`'));
+    }
+
+    // Handle a key while the console is open, giving back the line to run
+    // when it is Enter.
+    pub fn key(&mut self, keycode: Keycode) -> Option<String> {
+        match keycode {
+            Keycode::Backspace => {
+                self.input.pop();
+            },
+            Keycode::Return => {
+                let line = self.input.trim().to_string();
+
+                self.input.clear();
+                self.browsing = None;
+
+                if line.is_empty() { return None; }
+
+                if self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+
+                return Some(line);
+            },
+            Keycode::Up if !self.history.is_empty() => {
+                let i = match self.browsing {
+                    Some(i) => i.saturating_sub(1),
+                    None => self.history.len() - 1,
+                };
+
+                self.browsing = Some(i);
+                self.input = self.history[i].clone();
+            },
+            Keycode::Down => {
+                if let Some(i) = self.browsing {
+                    if i + 1 < self.history.len() {
+                        self.browsing = Some(i + 1);
+                        self.input = self.history[i + 1].clone();
+                    } else {
+                        self.browsing = None;
+                        self.input.clear();
+                    }
+                }
+            },
+            Keycode::Tab => self.complete(),
+            _ => {}
+        }
+
+        None
+    }
+
+    // Complete the word being typed, or as much of it as all the options
+    // share, listing them when there is more than one.
+    fn complete(&mut self) -> () {
+        let mut words: Vec<String> = self.input.split_whitespace().map(|word| word.to_string()).collect();
+
+        if self.input.is_empty() || self.input.ends_with(' ') {
+            words.push(String::new());
+        }
+
+        let position = words.len() - 1;
+        let options: Vec<&str> = match (position, words[0].as_str()) {
+            (0, _) => COMMANDS.to_vec(),
+            (1, "get") | (1, "set") => FIELDS.to_vec(),
+            (1, "hits") | (1, "ai") => SIDES.to_vec(),
+            (2, "ai") => Difficulty::names().iter().cloned().chain(Some("off")).collect(),
+            _ => vec![],
+        };
+
+        let matches: Vec<&str> = options.into_iter().filter(|option| option.starts_with(words[position].as_str())).collect();
+
+        let completed = match matches.len() {
+            0 => return,
+            1 => format!("{} ", matches[0]),
+            _ => {
+                self.print(&matches.join(" "));
+
+                let first = matches[0];
+                let shared = (0..first.len() + 1).rev()
+                    .find(|end| matches.iter().all(|option| option.starts_with(&first[..*end])))
+                    .unwrap_or(0);
+
+                first[..shared].to_string()
+            },
+        };
+
+        words[position] = completed;
+        self.input = words.join(" ");
+    }
+
+    // Run one command, its output going to the console.
+    pub fn run(&mut self, line: &str, nest: &mut Nest, levels: &mut Vec<Constants>, ai: &mut Option<Ai>) -> () {
+        let line = line.split('#').next().unwrap_or("").trim();
+
+        if line.is_empty() { return; }
+
+        self.print(&format!("> {}", line));
+
+        match self.execute(line, nest, levels, ai) {
+            Ok(lines) => {
+                for line in lines {
+                    self.print(&line);
+                }
+            },
+            Err(e) => self.print(&format!("error: {}", e)),
+        }
+    }
+
+    fn execute(&mut self, line: &str, nest: &mut Nest, levels: &mut Vec<Constants>, ai: &mut Option<Ai>) -> Result<Vec<String>, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let args = &words[1..];
+
+        match words[0] {
+            "help" => Ok(HELP.iter().map(|line| line.to_string()).collect()),
+            "clear" => {
+                self.output.clear();
+                Ok(vec![])
+            },
+            "get" => {
+                let field_name = args.first().ok_or("get FIELD [DEPTH]")?;
+                let depth = depth(args.get(1), levels)?;
+
+                Ok(vec![format!("{} = {} at depth {}", field_name, field(&levels[depth], field_name)?, depth)])
+            },
+            "set" => {
+                if args.len() < 2 { return Err("set FIELD VALUE [DEPTH]".to_string()); }
+
+                let depth = depth(args.get(2), levels)?;
+
+                let mut constants = levels[depth].clone();
+
+                set_field(&mut constants, args[0], args[1])?;
+                levels[depth] = constants;
+                nest.set_levels(levels);
+
+                Ok(vec![format!("{} = {} at depth {}", args[0], field(&levels[depth], args[0])?, depth)])
+            },
+            "spawn" => {
+                let depth = depth(args.first(), levels)?;
+                let mut spawned = 0;
+
+                for (_, game) in nest.games_mut().into_iter().filter(|(path, _)| path.len() == depth) {
+                    game.spawn_ball();
+                    spawned = spawned + 1;
+                }
+
+                Ok(vec![format!("served {} balls", spawned)])
+            },
+            "hits" => {
+                if args.len() < 2 { return Err("hits SIDE N [DEPTH]".to_string()); }
+
+                let side = side(args[0])?;
+                let hits: u8 = parse(args[1])?;
+                let depth = depth(args.get(2), levels)?;
+
+                for (_, game) in nest.games_mut().into_iter().filter(|(path, _)| path.len() == depth) {
+                    if let Some(paddle) = game.paddle_mut(side) {
+                        paddle.hits = hits.min(paddle.max_hits);
+                    }
+                }
+
+                Ok(vec![])
+            },
+            "next_color" => {
+                let depth = depth(args.first(), levels)?;
+
+                for (_, game) in nest.games_mut().into_iter().filter(|(path, _)| path.len() == depth) {
+                    game.next_color();
+                }
+
+                Ok(vec![])
+            },
+            "ai" => {
+                if args.len() < 2 { return Err("ai SIDE DIFFICULTY|off".to_string()); }
+
+                let side = side(args[0])?;
+
+                // The keyboard gets the paddle back once the computer lets
+                // go of it.
+                if let Some(ref old) = *ai {
+                    for (_, game) in nest.games_mut() {
+                        game.bot_actions.remove(&old.side);
+                    }
+                }
+
+                if args[1] == "off" {
+                    *ai = ai.take().filter(|old| old.side != side);
+                    return Ok(vec![]);
+                }
+
+                let difficulty = Difficulty::preset(args[1]).ok_or_else(|| {
+                    format!("unknown ai {}, try one of: {}", args[1], Difficulty::names().join(", "))
+                })?;
+
+                *ai = Some(Ai::new(side, difficulty));
+
+                Ok(vec![format!("{} is played by the computer", side.name())])
+            },
+            "exec" => {
+                let path = args.first().ok_or("exec FILE")?;
+
+                if self.scripts >= MAX_SCRIPT_DEPTH {
+                    return Err(format!("{}: too many scripts inside each other", path));
+                }
+
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| format!("{}: {}", path, e))?;
+
+                self.scripts = self.scripts + 1;
+
+                for line in contents.lines() {
+                    self.run(line, nest, levels, ai);
+                }
+
+                self.scripts = self.scripts - 1;
+
+                Ok(vec![])
+            },
+            command => Err(format!("unknown command {}, try help", command)),
+        }
+    }
+
+    fn print(&mut self, line: &str) -> () {
+        self.output.push(line.to_string());
+
+        if self.output.len() > OUTPUT_LINES {
+            self.output.remove(0);
+        }
+    }
+
+    // The output with the line being typed under it, always the same size.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = vec![String::new(); OUTPUT_LINES - self.output.len()];
+
+        lines.extend(self.output.iter().cloned());
+        lines.push(format!("{:<width$}", format!("> {}_", self.input), width = WIDTH));
+
+        return lines;
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} is not a valid number", value))
+}
+
+fn depth(arg: Option<&&str>, levels: &[Constants]) -> Result<usize, String> {
+    let depth = match arg {
+        Some(arg) => parse(arg)?,
+        None => 0,
+    };
+
+    if depth >= levels.len() {
+        return Err(format!("there are only {} levels", levels.len()));
+    }
+
+    Ok(depth)
+}
+
+fn side(name: &str) -> Result<Side, String> {
+    Side::from_name(name).ok_or_else(|| format!("unknown side {}, try left, right, top or bottom", name))
+}
+
+fn field(constants: &Constants, name: &str) -> Result<String, String> {
+    match name {
+        "paddle_speed" => Ok(constants.max_paddle_speed.to_string()),
+        "paddle_width" => Ok(constants.paddle_width.to_string()),
+        "paddle_height" => Ok(constants.paddle_height.to_string()),
+        "ball_width" => Ok(constants.ball_width.to_string()),
+        "ball_height" => Ok(constants.ball_height.to_string()),
+        "ball_speed_x" => Ok(constants.max_ball_speed.x.to_string()),
+        "ball_speed_y" => Ok(constants.max_ball_speed.y.to_string()),
+        "max_balls" => Ok(constants.max_balls.to_string()),
+        "spawn_interval_ms" => Ok(constants.ball_spawn_interval_ms.to_string()),
+        _ => Err(format!("unknown field {}, try one of: {}", name, FIELDS.join(", "))),
+    }
+}
+
+// Change one field, then check the constants as a whole so nothing that
+// would break the game gets through.
+fn set_field(constants: &mut Constants, name: &str, value: &str) -> Result<(), String> {
+    match name {
+        "paddle_speed" => constants.max_paddle_speed = parse(value)?,
+        "paddle_width" => constants.paddle_width = parse(value)?,
+        "paddle_height" => constants.paddle_height = parse(value)?,
+        "ball_width" => constants.ball_width = parse(value)?,
+        "ball_height" => constants.ball_height = parse(value)?,
+        "ball_speed_x" => constants.max_ball_speed.x = parse(value)?,
+        "ball_speed_y" => constants.max_ball_speed.y = parse(value)?,
+        "max_balls" => constants.max_balls = parse(value)?,
+        "spawn_interval_ms" => constants.ball_spawn_interval_ms = parse(value)?,
+        _ => return Err(format!("unknown field {}, try one of: {}", name, FIELDS.join(", "))),
+    }
+
+    constants.check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use constants::{OUTER_CONSTANTS, INNER_CONSTANTS};
+
+    fn set(name: &str, value: &str) -> Result<Constants, String> {
+        let mut constants = OUTER_CONSTANTS;
+
+        set_field(&mut constants, name, value).map(|_| constants)
+    }
+
+    #[test]
+    fn sets_every_field() {
+        for name in FIELDS.iter() {
+            let constants = set(name, "40").unwrap();
+
+            assert_eq!(field(&constants, name).unwrap(), "40");
+        }
+    }
+
+    #[test]
+    fn rejects_values_that_break_the_game() {
+        assert!(set("size", "40").is_err());
+        assert!(set("paddle_height", "tall").is_err());
+        assert!(set("paddle_height", "0").is_err());
+        assert!(set("paddle_width", "10").is_err());
+        assert!(set("paddle_width", "11").is_ok());
+        assert!(set("ball_width", "-5").is_err());
+        assert!(set("paddle_speed", "0").is_err());
+        assert!(set("ball_speed_x", "0").is_err());
+        assert!(set("max_balls", "0").is_err());
+        assert!(set("spawn_interval_ms", "0").is_ok());
+        // Serves go between 0.1 and half the top vertical speed.
+        assert!(set("ball_speed_y", "0.2").is_err());
+        assert!(set("ball_speed_y", "0.21").is_ok());
+    }
+
+    #[test]
+    fn a_rejected_set_changes_nothing() {
+        let mut levels = vec![OUTER_CONSTANTS, INNER_CONSTANTS];
+        let mut nest = Nest::new(&levels);
+        let mut ai = None;
+        let mut console = Console::default();
+
+        console.run("set ball_speed_y 0.1 1", &mut nest, &mut levels, &mut ai);
+        console.run("set paddle_width 5", &mut nest, &mut levels, &mut ai);
+
+        assert_eq!(levels[1].max_ball_speed.y, INNER_CONSTANTS.max_ball_speed.y);
+        assert_eq!(levels[0].paddle_width, OUTER_CONSTANTS.paddle_width);
+        assert_eq!(console.lines().iter().filter(|line| line.starts_with("error:")).count(), 2);
+
+        console.run("set paddle_width 50 1", &mut nest, &mut levels, &mut ai);
+
+        assert_eq!(levels[1].paddle_width, 50);
+        assert_eq!(nest.game.constants().paddle_width, OUTER_CONSTANTS.paddle_width);
+    }
+
+    #[test]
+    fn checks_the_depth() {
+        let mut levels = vec![OUTER_CONSTANTS];
+        let mut nest = Nest::new(&levels);
+        let mut console = Console::default();
+
+        console.run("set paddle_width 50 1", &mut nest, &mut levels, &mut None);
+
+        assert_eq!(levels[0].paddle_width, OUTER_CONSTANTS.paddle_width);
+    }
+}
@@ -1,16 +1,53 @@
+use std::borrow::Cow;
+
 use vector::{Vector};
+use powerup::{PowerUpSettings};
+use arena::{Arena, EMPTY_ARENA};
+use fx::{FxSettings};
+use coupling::{CouplingSettings, INDEPENDENT};
+use assist::{Handicap, NO_HANDICAP};
 
+// Colors are roles rather than RGB values. The palette in use decides what
+// each role actually looks like, see `palette.rs`.
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Color {
-    Red,
-    Green,
-    Blue,
-    Black,
-    White,
-    Purple,
+    // The field and the inside of the paddles.
+    Background,
+    Ball,
+    // Obstacles, goal posts and anything else the ball bounces off.
+    Border,
+    Portal,
+    // Power ups that help the collector or hinder their opponent.
+    Bonus,
+    Penalty,
+    SpeedUp,
+    SlowDown,
+    // Paddle colors, one per level. Levels past the end of the palette wrap
+    // around.
+    Level(usize),
+}
+
+pub const DEFAULT_COLOR_SEQUENCE: [Color; 3] = [Color::Level(0), Color::Level(1), Color::Level(2)];
+
+// Rules for the hit meter on each paddle and for moving on to the next
+// color.
+#[derive(Debug, Clone)]
+pub struct MeterSettings {
+    // Number of segments the meter on each paddle is split into.
+    pub segments: u8,
+    // Most hits a paddle can hold.
+    pub max_hits: u8,
+    // Hits lost when the ball gets past a paddle.
+    pub miss_decay: u8,
+    // Total hits across all paddles in a game needed to level up. When not
+    // set every paddle's meter has to be full.
+    pub level_up_hits: Option<u8>,
+    // Whether this game waits for every other game in the nest that also
+    // has this set before leveling up, or levels up on its own.
+    pub level_up_together: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Constants {
     pub window_width: i32,
     pub window_height: i32,
@@ -23,7 +60,69 @@ pub struct Constants {
     pub max_ball_speed: Vector,
     pub ball_color: Color,
     pub background_color: Color,
-    pub color_seqence: [Color; 3],
+    pub color_seqence: Cow<'static, [Color]>,
+    pub meter: MeterSettings,
+    // Multi ball. A `max_balls` of 1 is the classic single ball game. Extra
+    // balls are spawned every `ball_spawn_interval_ms` (0 disables the timer)
+    // and/or on level up until `max_balls` are in play.
+    pub max_balls: usize,
+    pub ball_spawn_interval_ms: u64,
+    pub spawn_ball_on_level_up: bool,
+    pub ball_collisions: bool,
+    pub powerups: PowerUpSettings,
+    pub arena: Arena,
+    // Adds paddles along the top and bottom walls, which then count as
+    // goals instead of bouncing the ball.
+    pub four_players: bool,
+    // Replaces the right paddle with a solid wall, for practicing alone.
+    pub practice_wall: bool,
+    // Handicaps for each side, in side order.
+    pub handicaps: [Handicap; 4],
+    pub effects: FxSettings,
+    // How this game interacts with the ball it is nested in.
+    pub coupling: CouplingSettings,
+}
+
+// The slowest a ball is served up or down, in pixels per ms. Serves go
+// between this and half the top vertical speed.
+pub const MIN_SERVE_SPEED: f64 = 0.1;
+
+// The meter is drawn 5 pixels in from every side of the paddle.
+pub const MIN_PADDLE_WIDTH: i32 = 11;
+
+impl Constants {
+    // Sizes and speeds that would break the game, like a paddle with no
+    // length to divide by. Anything that changes the constants while the
+    // game is running checks them again after applying its changes.
+    pub fn check(&self) -> Result<(), String> {
+        let sizes = [self.paddle_height, self.ball_width, self.ball_height];
+
+        if sizes.iter().any(|size| *size <= 0) {
+            return Err("sizes have to be more than 0".to_string());
+        }
+
+        if self.paddle_width < MIN_PADDLE_WIDTH {
+            return Err(format!("paddle_width has to be at least {}", MIN_PADDLE_WIDTH));
+        }
+
+        if self.max_paddle_speed <= 0.0 || self.max_ball_speed.x <= 0.0 {
+            return Err("speeds have to be more than 0".to_string());
+        }
+
+        if self.max_ball_speed.y * 0.5 <= MIN_SERVE_SPEED {
+            return Err(format!(
+                "the top vertical ball speed has to be more than {}, it is {}",
+                MIN_SERVE_SPEED * 2.0,
+                self.max_ball_speed.y,
+            ));
+        }
+
+        if self.max_balls == 0 {
+            return Err("max_balls has to be at least 1".to_string());
+        }
+
+        Ok(())
+    }
 }
 
 pub const OUTER_CONSTANTS: Constants = Constants {
@@ -36,9 +135,44 @@ pub const OUTER_CONSTANTS: Constants = Constants {
     ball_width: 510,
     ball_height: 310,
     max_ball_speed: Vector { x: 0.15, y: 0.8 },
-    ball_color: Color::Purple,
-    background_color: Color::Black,
-    color_seqence: [Color::Red, Color::Green, Color::Blue],
+    ball_color: Color::Ball,
+    background_color: Color::Background,
+    color_seqence: Cow::Borrowed(&DEFAULT_COLOR_SEQUENCE),
+    meter: MeterSettings {
+        segments: 5,
+        max_hits: 5,
+        miss_decay: 1,
+        level_up_hits: None,
+        level_up_together: true,
+    },
+    max_balls: 1,
+    ball_spawn_interval_ms: 20_000,
+    spawn_ball_on_level_up: true,
+    ball_collisions: true,
+    powerups: PowerUpSettings {
+        grow: true,
+        shrink: true,
+        speed_up: true,
+        slow_down: true,
+        extra_ball: true,
+        reverse_controls: true,
+        shield: true,
+        spawn_interval_ms: 15_000,
+        duration_ms: 10_000,
+        max_on_field: 2,
+        size: 40,
+    },
+    arena: EMPTY_ARENA,
+    four_players: false,
+    practice_wall: false,
+    handicaps: [NO_HANDICAP, NO_HANDICAP, NO_HANDICAP, NO_HANDICAP],
+    effects: FxSettings {
+        trails: true,
+        flashes: true,
+        particles: true,
+        shake: true,
+    },
+    coupling: INDEPENDENT,
 };
 
 pub const INNER_CONSTANTS: Constants = Constants {
@@ -51,7 +185,42 @@ pub const INNER_CONSTANTS: Constants = Constants {
     ball_width: 15,
     ball_height: 15,
     max_ball_speed: Vector { x: 0.45, y: 1.5 },
-    ball_color: Color::Purple,
-    background_color: Color::Black,
-    color_seqence: [Color::Red, Color::Green, Color::Blue],
+    ball_color: Color::Ball,
+    background_color: Color::Background,
+    color_seqence: Cow::Borrowed(&DEFAULT_COLOR_SEQUENCE),
+    meter: MeterSettings {
+        segments: 5,
+        max_hits: 5,
+        miss_decay: 1,
+        level_up_hits: None,
+        level_up_together: true,
+    },
+    max_balls: 1,
+    ball_spawn_interval_ms: 20_000,
+    spawn_ball_on_level_up: true,
+    ball_collisions: true,
+    powerups: PowerUpSettings {
+        grow: true,
+        shrink: true,
+        speed_up: true,
+        slow_down: true,
+        extra_ball: true,
+        reverse_controls: true,
+        shield: true,
+        spawn_interval_ms: 15_000,
+        duration_ms: 10_000,
+        max_on_field: 2,
+        size: 12,
+    },
+    arena: EMPTY_ARENA,
+    four_players: false,
+    practice_wall: false,
+    handicaps: [NO_HANDICAP, NO_HANDICAP, NO_HANDICAP, NO_HANDICAP],
+    effects: FxSettings {
+        trails: true,
+        flashes: true,
+        particles: true,
+        shake: true,
+    },
+    coupling: INDEPENDENT,
 };
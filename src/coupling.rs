@@ -0,0 +1,65 @@
+// How a nested game and the ball it is drawn on affect each other. These
+// live in the nested game's constants, the outermost game's are unused.
+#[derive(Debug, Clone)]
+pub struct CouplingSettings {
+    // The host ball's speed is multiplied by this whenever the nested game
+    // has a miss, up to `MAX_SPEED_UP` times its normal top speed. 1.0
+    // leaves it alone.
+    pub miss_speed_up: f64,
+    // When a paddle in the host game hits the ball, the nested game's balls
+    // get a random vertical kick of up to this share of their top speed.
+    pub host_hit_jolt: f64,
+    // A miss in the nested game ends the host ball's rally, counting as a
+    // miss for the same side of the host game and serving the ball again.
+    pub miss_ends_host_rally: bool,
+}
+
+// How fast `miss_speed_up` can make the host ball, relative to its top speed.
+pub const MAX_SPEED_UP: f64 = 2.0;
+
+// The games play independently.
+pub const INDEPENDENT: CouplingSettings = CouplingSettings {
+    miss_speed_up: 1.0,
+    host_hit_jolt: 0.0,
+    miss_ends_host_rally: false,
+};
+
+impl CouplingSettings {
+    // The built in rule sets, picked with `--rules`.
+    pub fn preset(name: &str) -> Option<CouplingSettings> {
+        match name {
+            "independent" => Some(INDEPENDENT),
+            // Missing on the ball speeds the ball up.
+            "accelerate" => Some(CouplingSettings { miss_speed_up: 1.15, ..INDEPENDENT }),
+            // Hitting the ball shakes up the game inside it.
+            "jolt" => Some(CouplingSettings { host_hit_jolt: 0.5, ..INDEPENDENT }),
+            // You have to win the inner game to keep the outer rally going.
+            "linked" => Some(CouplingSettings { miss_ends_host_rally: true, ..INDEPENDENT }),
+            "chaos" => Some(CouplingSettings {
+                miss_speed_up: 1.15,
+                host_hit_jolt: 0.5,
+                miss_ends_host_rally: false,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn names() -> &'static [&'static str] {
+        &["independent", "accelerate", "jolt", "linked", "chaos"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_name_has_a_preset() {
+        for name in CouplingSettings::names() {
+            assert!(CouplingSettings::preset(name).is_some(), "{}", name);
+        }
+
+        assert!(CouplingSettings::preset("Independent").is_none());
+        assert!(CouplingSettings::preset("").is_none());
+    }
+}
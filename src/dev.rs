@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+
+use modes::{Rules};
+
+use nest::{Nest};
+
+use stats::{MatchStats};
+
+// How many frames can be rewound, about ten seconds at 60 fps.
+const HISTORY_FRAMES: usize = 600;
+
+// How far a single step goes while paused, in ms.
+pub const STEP_MS: u64 = 16;
+
+const MIN_TIME_SCALE: f64 = 0.125;
+const MAX_TIME_SCALE: f64 = 4.0;
+
+// Tools for tuning the physics, turned on with `--dev`: slowing time down or
+// speeding it up, stepping a frame at a time while paused, rewinding and
+// drawing the hitboxes.
+#[derive(Debug)]
+pub struct DevMode {
+    // Multiplies the time every update is given, on top of the game speed.
+    pub time_scale: f64,
+    pub hitboxes: bool,
+    // The most recent frames, oldest first.
+    history: VecDeque<(Nest, MatchStats, Rules)>,
+}
+
+impl DevMode {
+    pub fn new() -> DevMode {
+        DevMode { time_scale: 1.0, hitboxes: false, history: VecDeque::new() }
+    }
+
+    pub fn slower(&mut self) -> () {
+        self.time_scale = (self.time_scale / 2.0).max(MIN_TIME_SCALE);
+    }
+
+    pub fn faster(&mut self) -> () {
+        self.time_scale = (self.time_scale * 2.0).min(MAX_TIME_SCALE);
+    }
+
+    // Call before every update that changes the match, so it can be undone.
+    pub fn record(&mut self, nest: &Nest, stats: &MatchStats, rules: &Rules) -> () {
+        if self.history.len() >= HISTORY_FRAMES {
+            self.history.pop_front();
+        }
+
+        self.history.push_back((nest.clone(), stats.clone(), rules.clone()));
+    }
+
+    // The match as it was a frame ago, if there is one left.
+    pub fn rewind(&mut self) -> Option<(Nest, MatchStats, Rules)> {
+        self.history.pop_back()
+    }
+
+    // A fresh match has nothing to go back to.
+    pub fn clear(&mut self) -> () {
+        self.history.clear();
+    }
+
+    // Call every frame, games that appear get the overlay too.
+    pub fn apply(&self, nest: &mut Nest) -> () {
+        for (_, game) in nest.games_mut() {
+            game.hitboxes = self.hitboxes;
+        }
+    }
+
+    pub fn status(&self) -> String {
+        format!("dev x{} rewind {}", self.time_scale, self.history.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use constants::{OUTER_CONSTANTS};
+    use modes::{Mode};
+
+    #[test]
+    fn time_scale_stops_at_its_limits() {
+        let mut dev = DevMode::new();
+
+        for _ in 0..10 { dev.slower(); }
+        assert_eq!(dev.time_scale, MIN_TIME_SCALE);
+
+        for _ in 0..10 { dev.faster(); }
+        assert_eq!(dev.time_scale, MAX_TIME_SCALE);
+    }
+
+    #[test]
+    fn rewinds_the_most_recent_frames() {
+        let mut dev = DevMode::new();
+        let mut nest = Nest::new(&[OUTER_CONSTANTS]);
+        let mut stats = MatchStats::new();
+        let rules = Rules::new(Mode::Classic { first_to: None });
+
+        for frame in 0..HISTORY_FRAMES as u64 + 10 {
+            stats.duration_ms = frame;
+            dev.record(&nest, &stats, &rules);
+        }
+
+        assert_eq!(dev.status(), format!("dev x1 rewind {}", HISTORY_FRAMES));
+        assert_eq!(dev.rewind().unwrap().1.duration_ms, HISTORY_FRAMES as u64 + 9);
+
+        for _ in 1..HISTORY_FRAMES - 1 { dev.rewind(); }
+        assert_eq!(dev.rewind().unwrap().1.duration_ms, 10);
+        assert!(dev.rewind().is_none());
+
+        dev.record(&nest, &stats, &rules);
+        dev.clear();
+        assert!(dev.rewind().is_none());
+
+        dev.hitboxes = true;
+        dev.apply(&mut nest);
+        assert!(nest.games().iter().all(|game| game.hitboxes));
+    }
+}
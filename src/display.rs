@@ -0,0 +1,80 @@
+extern crate sdl2;
+
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::{FullscreenType, Window};
+
+use vector::Vector;
+
+use camera::{Transform};
+use component::{FRect};
+
+// Maps the logical coordinates the game is played in onto the pixels of
+// the window. The logical field is scaled up or down to fit the window and
+// letterboxed along the other axis, so the game looks the same whatever
+// size or shape the window is.
+#[derive(Debug)]
+pub struct Display {
+    logical: Vector,
+    // Size of the window in actual pixels, which on high DPI screens is
+    // larger than the window size the OS reports.
+    physical: Vector,
+}
+
+impl Display {
+    pub fn new(logical: Vector, canvas: &Canvas<Window>) -> Display {
+        let mut display = Display {
+            logical: logical,
+            physical: Vector { x: 0.0, y: 0.0 },
+        };
+
+        display.resize(canvas);
+
+        return display;
+    }
+
+    // Call whenever the window changes size.
+    pub fn resize(&mut self, canvas: &Canvas<Window>) -> () {
+        let (width, height) = canvas.output_size().unwrap();
+
+        self.physical = Vector { x: width as f64, y: height as f64 };
+    }
+
+    pub fn transform(&self) -> Transform {
+        let origin = Vector { x: 0.0, y: 0.0 };
+
+        Transform::fit(&origin, &self.logical, &origin, &self.physical)
+    }
+
+    // The part of the window the game is drawn in, between the letterbox
+    // bars.
+    pub fn viewport(&self) -> Rect {
+        self.transform().apply(&FRect::new(0.0, 0.0, self.logical.x, self.logical.y))
+    }
+
+    pub fn toggle_fullscreen(&mut self, canvas: &mut Canvas<Window>) -> () {
+        let fullscreen = match canvas.window().fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+
+        if let Err(e) = canvas.window_mut().set_fullscreen(fullscreen) {
+            println!("Could not switch fullscreen: {}", e);
+        }
+
+        self.resize(canvas);
+    }
+}
+
+// The window size to start with when none is given, the logical size shrunk
+// to fit on the screen if it is too big.
+pub fn default_window_size(logical: &Vector, screen: Option<Rect>) -> (u32, u32) {
+    let scale = match screen {
+        Some(screen) => (screen.width() as f64 * 0.9 / logical.x)
+            .min(screen.height() as f64 * 0.9 / logical.y)
+            .min(1.0),
+        None => 1.0,
+    };
+
+    ((logical.x * scale) as u32, (logical.y * scale) as u32)
+}
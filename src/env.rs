@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use serde_json;
+use toml;
+
+use vector::{Vector};
+
+use component::{Side};
+
+use constants::{Constants};
+
+use events::{GameEvent};
+
+use fx::{FxSettings};
+
+use game::{Action, Game, PADDLE_KEYS};
+
+use nest::{Nest};
+
+use palette::{Palette};
+
+use render::{render_pixels};
+
+// What each side is rewarded for. Rewards from a game nested `n` levels
+// in are multiplied by `inner_scale` `n` times.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RewardSettings {
+    // For every point scored, one for every miss by another side.
+    pub point: f64,
+    // For every miss, usually negative.
+    pub miss: f64,
+    // For every return.
+    pub hit: f64,
+    pub inner_scale: f64,
+    // Every step, times how well the paddle lines up with the nearest ball
+    // in the outer game, from 0 to 1.
+    pub tracking: f64,
+}
+
+impl Default for RewardSettings {
+    fn default() -> RewardSettings {
+        RewardSettings {
+            point: 1.0,
+            miss: -1.0,
+            hit: 0.0,
+            inner_scale: 1.0,
+            tracking: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EnvSettings {
+    // Game time per update.
+    pub step_ms: u64,
+    // Updates per step, each with the same actions.
+    pub frame_skip: u32,
+    // The episode ends after this many steps, 0 for no limit.
+    pub max_steps: u64,
+    // Or when a side has this many points.
+    pub first_to: Option<u32>,
+    // Also observe the outer game as RGB pixels of this width and height.
+    pub pixels: Option<(u32, u32)>,
+    pub rewards: RewardSettings,
+    // Environments to run in parallel, one thread each.
+    pub envs: usize,
+}
+
+impl Default for EnvSettings {
+    fn default() -> EnvSettings {
+        EnvSettings {
+            step_ms: 16,
+            frame_skip: 1,
+            max_steps: 10_000,
+            first_to: None,
+            pixels: None,
+            rewards: RewardSettings::default(),
+            envs: 1,
+        }
+    }
+}
+
+impl EnvSettings {
+    pub fn load(path: &str) -> Result<EnvSettings, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        toml::from_str(&contents).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Observation {
+    // See `Env::observe`.
+    pub vector: Vec<f64>,
+    // Row by row, three bytes per pixel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixels: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Step {
+    pub observation: Observation,
+    // One per side, in side order.
+    pub rewards: Vec<f64>,
+    pub done: bool,
+}
+
+// A headless match for training agents against, driven one step at a time
+// in the style of a gym environment. Every side is controlled through
+// `step`, and each side's action moves its paddle in every nested game just
+// like the keyboard does.
+#[derive(Debug)]
+pub struct Env {
+    pub settings: EnvSettings,
+    levels: Vec<Constants>,
+    palette: Palette,
+    nest: Nest,
+    steps: u64,
+    points: Vec<u32>,
+    // Paddle centers at the last observation, by level and then side.
+    paddles: Vec<Vector>,
+}
+
+impl Env {
+    pub fn new(levels: &[Constants], settings: EnvSettings) -> Env {
+        // Effects only slow things down without anyone watching.
+        let levels: Vec<Constants> = levels.iter().cloned().map(|mut constants| {
+            constants.effects = FxSettings::all(false);
+            constants
+        }).collect();
+
+        let nest = Nest::seeded(&levels, 0);
+        let sides = nest.game.sides().len();
+
+        Env {
+            settings: settings,
+            levels: levels,
+            palette: Palette::default(),
+            nest: nest,
+            steps: 0,
+            points: vec![0; sides],
+            paddles: vec![],
+        }
+    }
+
+    pub fn sides(&self) -> Vec<Side> {
+        self.nest.game.sides()
+    }
+
+    // Every level has four numbers per paddle and five per ball slot.
+    pub fn observation_size(&self) -> usize {
+        let sides = self.sides().len();
+
+        self.levels.iter().map(|level| sides * 4 + level.max_balls * 5).sum()
+    }
+
+    // Start a new episode. The same seed plays out the same way given the
+    // same actions.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.nest = Nest::seeded(&self.levels, seed);
+        self.nest.start();
+        self.steps = 0;
+        self.points = vec![0; self.sides().len()];
+        self.paddles = vec![];
+
+        self.observe()
+    }
+
+    // Move each side's paddle by its action, missing actions meaning stay
+    // put, and run the game on for a step.
+    pub fn step(&mut self, actions: &[Action]) -> Step {
+        let sides = self.sides();
+        let mut keys_pressed = HashMap::new();
+
+        for (i, action) in actions.iter().enumerate().take(sides.len()) {
+            let (up, down) = &PADDLE_KEYS[sides[i] as usize];
+
+            match action.signum() {
+                -1 => { keys_pressed.insert(up, true); },
+                1 => { keys_pressed.insert(down, true); },
+                _ => {}
+            }
+        }
+
+        let mut rewards = vec![0.0; sides.len()];
+
+        for _ in 0..self.settings.frame_skip.max(1) {
+            self.nest.update(&keys_pressed, self.settings.step_ms);
+            self.reward(&sides, &mut rewards);
+        }
+
+        let tracking = self.settings.rewards.tracking;
+
+        if tracking != 0.0 {
+            for (i, side) in sides.iter().enumerate() {
+                rewards[i] = rewards[i] + tracking * lined_up(&self.nest.game, *side);
+            }
+        }
+
+        self.steps = self.steps + 1;
+
+        let out_of_time = self.settings.max_steps > 0 && self.steps >= self.settings.max_steps;
+        let won = match self.settings.first_to {
+            Some(first_to) => self.points.iter().any(|points| *points >= first_to),
+            None => false,
+        };
+
+        Step {
+            observation: self.observe(),
+            rewards: rewards,
+            done: out_of_time || won,
+        }
+    }
+
+    fn reward(&mut self, sides: &[Side], rewards: &mut [f64]) -> () {
+        let settings = &self.settings.rewards;
+
+        for (path, event) in self.nest.events() {
+            let scale = settings.inner_scale.powi(path.len() as i32);
+
+            match *event {
+                GameEvent::Miss { side, .. } => {
+                    for (i, other) in sides.iter().enumerate() {
+                        if *other == side {
+                            rewards[i] = rewards[i] + settings.miss * scale;
+                        } else {
+                            rewards[i] = rewards[i] + settings.point * scale;
+                            self.points[i] = self.points[i] + 1;
+                        }
+                    }
+                },
+                GameEvent::PaddleHit { side, .. } => {
+                    if let Some(i) = sides.iter().position(|other| *other == side) {
+                        rewards[i] = rewards[i] + settings.hit * scale;
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // For each level, outermost first, the game nested in the ball with the
+    // lowest id is observed. Its paddles come first, in side order, as the
+    // center's x and y as a share of the field size and its velocity as a
+    // share of the top paddle speed. Then one slot per ball the game can
+    // have: 1 if there is a ball in it, its center as a share of the field
+    // size and its velocity as a share of the top ball speed. Levels without
+    // a game and empty slots are all zeroes.
+    pub fn observe(&mut self) -> Observation {
+        let mut vector = Vec::with_capacity(self.observation_size());
+        let mut paddles = vec![];
+        let sides = self.sides();
+        let delta = (self.settings.step_ms * self.settings.frame_skip.max(1) as u64) as f64;
+        let mut nest = Some(&self.nest);
+
+        for (depth, level) in self.levels.iter().enumerate() {
+            let game = nest.map(|nest| &nest.game);
+            let width = level.window_width as f64;
+            let height = level.window_height as f64;
+            let top_speed = level.max_paddle_speed * delta;
+
+            for (i, side) in sides.iter().enumerate() {
+                let center = match game.and_then(|game| game.paddle(*side)) {
+                    Some(paddle) => paddle.center(),
+                    None => Vector { x: 0.0, y: 0.0 },
+                };
+
+                // Nothing to compare with on the first observation.
+                let last = self.paddles.get(depth * sides.len() + i).cloned().unwrap_or_else(|| center.clone());
+
+                vector.push(center.x / width);
+                vector.push(center.y / height);
+                vector.push((center.x - last.x) / top_speed);
+                vector.push((center.y - last.y) / top_speed);
+
+                paddles.push(center);
+            }
+
+            let balls = game.map(|game| game.balls.as_slice()).unwrap_or(&[]);
+
+            for slot in 0..level.max_balls {
+                match balls.get(slot) {
+                    Some(ball) => {
+                        let center = ball.center();
+
+                        vector.push(1.0);
+                        vector.push(center.x / width);
+                        vector.push(center.y / height);
+                        vector.push(ball.velocity.x / level.max_ball_speed.x);
+                        vector.push(ball.velocity.y / level.max_ball_speed.y);
+                    },
+                    None => vector.extend(&[0.0; 5]),
+                }
+            }
+
+            nest = nest.and_then(|nest| nest.inner.first().map(|(_, inner)| inner));
+        }
+
+        self.paddles = paddles;
+
+        let pixels = match self.settings.pixels {
+            Some((width, height)) => Some(render_pixels(&mut self.nest, &self.palette, width, height)),
+            None => None,
+        };
+
+        Observation { vector: vector, pixels: pixels }
+    }
+}
+
+// How well the paddle on `side` lines up with the nearest ball, 1 when it
+// is right in front of it and 0 when it is the whole field away.
+fn lined_up(game: &Game, side: Side) -> f64 {
+    let paddle = match game.paddle(side) {
+        Some(paddle) => paddle.center(),
+        None => return 0.0,
+    };
+    let size = game.size();
+
+    // How far the ball has to travel to reach the paddle.
+    let distance = |ball: &Vector| {
+        if side.is_horizontal() { (ball.y - paddle.y).abs() } else { (ball.x - paddle.x).abs() }
+    };
+
+    let nearest = game.balls.iter()
+        .map(|ball| ball.center())
+        .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap());
+
+    match nearest {
+        Some(ball) if side.is_horizontal() => 1.0 - (ball.x - paddle.x).abs() / size.x,
+        Some(ball) => 1.0 - (ball.y - paddle.y).abs() / size.y,
+        None => 0.0,
+    }
+}
+
+enum Request {
+    Reset(u64),
+    Step(Vec<Action>),
+}
+
+enum Reply {
+    Reset(Observation),
+    Step(Step),
+}
+
+// Environments stepped in parallel, each on its own thread. The threads
+// finish when this is dropped.
+pub struct Envs {
+    workers: Vec<(Sender<Request>, Receiver<Reply>)>,
+    pub sides: usize,
+    pub observation_size: usize,
+}
+
+impl Envs {
+    pub fn new(levels: &[Constants], settings: EnvSettings) -> Envs {
+        let template = Env::new(levels, settings.clone());
+        let mut workers = vec![];
+
+        for _ in 0..settings.envs.max(1) {
+            let (request_sender, requests) = channel();
+            let (reply_sender, replies) = channel();
+            let mut env = Env::new(levels, settings.clone());
+
+            thread::spawn(move || {
+                for request in requests.iter() {
+                    let reply = match request {
+                        Request::Reset(seed) => Reply::Reset(env.reset(seed)),
+                        Request::Step(actions) => Reply::Step(env.step(&actions)),
+                    };
+
+                    if reply_sender.send(reply).is_err() { break; }
+                }
+            });
+
+            workers.push((request_sender, replies));
+        }
+
+        Envs {
+            workers: workers,
+            sides: template.sides().len(),
+            observation_size: template.observation_size(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.workers.len()
+    }
+
+    // One seed per environment.
+    pub fn reset(&self, seeds: &[u64]) -> Vec<Observation> {
+        for ((requests, _), seed) in self.workers.iter().zip(seeds.iter()) {
+            requests.send(Request::Reset(*seed)).unwrap();
+        }
+
+        self.workers.iter().take(seeds.len()).map(|(_, replies)| match replies.recv().unwrap() {
+            Reply::Reset(observation) => observation,
+            Reply::Step(_) => unreachable!(),
+        }).collect()
+    }
+
+    // One set of actions per environment.
+    pub fn step(&self, actions: &[Vec<Action>]) -> Vec<Step> {
+        for ((requests, _), actions) in self.workers.iter().zip(actions.iter()) {
+            requests.send(Request::Step(actions.clone())).unwrap();
+        }
+
+        self.workers.iter().take(actions.len()).map(|(_, replies)| match replies.recv().unwrap() {
+            Reply::Step(step) => step,
+            Reply::Reset(_) => unreachable!(),
+        }).collect()
+    }
+}
+
+// Requests understood by `serve`, one JSON object per line.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+enum GymRequest {
+    Info,
+    Reset { seeds: Vec<u64> },
+    Step { actions: Vec<Vec<Action>> },
+}
+
+// Answers requests from `input` until it closes, with one JSON object per
+// line on `output`, so the environments can be driven from another
+// language. Anything that can't be understood gets an `error` back.
+pub fn serve<R: BufRead, W: Write>(envs: &Envs, input: R, mut output: W) -> Result<(), String> {
+    for line in input.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+
+        if line.trim().is_empty() { continue; }
+
+        let reply = match serde_json::from_str::<GymRequest>(&line) {
+            Ok(GymRequest::Info) => json!({
+                "envs": envs.count(),
+                "sides": envs.sides,
+                "observation_size": envs.observation_size,
+            }),
+            Ok(GymRequest::Reset { ref seeds }) if seeds.len() == envs.count() => {
+                json!({ "observations": envs.reset(seeds) })
+            },
+            Ok(GymRequest::Step { ref actions }) if actions.len() == envs.count() => {
+                json!({ "steps": envs.step(actions) })
+            },
+            Ok(_) => json!({ "error": format!("expected one entry per environment, there are {}", envs.count()) }),
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+
+        writeln!(output, "{}", reply).map_err(|e| e.to_string())?;
+        output.flush().map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use vector::Vector;
+
+use component::{Side};
+
+use powerup::{PowerUpKind};
+
+// Things that happen during a game update. A `Game` collects these as they
+// happen and anything that wants to react to them, sounds, effects and so
+// on, reads them back out rather than the game calling into it directly.
+// Positions are in the game's own coordinates.
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    // A ball was put into play from the center of the field.
+    Serve { ball: u32 },
+    // `hits` is the paddle's meter after the hit, `level` the color index
+    // the game was on and `speed` how fast the ball was going in pixels
+    // per ms.
+    PaddleHit { side: Side, ball: u32, pos: Vector, speed: f64, hits: u8, max_hits: u8, level: usize },
+    // The ball got past the paddle on `side`.
+    Miss { side: Side, ball: u32, pos: Vector, speed: f64 },
+    // A shield absorbed what would have been a miss.
+    Blocked { side: Side, pos: Vector },
+    BallCollision { pos: Vector },
+    Teleport { pos: Vector },
+    PowerUp { kind: PowerUpKind, side: Side, pos: Vector },
+    LevelUp { level: usize },
+}
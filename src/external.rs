@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json;
+
+use component::{Ball, Paddle, Side};
+
+use constants::{Constants};
+
+use fx::{FxSettings};
+
+use game::{Action, Game};
+
+use nest::{Nest};
+
+use stats::{MatchStats};
+
+// Paddles played by another program, which can be written in anything that
+// reads and writes lines of JSON. The program is either started with its
+// input and output connected to the game, or already running and listening
+// on a local socket, given as `tcp:HOST:PORT`.
+//
+// The game first sends
+//
+//     {"type": "hello", "sides": ["right"], "timeout_ms": 20}
+//
+// and then every tick the state of every game in the nest, paths being the
+// ids of the balls a game is nested in, outermost first:
+//
+//     {"type": "tick", "tick": 7, "games": [{"path": [], "width": 800, ...}]}
+//
+// The program replies to each tick with the moves for its paddles, anything
+// left out staying put:
+//
+//     {"tick": 7, "moves": [{"path": [], "side": "right", "move": "up"}]}
+//
+// A reply that doesn't arrive within the timeout counts as staying put and
+// is ignored if it turns up later. When the match is over the game sends
+// `{"type": "end", "points": {"left": 5, "right": 3}}`.
+pub struct ExternalBot {
+    pub name: String,
+    pub sides: Vec<Side>,
+    pub timeout: Duration,
+    // Ticks that went by without a reply in time.
+    pub timeouts: u32,
+    output: Box<dyn Write + Send>,
+    replies: Receiver<String>,
+    child: Option<Child>,
+    tick: u64,
+    disconnected: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct PaddleState {
+    side: &'static str,
+    x: f64,
+    y: f64,
+    width: u32,
+    height: u32,
+    hits: u8,
+    max_hits: u8,
+}
+
+#[derive(Debug, Serialize)]
+struct BallState {
+    id: u32,
+    x: f64,
+    y: f64,
+    vx: f64,
+    vy: f64,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct GameState {
+    path: Vec<u32>,
+    width: f64,
+    height: f64,
+    level: usize,
+    paddles: Vec<PaddleState>,
+    balls: Vec<BallState>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Move {
+    #[serde(default)]
+    path: Vec<u32>,
+    side: String,
+    #[serde(rename = "move")]
+    direction: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    tick: u64,
+    #[serde(default)]
+    moves: Vec<Move>,
+}
+
+impl PaddleState {
+    fn new(paddle: &Paddle, side: Side) -> PaddleState {
+        PaddleState {
+            side: side.name(),
+            x: paddle.pos.x,
+            y: paddle.pos.y,
+            width: paddle.width,
+            height: paddle.height,
+            hits: paddle.hits,
+            max_hits: paddle.max_hits,
+        }
+    }
+}
+
+impl BallState {
+    fn new(ball: &Ball) -> BallState {
+        BallState {
+            id: ball.id,
+            x: ball.pos.x,
+            y: ball.pos.y,
+            vx: ball.velocity.x,
+            vy: ball.velocity.y,
+            width: ball.width,
+            height: ball.height,
+        }
+    }
+}
+
+impl GameState {
+    fn new(path: Vec<u32>, game: &Game) -> GameState {
+        let size = game.size();
+
+        GameState {
+            path: path,
+            width: size.x,
+            height: size.y,
+            level: game.color_index,
+            paddles: game.sides().into_iter()
+                .filter_map(|side| game.paddle(side).map(|paddle| PaddleState::new(paddle, side)))
+                .collect(),
+            balls: game.balls.iter().map(BallState::new).collect(),
+        }
+    }
+}
+
+impl ExternalBot {
+    // Start the bot, `command` being either a program and its arguments or
+    // `tcp:HOST:PORT` for one that is already running.
+    pub fn connect(command: &str, sides: Vec<Side>, timeout: Duration) -> Result<ExternalBot, String> {
+        let (output, input, child): (Box<dyn Write + Send>, Box<dyn Read + Send>, Option<Child>) =
+            if let Some(address) = command.strip_prefix("tcp:") {
+                let stream = TcpStream::connect(address).map_err(|e| format!("{}: {}", address, e))?;
+                let input = stream.try_clone().map_err(|e| format!("{}: {}", address, e))?;
+
+                (Box::new(stream), Box::new(input), None)
+            } else {
+                let mut parts = command.split_whitespace();
+                let program = parts.next().ok_or_else(|| "empty bot command".to_string())?;
+
+                let mut child = Command::new(program)
+                    .args(parts)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| format!("{}: {}", command, e))?;
+
+                let output = child.stdin.take().unwrap();
+                let input = child.stdout.take().unwrap();
+
+                (Box::new(output), Box::new(input), Some(child))
+            };
+
+        // Replies are read on their own thread so waiting for one can time
+        // out.
+        let (sender, replies) = channel();
+
+        thread::spawn(move || {
+            for line in BufReader::new(input).lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut bot = ExternalBot {
+            name: command.to_string(),
+            sides: sides,
+            timeout: timeout,
+            timeouts: 0,
+            output: output,
+            replies: replies,
+            child: child,
+            tick: 0,
+            disconnected: false,
+        };
+
+        let names: Vec<&str> = bot.sides.iter().map(|side| side.name()).collect();
+        let hello = json!({
+            "type": "hello",
+            "sides": names,
+            "timeout_ms": timeout.as_secs() * 1000 + timeout.subsec_millis() as u64,
+        });
+        bot.send(&hello.to_string());
+
+        Ok(bot)
+    }
+
+    fn send(&mut self, line: &str) -> () {
+        if self.disconnected { return; }
+
+        let sent = writeln!(self.output, "{}", line).and_then(|_| self.output.flush());
+
+        if sent.is_err() {
+            println!("Bot {} has gone away", self.name);
+            self.disconnected = true;
+        }
+    }
+
+    // Send the state of every game and move the bot's paddles however it
+    // says to.
+    pub fn control(&mut self, nest: &mut Nest) -> () {
+        self.tick = self.tick + 1;
+
+        let mut games = nest.games_mut();
+
+        for (_, game) in games.iter_mut() {
+            for side in self.sides.iter() {
+                game.bot_actions.insert(*side, 0);
+            }
+        }
+
+        if self.disconnected { return; }
+
+        let states: Vec<GameState> = games.iter().map(|(path, game)| GameState::new(path.clone(), game)).collect();
+        let message = json!({ "type": "tick", "tick": self.tick, "games": states });
+        self.send(&message.to_string());
+
+        let reply = match self.wait_for_reply() {
+            Some(reply) => reply,
+            None => return,
+        };
+
+        for bot_move in reply.moves {
+            let side = match Side::from_name(&bot_move.side) {
+                Some(side) if self.sides.contains(&side) => side,
+                _ => continue,
+            };
+            let action: Action = match bot_move.direction.as_str() {
+                "up" | "left" => -1,
+                "down" | "right" => 1,
+                _ => 0,
+            };
+
+            if let Some((_, game)) = games.iter_mut().find(|(path, _)| *path == bot_move.path) {
+                game.bot_actions.insert(side, action);
+            }
+        }
+    }
+
+    // The reply to the current tick, skipping any that were too late for
+    // earlier ones.
+    fn wait_for_reply(&mut self) -> Option<Reply> {
+        let deadline = Instant::now() + self.timeout;
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+
+            match self.replies.recv_timeout(left) {
+                Ok(line) => match serde_json::from_str::<Reply>(&line) {
+                    Ok(ref reply) if reply.tick < self.tick => continue,
+                    Ok(reply) => return Some(reply),
+                    Err(e) => println!("Bot {} sent something odd: {}", self.name, e),
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    self.timeouts = self.timeouts + 1;
+                    return None;
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    println!("Bot {} has gone away", self.name);
+                    self.disconnected = true;
+                    return None;
+                },
+            }
+        }
+    }
+
+    pub fn end(&mut self, stats: &MatchStats) -> () {
+        let points: HashMap<&str, u32> = Side::all().iter()
+            .map(|side| (side.name(), stats.points(*side)))
+            .collect();
+
+        self.send(&json!({ "type": "end", "points": points }).to_string());
+    }
+}
+
+impl Drop for ExternalBot {
+    fn drop(&mut self) -> () {
+        if let Some(ref mut child) = self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BotMatch {
+    // The bots' commands, left then right.
+    pub bots: (String, String),
+    pub points: (u32, u32),
+    pub timeouts: (u32, u32),
+    pub duration_ms: u64,
+}
+
+// Play two bots against each other without a window, left against right,
+// until one of them has `first_to` points or `max_ms` of game time has
+// gone by.
+pub fn play_match(
+    levels: &[Constants],
+    left: &str,
+    right: &str,
+    first_to: u32,
+    timeout: Duration,
+    max_ms: u64,
+    seed: u64
+) -> Result<BotMatch, String> {
+    const STEP_MS: u64 = 16;
+
+    let levels: Vec<Constants> = levels.iter().cloned().map(|mut constants| {
+        constants.effects = FxSettings::all(false);
+        constants
+    }).collect();
+
+    let mut bots = [
+        ExternalBot::connect(left, vec![Side::One], timeout)?,
+        ExternalBot::connect(right, vec![Side::Two], timeout)?,
+    ];
+
+    let mut nest = Nest::seeded(&levels, seed);
+    let mut stats = MatchStats::new();
+    let keys_pressed = HashMap::new();
+
+    nest.start();
+
+    while stats.duration_ms < max_ms &&
+        stats.points(Side::One) < first_to &&
+        stats.points(Side::Two) < first_to {
+
+        for bot in bots.iter_mut() {
+            bot.control(&mut nest);
+        }
+
+        nest.update(&keys_pressed, STEP_MS);
+        stats.update(&nest, STEP_MS);
+    }
+
+    for bot in bots.iter_mut() {
+        bot.end(&stats);
+    }
+
+    Ok(BotMatch {
+        bots: (left.to_string(), right.to_string()),
+        points: (stats.points(Side::One), stats.points(Side::Two)),
+        timeouts: (bots[0].timeouts, bots[1].timeouts),
+        duration_ms: stats.duration_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    use constants::{OUTER_CONSTANTS};
+
+    // A bot on the other end of a local socket, driven by the test.
+    fn connect(sides: Vec<Side>) -> (ExternalBot, BufReader<TcpStream>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("tcp:{}", listener.local_addr().unwrap());
+        let bot = ExternalBot::connect(&address, sides, Duration::from_millis(200)).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        (bot, BufReader::new(stream.try_clone().unwrap()), stream)
+    }
+
+    fn receive(input: &mut BufReader<TcpStream>) -> serde_json::Value {
+        let mut line = String::new();
+
+        input.read_line(&mut line).unwrap();
+
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn rejects_commands_that_cant_start() {
+        assert!(ExternalBot::connect("", vec![Side::One], Duration::from_millis(10)).is_err());
+        assert!(ExternalBot::connect("popongong-no-such-bot", vec![Side::One], Duration::from_millis(10)).is_err());
+    }
+
+    #[test]
+    fn moves_only_its_own_paddles() {
+        let (mut bot, mut input, mut output) = connect(vec![Side::Two]);
+        let mut nest = Nest::new(&[OUTER_CONSTANTS]);
+
+        assert_eq!(receive(&mut input), json!({ "type": "hello", "sides": ["right"], "timeout_ms": 200 }));
+
+        // A late reply to an earlier tick, a move for someone else's paddle
+        // and a move that doesn't mean anything all get ignored.
+        writeln!(output, "{}", json!({ "tick": 0, "moves": [{ "side": "right", "move": "down" }] })).unwrap();
+        writeln!(output, "{}", json!({ "tick": 1, "moves": [
+            { "side": "left", "move": "down" },
+            { "side": "right", "move": "sideways" },
+        ] })).unwrap();
+        bot.control(&mut nest);
+
+        let tick = receive(&mut input);
+
+        assert_eq!(tick["tick"], 1);
+        assert_eq!(tick["games"][0]["path"], json!([]));
+        assert_eq!(nest.games()[0].bot_actions.get(&Side::One), None);
+        assert_eq!(nest.games()[0].bot_actions.get(&Side::Two), Some(&0));
+
+        writeln!(output, "{}", json!({ "tick": 2, "moves": [{ "side": "right", "move": "up" }] })).unwrap();
+        bot.control(&mut nest);
+
+        assert_eq!(nest.games()[0].bot_actions.get(&Side::Two), Some(&-1));
+        assert_eq!(bot.timeouts, 0);
+    }
+
+    #[test]
+    fn counts_replies_that_dont_arrive_in_time() {
+        let (mut bot, _input, _output) = connect(vec![Side::One]);
+        let mut nest = Nest::new(&[OUTER_CONSTANTS]);
+
+        bot.control(&mut nest);
+
+        assert_eq!(bot.timeouts, 1);
+        assert_eq!(nest.games()[0].bot_actions.get(&Side::One), Some(&0));
+    }
+}
@@ -0,0 +1,253 @@
+use rand::{thread_rng, Rng};
+
+use sdl2::rect::Rect;
+
+use vector::Vector;
+
+use component::{Ball, Component, FRect, Part};
+
+use constants::{Color};
+
+use events::{GameEvent};
+
+// Which visual effects are shown. They are purely cosmetic and can all be
+// turned off for players who find them distracting.
+#[derive(Debug, Clone)]
+pub struct FxSettings {
+    pub trails: bool,
+    pub flashes: bool,
+    pub particles: bool,
+    pub shake: bool,
+}
+
+impl FxSettings {
+    pub fn all(enabled: bool) -> FxSettings {
+        FxSettings { trails: enabled, flashes: enabled, particles: enabled, shake: enabled }
+    }
+
+    pub fn any(&self) -> bool {
+        self.trails || self.flashes || self.particles || self.shake
+    }
+}
+
+const TRAIL_MS: u64 = 150;
+const FLASH_MS: u64 = 120;
+const PARTICLE_MS: u64 = 400;
+
+// Something short lived that shrinks away over `duration_ms`.
+#[derive(Debug, Clone)]
+struct Fade {
+    pos: Vector,
+    velocity: Vector,
+    width: f64,
+    height: f64,
+    duration_ms: u64,
+    remaining_ms: u64,
+    rect: FRect,
+    color: Color,
+}
+
+impl Fade {
+    fn update(&mut self, delta_ms: u64) -> () {
+        self.remaining_ms = self.remaining_ms.saturating_sub(delta_ms);
+        self.pos.add_mut(&Vector {
+            x: self.velocity.x * delta_ms as f64,
+            y: self.velocity.y * delta_ms as f64,
+        });
+    }
+
+    fn components(&mut self, origin: &Vector) -> Vec<Component<'_>> {
+        let left = self.remaining_ms as f64 / self.duration_ms as f64;
+        let width = (self.width * left).max(1.0);
+        let height = (self.height * left).max(1.0);
+
+        self.rect.set_x(self.pos.x - width / 2.0 + origin.x);
+        self.rect.set_y(self.pos.y - height / 2.0 + origin.y);
+        self.rect.set_width(width);
+        self.rect.set_height(height);
+
+        return vec![
+            (&self.rect, &self.color, Part::Plain),
+        ];
+    }
+}
+
+// Visual effects for one game. Everything is driven by the game's events
+// and sized by `scale`, which grows with the size of the field so effects
+// in the outer game are bigger than those in a nested one.
+#[derive(Debug, Clone)]
+pub struct Fx {
+    pub settings: FxSettings,
+    scale: f64,
+    center: Vector,
+    trails: Vec<Fade>,
+    flashes: Vec<Fade>,
+    particles: Vec<Fade>,
+    shake_ms: u64,
+    shake_magnitude: f64,
+}
+
+impl Fx {
+    pub fn new(settings: FxSettings, field_width: i32, field_height: i32) -> Fx {
+        Fx {
+            settings: settings,
+            scale: field_width as f64 / 500.0,
+            center: Vector { x: field_width as f64 / 2.0, y: field_height as f64 / 2.0 },
+            trails: vec![],
+            flashes: vec![],
+            particles: vec![],
+            shake_ms: 0,
+            shake_magnitude: 0.0,
+        }
+    }
+
+    // `paddle` is where the paddle on the event's side was when it happened.
+    pub fn handle(&mut self, event: &GameEvent, paddle: Option<Rect>) -> () {
+        let flash = match *event {
+            GameEvent::PaddleHit { ref pos, level, .. } => {
+                self.burst(pos, 8, Color::Level(level));
+                Some(Color::Border)
+            },
+            GameEvent::Miss { ref pos, .. } => {
+                self.burst(pos, 12, Color::Penalty);
+                self.shake(150, 4.0);
+                Some(Color::Penalty)
+            },
+            GameEvent::Blocked { ref pos, .. } => {
+                self.burst(pos, 8, Color::Bonus);
+                Some(Color::Bonus)
+            },
+            GameEvent::PowerUp { kind, ref pos, .. } => {
+                self.burst(pos, 8, kind.color());
+                Some(kind.color())
+            },
+            GameEvent::BallCollision { ref pos } => {
+                self.burst(pos, 4, Color::Ball);
+                None
+            },
+            GameEvent::Teleport { ref pos } => {
+                self.burst(pos, 6, Color::Portal);
+                None
+            },
+            GameEvent::LevelUp { level } => {
+                let center = self.center.clone();
+
+                self.shake(400, 8.0);
+                self.burst(&center, 30, Color::Level(level));
+                None
+            },
+            GameEvent::Serve { .. } => None,
+        };
+
+        if let (Some(color), Some(paddle)) = (flash, paddle) {
+            self.flash(paddle, color);
+        }
+    }
+
+    pub fn update(&mut self, delta_ms: u64, balls: &[Ball]) -> () {
+        for fade in self.trails.iter_mut()
+            .chain(self.flashes.iter_mut())
+            .chain(self.particles.iter_mut()) {
+
+            fade.update(delta_ms);
+        }
+
+        self.trails.retain(|fade| fade.remaining_ms > 0);
+        self.flashes.retain(|fade| fade.remaining_ms > 0);
+        self.particles.retain(|fade| fade.remaining_ms > 0);
+        self.shake_ms = self.shake_ms.saturating_sub(delta_ms);
+
+        if self.settings.trails {
+            for ball in balls.iter() {
+                self.trails.push(Fade {
+                    pos: ball.center(),
+                    velocity: Vector { x: 0.0, y: 0.0 },
+                    width: ball.width as f64 * 0.6,
+                    height: ball.height as f64 * 0.6,
+                    duration_ms: TRAIL_MS,
+                    remaining_ms: TRAIL_MS,
+                    rect: FRect::new(0.0, 0.0, 1.0, 1.0),
+                    color: ball.color.clone(),
+                });
+            }
+        }
+    }
+
+    // How far to nudge the game this frame while the screen is shaking.
+    pub fn shake_offset(&self) -> Vector {
+        if !self.settings.shake || self.shake_ms == 0 {
+            return Vector { x: 0.0, y: 0.0 };
+        }
+
+        let magnitude = self.shake_magnitude * self.scale;
+
+        Vector {
+            x: thread_rng().gen_range(-magnitude, magnitude),
+            y: thread_rng().gen_range(-magnitude, magnitude),
+        }
+    }
+
+    // Trails, which go under the balls and paddles, and the flashes and
+    // particles drawn on top of everything else.
+    pub fn components(&mut self, origin: &Vector) -> (Vec<Component<'_>>, Vec<Component<'_>>) {
+        let mut background = vec![];
+        let mut foreground = vec![];
+
+        for fade in self.trails.iter_mut() {
+            background.append(&mut fade.components(&origin));
+        }
+        for fade in self.flashes.iter_mut().chain(self.particles.iter_mut()) {
+            foreground.append(&mut fade.components(&origin));
+        }
+
+        return (background, foreground);
+    }
+
+    fn flash(&mut self, paddle: Rect, color: Color) -> () {
+        if !self.settings.flashes { return; }
+
+        let grow = 6.0 * self.scale;
+
+        self.flashes.push(Fade {
+            pos: Vector {
+                x: paddle.center().x() as f64,
+                y: paddle.center().y() as f64,
+            },
+            velocity: Vector { x: 0.0, y: 0.0 },
+            width: paddle.width() as f64 + grow,
+            height: paddle.height() as f64 + grow,
+            duration_ms: FLASH_MS,
+            remaining_ms: FLASH_MS,
+            rect: FRect::new(0.0, 0.0, 1.0, 1.0),
+            color: color,
+        });
+    }
+
+    fn burst(&mut self, pos: &Vector, count: usize, color: Color) -> () {
+        if !self.settings.particles { return; }
+
+        let size = 4.0 * self.scale;
+        let speed = 0.2 * self.scale;
+
+        for _ in 0..count {
+            self.particles.push(Fade {
+                pos: pos.clone(),
+                velocity: Vector {
+                    x: thread_rng().gen_range(-speed, speed),
+                    y: thread_rng().gen_range(-speed, speed),
+                },
+                width: size,
+                height: size,
+                duration_ms: PARTICLE_MS,
+                remaining_ms: PARTICLE_MS,
+                rect: FRect::new(0.0, 0.0, 1.0, 1.0),
+                color: color.clone(),
+            });
+        }
+    }
+
+    fn shake(&mut self, duration_ms: u64, magnitude: f64) -> () {
+        self.shake_ms = self.shake_ms.max(duration_ms);
+        self.shake_magnitude = magnitude;
+    }
+}
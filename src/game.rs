@@ -1,4 +1,5 @@
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng};
+use rand::rngs::{SmallRng};
 
 use sdl2::rect::Rect;
 use sdl2::keyboard::Keycode;
@@ -7,109 +8,582 @@ use std::collections::HashMap;
 
 use vector::Vector;
 
-use component::{Paddle, Ball, Component};
+use component::{Paddle, Ball, Component, FRect, Part, Side};
 
-use constants::{Color, Constants};
+use constants::{Color, Constants, MIN_SERVE_SPEED};
 
-use sounds::{Sounds};
+use coupling::{MAX_SPEED_UP};
 
-#[derive(Debug, PartialEq)]
+use events::{GameEvent};
+
+use fx::{Fx};
+
+use powerup::{PowerUp, PowerUpKind, Effect};
+
+use arena::{Obstacle, ObstacleLayout, Portal};
+
+use text::{Text};
+
+// The keys that move each side's paddle, up or left first, in side order.
+pub static PADDLE_KEYS: [(Keycode, Keycode); 4] = [
+    (Keycode::W, Keycode::S),
+    (Keycode::Up, Keycode::Down),
+    (Keycode::Z, Keycode::X),
+    (Keycode::Comma, Keycode::Period),
+];
+
+// Which way to move a paddle: -1 for up (or left), 0 to stay put and 1 for
+// down (or right).
+pub type Action = i8;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum GameStates {
     Playing,
 //    Finished,
     Paused
 }
 
-#[derive(Debug)]
-pub struct Game<'a> {
-    pub background: Rect,
+#[derive(Debug, Clone)]
+pub struct Game {
+    pub background: FRect,
     pub background_color: Color,
     pub paddle_one: Paddle,
     pub paddle_two: Paddle,
-    pub ball: Ball,
+    // Top and bottom paddles, only in four player mode.
+    pub paddle_three: Option<Paddle>,
+    pub paddle_four: Option<Paddle>,
+    pub balls: Vec<Ball>,
+    pub powerups: Vec<PowerUp>,
+    pub effects: Vec<Effect>,
+    pub obstacles: Vec<Obstacle>,
+    pub portals: Vec<Portal>,
     pub state: GameStates,
     pub color_index: usize,
-    pub sounds: &'a Sounds<'a>,
+    // What happened during the last update, see `GameEvent`.
+    pub events: Vec<GameEvent>,
+    pub fx: Fx,
+    // Paddles played by a bot rather than from the keyboard, and how they
+    // should move on the next update.
+    pub bot_actions: HashMap<Side, Action>,
+    // Draw the debug overlay: hitboxes, ball velocities, the paddle ends
+    // and how far the nearest ball is from each paddle's center.
+    pub hitboxes: bool,
+    overlay: Vec<(FRect, Color)>,
+    overlay_text: Vec<Text>,
     constants: Constants,
+    // Everything random in the game comes from here, so a game started
+    // from the same seed and given the same input plays out the same way.
+    rng: SmallRng,
+    next_ball_id: u32,
+    ball_spawn_timer_ms: u64,
+    powerup_timer_ms: u64,
 }
 
-impl<'a> Game<'a> {
-    pub fn new(constants: Constants, sounds: &'a Sounds) -> Game<'a> {
+impl Game {
+    pub fn new(constants: Constants, seed: u64) -> Game {
+        // Leave room for the top paddle in the corners.
+        let top = if constants.four_players { constants.paddle_width } else { 0 };
+
+        // Paddle lengths and speeds for each side, handicaps included. Like
+        // `paddle_limits`, a paddle can't be longer than its wall once the
+        // corners are taken out.
+        let length = |side: Side| {
+            let wall = if side.is_horizontal() { constants.window_width } else { constants.window_height };
+
+            (constants.paddle_height as f64 * constants.handicaps[side as usize].paddle_length)
+                .max((constants.paddle_segment * 2) as f64)
+                .min((wall - top * 2) as f64) as u32
+        };
+        let speed = |side: Side| constants.max_paddle_speed * constants.handicaps[side as usize].paddle_speed;
+
         let paddle_one = Paddle::new(
-            Vector { x: 0.0, y: 0.0 },
+            Vector { x: 0.0, y: top as f64 },
             constants.paddle_width as u32,
-            constants.paddle_height as u32,
-            constants.max_paddle_speed,
+            length(Side::One),
+            speed(Side::One),
             constants.color_seqence[0].clone(),
+            &constants.meter,
         );
 
         let paddle_two = Paddle::new(
             Vector {
                 x: (constants.window_width - constants.paddle_width) as f64,
-                y: 0.0
+                y: top as f64
             },
             constants.paddle_width as u32,
-            constants.paddle_height as u32,
-            constants.max_paddle_speed,
+            length(Side::Two),
+            speed(Side::Two),
             constants.color_seqence[0].clone(),
+            &constants.meter,
         );
 
-        let ball_x = constants.window_width / 2 - constants.ball_width / 2;
-        let ball_y = constants.window_height / 2 - constants.ball_height / 2;
+        let (paddle_three, paddle_four) = if constants.four_players {
+            let x = (constants.window_width / 2 - constants.paddle_height / 2) as f64;
 
-        let ball_starting_pos = Vector {
-                x: ball_x as f64,
-                y: ball_y as f64,
-            };
+            let paddle_three = Paddle::new_horizontal(
+                Vector { x: x, y: 0.0 },
+                length(Side::Three),
+                constants.paddle_width as u32,
+                speed(Side::Three),
+                constants.color_seqence[0].clone(),
+                &constants.meter,
+            );
 
-        let y_sign_vals = vec![-1.0, 1.0];
-        let y_sign = thread_rng().choose(&y_sign_vals).unwrap();
-        let starting_ball_y_vel = thread_rng().gen_range(0.1, constants.max_ball_speed.y * 0.50) * y_sign;
+            let paddle_four = Paddle::new_horizontal(
+                Vector {
+                    x: x,
+                    y: (constants.window_height - constants.paddle_width) as f64
+                },
+                length(Side::Four),
+                constants.paddle_width as u32,
+                speed(Side::Four),
+                constants.color_seqence[0].clone(),
+                &constants.meter,
+            );
 
-        let x_sign_vals = vec![-1.0, 1.0];
-        let x_sign = thread_rng().choose(&x_sign_vals).unwrap();
-        let starting_ball_x_vel = constants.max_ball_speed.x * x_sign;
+            (Some(paddle_three), Some(paddle_four))
+        } else {
+            (None, None)
+        };
 
-        let ball = Ball::new(
-            ball_starting_pos,
-            constants.ball_width as u32,
-            constants.ball_height as u32,
-            Vector { x: starting_ball_x_vel, y: starting_ball_y_vel },
-            constants.ball_color.clone()
-        );
+        let mut obstacles: Vec<Obstacle> = constants.arena.obstacles.iter()
+            .map(Obstacle::new)
+            .collect();
+
+        // The parts of the side walls outside the goal are drawn, and
+        // bounced off, like any other obstacle.
+        if let Some(goal_height) = constants.arena.goal_height {
+            let post_height = (constants.window_height as f64 - goal_height as f64) / 2.0;
+
+            if post_height > 0.0 {
+                for x in [0.0, (constants.window_width - 5) as f64].iter() {
+                    for y in [0.0, constants.window_height as f64 - post_height].iter() {
+                        obstacles.push(Obstacle::new(&ObstacleLayout {
+                            x: *x,
+                            y: *y,
+                            width: 5,
+                            height: post_height as u32,
+                            to: None,
+                            speed: 0.0,
+                        }));
+                    }
+                }
+            }
+        }
+
+        if constants.practice_wall {
+            obstacles.push(Obstacle::new(&ObstacleLayout {
+                x: (constants.window_width - constants.paddle_width) as f64,
+                y: 0.0,
+                width: constants.paddle_width as u32,
+                height: constants.window_height as u32,
+                to: None,
+                speed: 0.0,
+            }));
+        }
 
-        Game {
-            background: Rect::new(0, 0, constants.window_width as u32, constants.window_height as u32),
+        let portals = constants.arena.portals.iter().map(Portal::new).collect();
+        let fx = Fx::new(constants.effects.clone(), constants.window_width, constants.window_height);
+
+        let mut game = Game {
+            background: FRect::new(0.0, 0.0, constants.window_width as f64, constants.window_height as f64),
             background_color: constants.background_color.clone(),
             paddle_one: paddle_one,
             paddle_two: paddle_two,
-            ball: ball,
+            paddle_three: paddle_three,
+            paddle_four: paddle_four,
+            balls: vec![],
+            powerups: vec![],
+            effects: vec![],
+            obstacles: obstacles,
+            portals: portals,
             state: GameStates::Paused,
             color_index: 0,
+            events: vec![],
+            fx: fx,
+            bot_actions: HashMap::new(),
+            hitboxes: false,
+            overlay: vec![],
+            overlay_text: vec![],
             constants: constants,
-            sounds: &sounds
+            rng: SmallRng::seed_from_u64(seed),
+            next_ball_id: 0,
+            ball_spawn_timer_ms: 0,
+            powerup_timer_ms: 0,
+        };
+
+        game.spawn_ball();
+
+        return game;
+    }
+
+    // Width and height of the field.
+    pub fn size(&self) -> Vector {
+        Vector {
+            x: self.constants.window_width as f64,
+            y: self.constants.window_height as f64,
+        }
+    }
+
+    // A seed for a game nested inside this one.
+    pub fn seed(&mut self) -> u64 {
+        self.rng.gen()
+    }
+
+    pub fn constants(&self) -> &Constants {
+        &self.constants
+    }
+
+    // Switch to new constants in the middle of a match. The field keeps its
+    // size, layout and arena, paddles and balls are resized around their
+    // centers and kept inside the field, and balls in play change speed along
+    // with the top speed. `constants` should already have passed
+    // `Constants::check`.
+    pub fn set_constants(&mut self, constants: Constants) -> () {
+        let old = std::mem::replace(&mut self.constants, constants);
+
+        self.constants.window_width = old.window_width;
+        self.constants.window_height = old.window_height;
+        self.constants.four_players = old.four_players;
+        self.constants.practice_wall = old.practice_wall;
+        self.constants.arena = old.arena.clone();
+
+        let (width, height) = (self.constants.window_width as f64, self.constants.window_height as f64);
+        let thickness = self.constants.paddle_width;
+        let meter = self.constants.meter.clone();
+
+        for side in self.sides() {
+            let speed = self.constants.max_paddle_speed * self.constants.handicaps[side as usize].paddle_speed;
+            let paddle = self.paddle_mut(side).unwrap();
+
+            paddle.set_thickness(thickness as u32);
+            paddle.max_hits = meter.max_hits;
+            paddle.miss_decay = meter.miss_decay;
+            paddle.hits = paddle.hits.min(meter.max_hits);
+
+            match side {
+                Side::One => { paddle.velocity.y = speed; },
+                Side::Two => { paddle.velocity.y = speed; paddle.pos.x = width - thickness as f64; },
+                Side::Three => { paddle.velocity.x = speed; },
+                Side::Four => { paddle.velocity.x = speed; paddle.pos.y = height - thickness as f64; },
+            }
+        }
+
+        self.resize_paddles();
+
+        if self.color_index >= self.constants.color_seqence.len() {
+            self.color_index = 0;
+        }
+
+        let color = self.constants.color_seqence[self.color_index].clone();
+
+        for side in self.sides() {
+            self.paddle_mut(side).unwrap().set_color(&color);
+        }
+
+        // Keep balls clear of the paddles, so they don't start out inside
+        // one.
+        let (ball_width, ball_height) = (self.constants.ball_width as u32, self.constants.ball_height as u32);
+        let speed_x = self.constants.max_ball_speed.x / old.max_ball_speed.x;
+        let speed_y = self.constants.max_ball_speed.y / old.max_ball_speed.y;
+        let left = if self.sides().contains(&Side::One) { thickness as f64 } else { 0.0 };
+        let right = width - if self.sides().contains(&Side::Two) { thickness as f64 } else { 0.0 };
+        let top = if self.constants.four_players { thickness as f64 } else { 0.0 };
+        let bottom = height - top;
+
+        for ball in self.balls.iter_mut() {
+            let center = ball.center();
+
+            ball.width = ball_width;
+            ball.height = ball_height;
+            ball.rect.set_width(ball_width as f64);
+            ball.rect.set_height(ball_height as f64);
+            ball.pos.x = (center.x - ball_width as f64 / 2.0).min(right - ball_width as f64).max(left);
+            ball.pos.y = (center.y - ball_height as f64 / 2.0).min(bottom - ball_height as f64).max(top);
+
+            if speed_x.is_finite() && speed_x > 0.0 { ball.velocity.x = ball.velocity.x * speed_x; }
+            if speed_y.is_finite() && speed_y > 0.0 { ball.velocity.y = ball.velocity.y * speed_y; }
+        }
+    }
+
+    pub fn ball(&self, id: u32) -> Option<&Ball> {
+        self.balls.iter().find(|ball| ball.id == id)
+    }
+
+    // Serve a new ball from the center of the field in a random direction.
+    pub fn spawn_ball(&mut self) -> () {
+        let (ball_starting_pos, ball_starting_vel) = self.serve();
+
+        let ball = Ball::new(
+            self.next_ball_id,
+            ball_starting_pos,
+            self.constants.ball_width as u32,
+            self.constants.ball_height as u32,
+            ball_starting_vel,
+            self.constants.ball_color.clone()
+        );
+
+        self.emit(GameEvent::Serve { ball: ball.id });
+        self.next_ball_id = self.next_ball_id + 1;
+        self.balls.push(ball);
+    }
+
+    // Where a served ball starts and how fast it goes.
+    fn serve(&mut self) -> (Vector, Vector) {
+        let ball_x = self.constants.window_width / 2 - self.constants.ball_width / 2;
+        let ball_y = self.constants.window_height / 2 - self.constants.ball_height / 2;
+
+        let ball_starting_pos = Vector {
+                x: ball_x as f64,
+                y: ball_y as f64,
+            };
+
+        let y_sign_vals = vec![-1.0, 1.0];
+        let y_sign = *self.rng.choose(&y_sign_vals).unwrap();
+        // `Constants::check` keeps the range open, but a top speed that
+        // slips past it serves as fast as it can rather than panicking.
+        let max_serve_speed = self.constants.max_ball_speed.y * 0.50;
+        let serve_speed = if max_serve_speed > MIN_SERVE_SPEED {
+            self.rng.gen_range(MIN_SERVE_SPEED, max_serve_speed)
+        } else {
+            max_serve_speed
+        };
+        let starting_ball_y_vel = serve_speed * y_sign;
+
+        let x_sign_vals = vec![-1.0, 1.0];
+        let x_sign = *self.rng.choose(&x_sign_vals).unwrap();
+        let starting_ball_x_vel = self.constants.max_ball_speed.x * x_sign;
+
+        (ball_starting_pos, Vector { x: starting_ball_x_vel, y: starting_ball_y_vel })
+    }
+
+    // Count a miss against `side` and serve the ball with `id` again from
+    // the center, as if it had gone out.
+    pub fn end_rally(&mut self, id: u32, side: Side) -> () {
+        let (pos, velocity) = self.serve();
+
+        let (center, speed) = match self.balls.iter_mut().find(|ball| ball.id == id) {
+            Some(ball) => {
+                let missed = (ball.center(), ball.speed());
+
+                ball.pos = pos;
+                ball.velocity = velocity;
+                ball.last_hit = None;
+
+                missed
+            },
+            None => return,
+        };
+
+        if let Some(paddle) = self.paddle_mut(side) {
+            paddle.miss();
+        }
+
+        self.emit(GameEvent::Miss { side: side, ball: id, pos: center, speed: speed });
+        self.emit(GameEvent::Serve { ball: id });
+    }
+
+    // Speed up the ball with `id` by `factor`, up to `MAX_SPEED_UP` times
+    // the top speed.
+    pub fn speed_up_ball(&mut self, id: u32, factor: f64) -> () {
+        let max_x = self.constants.max_ball_speed.x * MAX_SPEED_UP;
+        let max_y = self.constants.max_ball_speed.y * MAX_SPEED_UP;
+
+        if let Some(ball) = self.balls.iter_mut().find(|ball| ball.id == id) {
+            ball.velocity.x = (ball.velocity.x * factor).max(-max_x).min(max_x);
+            ball.velocity.y = (ball.velocity.y * factor).max(-max_y).min(max_y);
+        }
+    }
+
+    // Give every ball a random vertical kick of up to `strength` times the
+    // top speed.
+    pub fn jolt_balls(&mut self, strength: f64) -> () {
+        let max_y = self.constants.max_ball_speed.y;
+
+        for ball in self.balls.iter_mut() {
+            let kick = self.rng.gen_range(-strength, strength) * max_y;
+
+            ball.velocity.y = (ball.velocity.y + kick).max(-max_y).min(max_y);
         }
     }
 
+    fn can_spawn_ball(&self) -> bool {
+        if self.balls.len() >= self.constants.max_balls { return false; }
+
+        // Hold the serve until the center is clear, otherwise the new ball
+        // would start out inside one already in play.
+        let spawn_area = Rect::new(
+            self.constants.window_width / 2 - self.constants.ball_width / 2,
+            self.constants.window_height / 2 - self.constants.ball_height / 2,
+            self.constants.ball_width as u32,
+            self.constants.ball_height as u32
+        );
+
+        !self.balls.iter().any(|ball| spawn_area.has_intersection(ball.bounds()))
+    }
+
     pub fn start(&mut self) -> () {
         self.state = GameStates::Playing;
     }
 
-    pub fn components(&mut self, origin: &Vector) -> Vec<Component> {
-        self.background.set_x(origin.x as i32);
-        self.background.set_y(origin.y as i32);
+    pub fn components(&mut self, origin: &Vector) -> Vec<Component<'_>> {
+        self.background.set_x(origin.x);
+        self.background.set_y(origin.y);
+
+        if self.hitboxes {
+            self.build_overlay(origin);
+        } else {
+            self.overlay.clear();
+            self.overlay_text.clear();
+        }
 
         let mut components = vec![
-            (&self.background, &self.background_color)
+            (&self.background, &self.background_color, Part::Field)
         ];
+        let (mut trails, mut fx) = self.fx.components(&origin);
+
+        for obstacle in self.obstacles.iter_mut() {
+            components.append(&mut obstacle.components(&origin));
+        }
+        for portal in self.portals.iter_mut() {
+            components.append(&mut portal.components(&origin));
+        }
+
+        components.append(&mut trails);
 
         components.append(&mut self.paddle_one.components(&origin));
-        components.append(&mut self.paddle_two.components(&origin));
-        components.append(&mut self.ball.components(&origin));
+        if !self.constants.practice_wall {
+            components.append(&mut self.paddle_two.components(&origin));
+        }
+        for paddle in self.paddle_three.iter_mut().chain(self.paddle_four.iter_mut()) {
+            components.append(&mut paddle.components(&origin));
+        }
+        for powerup in self.powerups.iter_mut() {
+            components.append(&mut powerup.components(&origin));
+        }
+        for ball in self.balls.iter_mut() {
+            components.append(&mut ball.components(&origin));
+        }
+
+        let bar_height = (self.constants.powerups.size / 6).max(2);
+        let mut slots = [0, 0];
+        for effect in self.effects.iter_mut() {
+            let slot = match effect.side {
+                Side::One | Side::Three => &mut slots[0],
+                Side::Two | Side::Four => &mut slots[1],
+            };
+            components.append(&mut effect.components(
+                &origin,
+                self.constants.window_width,
+                self.constants.window_height,
+                bar_height,
+                *slot
+            ));
+            *slot = *slot + 1;
+        }
+
+        components.append(&mut fx);
+
+        for (rect, color) in self.overlay.iter() {
+            components.push((rect, color, Part::Plain));
+        }
+        for text in self.overlay_text.iter() {
+            components.append(&mut text.components());
+        }
 
         return components;
     }
 
+    fn build_overlay(&mut self, origin: &Vector) -> () {
+        // How far ahead the velocity lines reach, in ms.
+        const VELOCITY_MS: f64 = 250.0;
+        const DOTS: usize = 10;
+
+        let mut rects = vec![];
+        let mut text = vec![];
+
+        for ball in self.balls.iter() {
+            rects.append(&mut outline(&ball.bounds(), origin, Color::Ball));
+
+            let center = ball.center();
+
+            for i in 1..DOTS + 1 {
+                let along = VELOCITY_MS * i as f64 / DOTS as f64;
+
+                rects.push((FRect::new(
+                    origin.x + center.x + ball.velocity.x * along - 2.0,
+                    origin.y + center.y + ball.velocity.y * along - 2.0,
+                    4.0,
+                    4.0
+                ), Color::SpeedUp));
+            }
+        }
+
+        for obstacle in self.obstacles.iter() {
+            rects.append(&mut outline(&obstacle.bounds(), origin, Color::Bonus));
+        }
+
+        for powerup in self.powerups.iter() {
+            let bounds = Rect::new(
+                powerup.pos.x as i32,
+                powerup.pos.y as i32,
+                powerup.rect.width() as u32,
+                powerup.rect.height() as u32
+            );
+
+            rects.append(&mut outline(&bounds, origin, Color::Bonus));
+        }
+
+        for side in self.sides() {
+            let paddle = self.paddle(side).unwrap();
+            let center = paddle.center();
+            let length = paddle.length() as f64;
+            let extremity = (self.constants.paddle_segment * 2 * paddle.length() as i32 /
+                self.constants.paddle_height) as f64;
+
+            rects.append(&mut outline(&paddle.bounds(), origin, Color::Bonus));
+
+            // Balls hitting further than `extremity` from the center come
+            // off the ends.
+            if extremity < length / 2.0 {
+                let end = length / 2.0 - extremity;
+
+                for start in [-length / 2.0, extremity].iter() {
+                    let rect = if paddle.horizontal {
+                        FRect::new(origin.x + center.x + start, origin.y + paddle.pos.y, end, paddle.height as f64)
+                    } else {
+                        FRect::new(origin.x + paddle.pos.x, origin.y + center.y + start, paddle.width as f64, end)
+                    };
+
+                    rects.push((rect, Color::Penalty));
+                }
+            }
+
+            // The `collision_distance` of the nearest ball.
+            let nearest = self.balls.iter()
+                .map(|ball| ball.center().subtract(&center))
+                .min_by(|a, b| (a.x * a.x + a.y * a.y).partial_cmp(&(b.x * b.x + b.y * b.y)).unwrap());
+
+            if let Some(distance) = nearest {
+                let distance = if paddle.horizontal { distance.x } else { distance.y };
+                let lines = vec![format!("{:.0}/{:.0}", distance.abs(), extremity)];
+                let size = Text::size(&lines, 2.0);
+                let mut pos = match side {
+                    Side::One => Vector { x: paddle.pos.x + paddle.width as f64 + 8.0, y: center.y - size.y / 2.0 },
+                    Side::Two => Vector { x: paddle.pos.x - size.x - 8.0, y: center.y - size.y / 2.0 },
+                    Side::Three => Vector { x: center.x - size.x / 2.0, y: paddle.pos.y + paddle.height as f64 + 8.0 },
+                    Side::Four => Vector { x: center.x - size.x / 2.0, y: paddle.pos.y - size.y - 8.0 },
+                };
+
+                pos.add_mut(origin);
+                text.push(Text::new(&lines, &pos, 2.0, Color::Bonus));
+            }
+        }
+
+        self.overlay = rects;
+        self.overlay_text = text;
+    }
+
     pub fn play_pause(&mut self) -> () {
         if self.state == GameStates::Paused {
             self.state = GameStates::Playing;
@@ -119,21 +593,62 @@ impl<'a> Game<'a> {
     }
 
     pub fn next_color(&mut self) -> () {
-        if self.color_index < 2 {
+        if self.color_index < self.constants.color_seqence.len() - 1 {
             self.color_index = self.color_index + 1;
         } else {
             self.color_index = 0;
         }
 
-        self.paddle_one.set_color(&self.constants.color_seqence[self.color_index]);
-        self.paddle_two.set_color(&self.constants.color_seqence[self.color_index]);
+        let color = self.constants.color_seqence[self.color_index].clone();
+
+        for side in self.sides() {
+            let paddle = self.paddle_mut(side).unwrap();
 
-        self.paddle_one.reset_hits();
-        self.paddle_two.reset_hits();
+            paddle.set_color(&color);
+            paddle.reset_hits();
+        }
+
+        if self.constants.spawn_ball_on_level_up && self.can_spawn_ball() {
+            self.spawn_ball();
+        }
+
+        let level = self.color_index;
+        self.emit(GameEvent::LevelUp { level: level });
     }
 
-    pub fn hits (&self) -> u8 {
-        return self.paddle_one.hits + self.paddle_two.hits;
+    // Summed wider than a paddle's own count, four full meters don't fit in
+    // a u8.
+    pub fn hits (&self) -> u32 {
+        return self.sides().iter().map(|side| self.paddle(*side).unwrap().hits as u32).sum();
+    }
+
+    // Hits when every paddle's meter is full.
+    pub fn max_hits(&self) -> u32 {
+        self.sides().iter().map(|side| self.paddle(*side).unwrap().max_hits as u32).sum()
+    }
+
+    pub fn level_up_ready(&self) -> bool {
+        self.hits() >= self.constants.meter.level_up_hits.map(|hits| hits as u32).unwrap_or(self.max_hits())
+    }
+
+    pub fn levels_up_together(&self) -> bool {
+        self.constants.meter.level_up_together
+    }
+
+    // The sides that have a paddle in this game.
+    pub fn sides(&self) -> Vec<Side> {
+        let mut sides = if self.constants.four_players {
+            vec![Side::One, Side::Two, Side::Three, Side::Four]
+        } else {
+            vec![Side::One, Side::Two]
+        };
+
+        // The wall takes the place of the right paddle.
+        if self.constants.practice_wall {
+            sides.retain(|side| *side != Side::Two);
+        }
+
+        return sides;
     }
 
     pub fn update(
@@ -141,141 +656,800 @@ impl<'a> Game<'a> {
         keys_pressed: &HashMap<&Keycode, bool>,
         delta_ms: u64
     ) -> () {
+        self.events.clear();
+
         if self.state != GameStates::Paused {
+            // Keys arrive already translated by `Bindings` into the
+            // `PADDLE_KEYS` for each side.
             for (key, _) in keys_pressed {
-                match key {
-                    Keycode::Up => {
-                        self.paddle_two.up(delta_ms, 0.0);
-                    },
-                    Keycode::Down => {
-                        self.paddle_two.down(
-                            delta_ms,
-                            (self.constants.window_height) as f64
-                        );
-                    },
-                    Keycode::W => {
-                        self.paddle_one.up(delta_ms, 0.0);
-                    },
-                    Keycode::S => {
-                        self.paddle_one.down(
-                            delta_ms,
-                            self.constants.window_height as f64
-                        );
-                    },
-                    _ => {}
+                let (side, up) = match PADDLE_KEYS.iter().position(|(up, down)| up == *key || down == *key) {
+                    Some(i) => (Side::all()[i], PADDLE_KEYS[i].0 == **key),
+                    None => continue,
+                };
+
+                // The keyboard can't move a bot's paddle.
+                if !self.bot_actions.contains_key(&side) {
+                    self.move_paddle(side, up, delta_ms);
                 }
             }
 
-            self.ball.update(delta_ms);
+            let bot_actions: Vec<(Side, Action)> = self.bot_actions.iter()
+                .map(|(side, action)| (*side, *action))
+                .collect();
+
+            for (side, action) in bot_actions {
+                if action != 0 {
+                    self.move_paddle(side, action < 0, delta_ms);
+                }
+            }
 
-            // Edge collisions
-            if self.ball_collides_with_top() || self.ball_collides_with_bottom() {
-                self.ball.flip_y();
+            for obstacle in self.obstacles.iter_mut() {
+                obstacle.update(delta_ms);
             }
 
-            if self.ball_collides_with_left() {
-                self.ball.flip_x();
-                self.paddle_one.miss();
-                self.sounds.play("left_right_edge_collision".to_string());
+            let mut balls = std::mem::take(&mut self.balls);
+
+            for ball in balls.iter_mut() {
+                let scale = self.ball_speed_scale() * self.ball_handicap(ball);
+
+                self.update_ball(ball, (delta_ms as f64 * scale).round() as u64);
             }
 
-            if self.ball_collides_with_right() {
-                self.ball.flip_x();
-                self.paddle_two.miss();
-                self.sounds.play("left_right_edge_collision".to_string());
+            if self.constants.ball_collisions {
+                self.ball_to_ball_collisions(&mut balls);
             }
 
-            if self.ball_collides_with_paddle_one() {
-                let collision_distance = self.ball.distance_to(&self.paddle_one).y.abs();
-                let new_velocity = self.vel_modifier(collision_distance);
+            self.balls = balls;
+
+            if self.constants.ball_spawn_interval_ms > 0 {
+                self.ball_spawn_timer_ms = self.ball_spawn_timer_ms + delta_ms;
 
-                if self.ball_collides_with_paddle_extremity(collision_distance) &&
-                    self.ball_moves_into_bottom_half(&self.paddle_one) ||
-                    self.ball_moves_into_top_half(&self.paddle_one) {
+                if self.ball_spawn_timer_ms >= self.constants.ball_spawn_interval_ms &&
+                    self.can_spawn_ball() {
 
-                    self.ball.flip_y();
+                    self.ball_spawn_timer_ms = 0;
+                    self.spawn_ball();
                 }
+            }
 
-                self.ball.set_velocity_y_magnitude(new_velocity);
-                self.ball.flip_x();
-                self.paddle_one.hit();
-                self.paddle_collision_sound(&self.paddle_one);
+            self.update_powerups(delta_ms);
+            self.fx.update(delta_ms, &self.balls);
+        }
+    }
+
+    pub fn paddle(&self, side: Side) -> Option<&Paddle> {
+        match side {
+            Side::One => Some(&self.paddle_one),
+            Side::Two if self.constants.practice_wall => None,
+            Side::Two => Some(&self.paddle_two),
+            Side::Three => self.paddle_three.as_ref(),
+            Side::Four => self.paddle_four.as_ref(),
+        }
+    }
+
+    pub fn paddle_mut(&mut self, side: Side) -> Option<&mut Paddle> {
+        match side {
+            Side::One => Some(&mut self.paddle_one),
+            Side::Two if self.constants.practice_wall => None,
+            Side::Two => Some(&mut self.paddle_two),
+            Side::Three => self.paddle_three.as_mut(),
+            Side::Four => self.paddle_four.as_mut(),
+        }
+    }
+
+    // How far a paddle on `side` can travel, as (start, end) along its
+    // length. In four player mode the paddles stay out of each other's
+    // corners.
+    fn paddle_limits(&self, side: Side) -> (f64, f64) {
+        let corner = if self.constants.four_players {
+            self.constants.paddle_width as f64
+        } else {
+            0.0
+        };
+
+        if side.is_horizontal() {
+            (corner, self.constants.window_width as f64 - corner)
+        } else {
+            (corner, self.constants.window_height as f64 - corner)
+        }
+    }
+
+    // Move a paddle up, or left for horizontal paddles, when `up` is true and
+    // down or right otherwise.
+    fn move_paddle(&mut self, side: Side, up: bool, delta_ms: u64) -> () {
+        let up = up != self.effect_active(PowerUpKind::ReverseControls, side);
+        let (start, end) = self.paddle_limits(side);
+        let paddle = match self.paddle_mut(side) {
+            Some(paddle) => paddle,
+            None => return,
+        };
+
+        match (paddle.horizontal, up) {
+            (false, true) => paddle.up(delta_ms, start),
+            (false, false) => paddle.down(delta_ms, end),
+            (true, true) => paddle.left(delta_ms, start),
+            (true, false) => paddle.right(delta_ms, end),
+        }
+    }
+
+    pub fn effect_active(&self, kind: PowerUpKind, side: Side) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind && effect.side == side)
+    }
+
+    fn ball_speed_scale(&self) -> f64 {
+        self.effects.iter().fold(1.0, |scale, effect| {
+            match effect.kind {
+                PowerUpKind::SpeedUp => scale * 1.5,
+                PowerUpKind::SlowDown => scale * 0.6,
+                _ => scale,
             }
+        })
+    }
 
-            if self.ball_collides_with_paddle_two() {
-                let collision_distance = self.ball.distance_to(&self.paddle_two).y.abs();
-                let new_velocity = self.vel_modifier(collision_distance);
+    // The ball speed handicap of the side whose wall the ball is closest to.
+    fn ball_handicap(&self, ball: &Ball) -> f64 {
+        let center = ball.center();
+        let size = self.size();
 
-                if self.ball_collides_with_paddle_extremity(collision_distance) &&
-                    self.ball_moves_into_bottom_half(&self.paddle_two) ||
-                    self.ball_moves_into_top_half(&self.paddle_two) {
+        let nearest = self.sides().into_iter().min_by(|a, b| {
+            let distance = |side: &Side| match *side {
+                Side::One => center.x,
+                Side::Two => size.x - center.x,
+                Side::Three => center.y,
+                Side::Four => size.y - center.y,
+            };
 
-                    self.ball.flip_y();
-                }
+            distance(a).partial_cmp(&distance(b)).unwrap()
+        });
+
+        match nearest {
+            Some(side) => self.constants.handicaps[side as usize].ball_speed,
+            None => 1.0,
+        }
+    }
+
+    // Move the paddle on `side` by `distance` along its wall, without
+    // leaving the field.
+    pub fn nudge_paddle(&mut self, side: Side, distance: f64) -> () {
+        let (start, end) = self.paddle_limits(side);
+
+        if let Some(paddle) = self.paddle_mut(side) {
+            // A paddle as long as its wall has nowhere to go.
+            let last = start.max(end - paddle.length() as f64);
+
+            if paddle.horizontal {
+                paddle.pos.x = (paddle.pos.x + distance).clamp(start, last);
+            } else {
+                paddle.pos.y = (paddle.pos.y + distance).clamp(start, last);
+            }
+        }
+    }
 
-                self.ball.set_velocity_y_magnitude(new_velocity);
-                self.ball.flip_x();
-                self.paddle_two.hit();
-                self.paddle_collision_sound(&self.paddle_two);
+    fn update_powerups(&mut self, delta_ms: u64) -> () {
+        let settings = &self.constants.powerups;
+
+        if settings.spawn_interval_ms > 0 {
+            self.powerup_timer_ms = self.powerup_timer_ms + delta_ms;
+
+            if self.powerup_timer_ms >= settings.spawn_interval_ms {
+                self.powerup_timer_ms = 0;
+
+                if self.powerups.len() < settings.max_on_field {
+                    if let Some(powerup) = PowerUp::random(
+                        &mut self.rng,
+                        settings,
+                        self.constants.window_width,
+                        self.constants.window_height
+                    ) {
+                        self.powerups.push(powerup);
+                    }
+                }
             }
         }
+
+        // A power up is collected when a ball passes through it and is
+        // credited to the paddle that last returned that ball.
+        let mut collected = vec![];
+
+        for ball in self.balls.iter() {
+            let collector = match ball.last_hit {
+                Some(side) => side,
+                None => continue,
+            };
+            let ball_rect = ball.bounds();
+
+            self.powerups.retain(|powerup| {
+                let hit = Rect::new(
+                    powerup.pos.x as i32,
+                    powerup.pos.y as i32,
+                    powerup.rect.width() as u32,
+                    powerup.rect.height() as u32
+                ).has_intersection(ball_rect);
+
+                if hit { collected.push((powerup.kind, collector, powerup.center())); }
+
+                !hit
+            });
+        }
+
+        for (kind, collector, pos) in collected {
+            self.activate(kind, collector);
+            self.emit(GameEvent::PowerUp { kind: kind, side: collector, pos: pos });
+        }
+
+        for effect in self.effects.iter_mut() {
+            effect.update(delta_ms);
+        }
+
+        let (expired, active) = std::mem::take(&mut self.effects)
+            .into_iter()
+            .partition(|effect| effect.expired());
+        self.effects = active;
+
+        for effect in expired {
+            self.deactivate(effect);
+        }
     }
 
-    fn paddle_collision_sound(&self, paddle: &Paddle) -> () {
-        self.sounds.play(
-            format!("paddle_{}_{}", self.color_index + 1, paddle.hits)
+    fn activate(&mut self, kind: PowerUpKind, collector: Side) -> () {
+        let mut effect = Effect::new(
+            kind,
+            kind.target(collector),
+            self.constants.powerups.duration_ms
         );
+
+        // Shields don't stack, collecting another one just tops up the timer.
+        if kind == PowerUpKind::Shield {
+            self.effects.retain(|effect| !(effect.kind == kind && effect.side == collector));
+        }
+
+        if kind == PowerUpKind::ExtraBall {
+            self.spawn_ball();
+            effect.ball_id = self.balls.last().map(|ball| ball.id);
+        }
+
+        self.effects.push(effect);
+
+        if kind == PowerUpKind::Grow || kind == PowerUpKind::Shrink {
+            self.resize_paddles();
+        }
+    }
+
+    fn deactivate(&mut self, effect: Effect) -> () {
+        match effect.kind {
+            PowerUpKind::ExtraBall => {
+                if let Some(id) = effect.ball_id {
+                    self.balls.retain(|ball| ball.id != id);
+                }
+            },
+            PowerUpKind::Grow | PowerUpKind::Shrink => {
+                self.resize_paddles();
+            },
+            _ => {}
+        }
+    }
+
+    fn resize_paddles(&mut self) -> () {
+        let min_length = (self.constants.paddle_segment * 2) as f64;
+
+        for side in self.sides().iter() {
+            let (start, end) = self.paddle_limits(*side);
+            let scale = self.effects.iter()
+                .filter(|effect| effect.side == *side)
+                .fold(1.0, |scale, effect| {
+                    match effect.kind {
+                        PowerUpKind::Grow => scale * 1.5,
+                        PowerUpKind::Shrink => scale * 0.6,
+                        _ => scale,
+                    }
+                });
+
+            let length = (self.constants.paddle_height as f64 * scale * self.constants.handicaps[*side as usize].paddle_length)
+                .max(min_length)
+                .min(end - start);
+            let paddle = self.paddle_mut(*side).unwrap();
+
+            paddle.set_length(length as u32);
+
+            // Keep the resized paddle inside the field.
+            if paddle.horizontal {
+                paddle.pos.x = paddle.pos.x.max(start).min(end - length);
+            } else {
+                paddle.pos.y = paddle.pos.y.max(start).min(end - length);
+            }
+        }
+    }
+
+    // Use up a shield on `side` if there is one, returns false if the wall
+    // was unprotected.
+    fn use_shield(&mut self, side: Side) -> bool {
+        let count = self.effects.len();
+
+        self.effects.retain(|effect| !(effect.kind == PowerUpKind::Shield && effect.side == side));
+
+        self.effects.len() != count
+    }
+
+    fn update_ball(&mut self, ball: &mut Ball, delta_ms: u64) -> () {
+        ball.update(delta_ms);
+
+        // Edge collisions
+        if self.ball_collides_with_top(ball) {
+            ball.flip_y();
+
+            if self.constants.four_players {
+                self.goal(Side::Three, ball);
+            }
+        }
+
+        if self.ball_collides_with_bottom(ball) {
+            ball.flip_y();
+
+            if self.constants.four_players {
+                self.goal(Side::Four, ball);
+            }
+        }
+
+        if self.ball_collides_with_left(ball) {
+            ball.flip_x();
+
+            if self.ball_in_goal(ball) {
+                self.goal(Side::One, ball);
+            }
+        }
+
+        if self.ball_collides_with_right(ball) {
+            ball.flip_x();
+
+            if self.ball_in_goal(ball) && !self.constants.practice_wall {
+                self.goal(Side::Two, ball);
+            }
+        }
+
+        for obstacle in self.obstacles.iter() {
+            ball.reflect_off(&obstacle.bounds());
+        }
+
+        self.ball_through_portals(ball);
+
+        if self.ball_collides_with_paddle_one(ball) {
+            let collision_distance = ball.distance_to(&self.paddle_one).y.abs();
+            let new_velocity = self.vel_modifier(collision_distance, &self.paddle_one, self.constants.max_ball_speed.y);
+
+            if self.ball_flips_off_paddle_end(collision_distance, ball, &self.paddle_one) {
+
+                ball.flip_y();
+            }
+
+            ball.set_velocity_y_magnitude(new_velocity);
+            ball.flip_x();
+            ball.last_hit = Some(Side::One);
+            self.paddle_hit(ball, Side::One);
+        }
+
+        if self.ball_collides_with_paddle_three(ball) {
+            self.horizontal_paddle_hit(ball, Side::Three);
+        }
+
+        if self.ball_collides_with_paddle_four(ball) {
+            self.horizontal_paddle_hit(ball, Side::Four);
+        }
+
+        if self.ball_collides_with_paddle_two(ball) {
+            let collision_distance = ball.distance_to(&self.paddle_two).y.abs();
+            let new_velocity = self.vel_modifier(collision_distance, &self.paddle_two, self.constants.max_ball_speed.y);
+
+            if self.ball_flips_off_paddle_end(collision_distance, ball, &self.paddle_two) {
+
+                ball.flip_y();
+            }
+
+            ball.set_velocity_y_magnitude(new_velocity);
+            ball.flip_x();
+            ball.last_hit = Some(Side::Two);
+            self.paddle_hit(ball, Side::Two);
+        }
     }
 
-    fn ball_collides_with_paddle_one(&self) -> bool {
-        self.ball.is_moving_left() &&
-            self.ball.left_edge() <= self.paddle_one.right_edge() &&
-            self.ball_within_paddle_collision_range(&self.paddle_one)
+    // The ball got past the paddle on `side`.
+    fn goal(&mut self, side: Side, ball: &Ball) -> () {
+        if self.use_shield(side) {
+            self.emit(GameEvent::Blocked { side: side, pos: ball.center() });
+        } else {
+            if let Some(paddle) = self.paddle_mut(side) {
+                paddle.miss();
+            }
+            self.emit(GameEvent::Miss { side: side, ball: ball.id, pos: ball.center(), speed: ball.speed() });
+        }
     }
 
-    fn ball_collides_with_paddle_two(&self) -> bool {
-        self.ball.is_moving_right() &&
-            self.ball.right_edge() >= self.constants.window_width - self.paddle_two.width as i32 &&
-            self.ball_within_paddle_collision_range(&self.paddle_two)
+    fn horizontal_paddle_hit(&mut self, ball: &mut Ball, side: Side) -> () {
+        let (new_velocity, flip) = {
+            let paddle = self.paddle(side).unwrap();
+            let collision_distance = ball.center().subtract(&paddle.center()).x.abs();
+
+            (
+                self.vel_modifier(collision_distance, paddle, self.constants.max_ball_speed.x),
+                self.ball_flips_off_paddle_end(collision_distance, ball, paddle),
+            )
+        };
+
+        if flip {
+            ball.flip_x();
+        }
+
+        ball.set_velocity_x_magnitude(new_velocity);
+        ball.flip_y();
+        self.paddle_hit(ball, side);
+    }
+
+    fn paddle_hit(&mut self, ball: &Ball, side: Side) -> () {
+        let (hits, max_hits) = {
+            let paddle = self.paddle_mut(side).unwrap();
+            paddle.hit();
+
+            (paddle.hits, paddle.max_hits)
+        };
+
+        let level = self.color_index;
+        self.emit(GameEvent::PaddleHit {
+            side: side,
+            ball: ball.id,
+            pos: ball.center(),
+            speed: ball.speed(),
+            hits: hits,
+            max_hits: max_hits,
+            level: level,
+        });
     }
 
-    fn ball_within_paddle_collision_range(&self, paddle: &Paddle) -> bool {
-        self.ball.bottom_edge() >= paddle.top_edge() &&
-            self.ball.top_edge() <= paddle.bottom_edge()
+    // Record an event and let the effects react to it.
+    fn emit(&mut self, event: GameEvent) -> () {
+        let paddle = match event {
+            GameEvent::PaddleHit { side, .. } |
+            GameEvent::Miss { side, .. } |
+            GameEvent::Blocked { side, .. } |
+            GameEvent::PowerUp { side, .. } => self.paddle(side).map(|paddle| paddle.bounds()),
+            _ => None,
+        };
+
+        self.fx.handle(&event, paddle);
+        self.events.push(event);
     }
 
-    fn ball_collides_with_top(&self) -> bool {
-        self.ball.is_moving_up() && self.ball.top_edge() <= 0
+    fn ball_through_portals(&mut self, ball: &mut Ball) -> () {
+        let exit = self.portals.iter()
+            .filter_map(|portal| portal.exit_for(ball.bounds()))
+            .next();
+
+        match exit {
+            Some(exit) => {
+                if !ball.teleported {
+                    ball.center_on(&exit);
+                    ball.teleported = true;
+                    self.emit(GameEvent::Teleport { pos: ball.center() });
+                }
+            },
+            None => ball.teleported = false,
+        }
     }
 
-    fn ball_collides_with_bottom(&self) -> bool {
-        self.ball.is_moving_down() && self.ball.bottom_edge() >= self.constants.window_height
+    fn ball_in_goal(&self, ball: &Ball) -> bool {
+        match self.constants.arena.goal_height {
+            Some(goal_height) => {
+                let center = ball.center().y;
+                let goal_top = (self.constants.window_height as f64 - goal_height as f64) / 2.0;
+
+                center >= goal_top && center <= goal_top + goal_height as f64
+            },
+            None => true,
+        }
     }
 
-    fn ball_collides_with_left(&self) -> bool {
-        self.ball.is_moving_left() && self.ball.left_edge() <= 0
+    fn ball_to_ball_collisions(&mut self, balls: &mut [Ball]) -> () {
+        for i in 0..balls.len() {
+            let (head, tail) = balls.split_at_mut(i + 1);
+            let ball = &mut head[i];
+
+            for other in tail.iter_mut() {
+                if ball.overlaps(other) && ball.bounce_off(other) {
+                    self.emit(GameEvent::BallCollision { pos: ball.center() });
+                }
+            }
+        }
     }
 
-    fn ball_collides_with_right(&self) -> bool {
-        self.ball.is_moving_right() && self.ball.right_edge() >= self.constants.window_width
+    fn ball_collides_with_paddle_one(&self, ball: &Ball) -> bool {
+        ball.is_moving_left() &&
+            ball.left_edge() <= self.paddle_one.right_edge() &&
+            self.ball_within_paddle_collision_range(ball, &self.paddle_one)
     }
 
+    fn ball_collides_with_paddle_two(&self, ball: &Ball) -> bool {
+        !self.constants.practice_wall &&
+            ball.is_moving_right() &&
+            ball.right_edge() >= self.constants.window_width - self.paddle_two.width as i32 &&
+            self.ball_within_paddle_collision_range(ball, &self.paddle_two)
+    }
+
+    fn ball_collides_with_paddle_three(&self, ball: &Ball) -> bool {
+        match self.paddle_three {
+            Some(ref paddle) => {
+                ball.is_moving_up() &&
+                    ball.top_edge() <= paddle.bottom_edge() &&
+                    ball.bottom_edge() >= paddle.top_edge() &&
+                    self.ball_within_horizontal_paddle_collision_range(ball, paddle)
+            },
+            None => false,
+        }
+    }
+
+    fn ball_collides_with_paddle_four(&self, ball: &Ball) -> bool {
+        match self.paddle_four {
+            Some(ref paddle) => {
+                ball.is_moving_down() &&
+                    ball.bottom_edge() >= paddle.top_edge() &&
+                    ball.top_edge() <= paddle.bottom_edge() &&
+                    self.ball_within_horizontal_paddle_collision_range(ball, paddle)
+            },
+            None => false,
+        }
+    }
+
+    fn ball_within_horizontal_paddle_collision_range(&self, ball: &Ball, paddle: &Paddle) -> bool {
+        ball.right_edge() >= paddle.left_edge() &&
+            ball.left_edge() <= paddle.right_edge()
+    }
+
+    fn ball_within_paddle_collision_range(&self, ball: &Ball, paddle: &Paddle) -> bool {
+        ball.bottom_edge() >= paddle.top_edge() &&
+            ball.top_edge() <= paddle.bottom_edge()
+    }
+
+    fn ball_collides_with_top(&self, ball: &Ball) -> bool {
+        ball.is_moving_up() && ball.top_edge() <= 0
+    }
+
+    fn ball_collides_with_bottom(&self, ball: &Ball) -> bool {
+        ball.is_moving_down() && ball.bottom_edge() >= self.constants.window_height
+    }
+
+    fn ball_collides_with_left(&self, ball: &Ball) -> bool {
+        ball.is_moving_left() && ball.left_edge() <= 0
+    }
+
+    fn ball_collides_with_right(&self, ball: &Ball) -> bool {
+        ball.is_moving_right() && ball.right_edge() >= self.constants.window_width
+    }
+
+    // The ball's speed along the paddle after hitting it `distance` from
+    // the center, `max_speed` being the top speed in that direction.
+    //
     // TODO: A nice enhancment here would be to factor in paddle velocity. If
     // the paddle is stationary don't apply any modification.
-    fn vel_modifier(&self, distance: f64) -> f64 {
-        distance / self.constants.paddle_height as f64 / 2.0 * self.constants.max_ball_speed.y
+    fn vel_modifier(&self, distance: f64, paddle: &Paddle, max_speed: f64) -> f64 {
+        distance / paddle.length() as f64 / 2.0 * max_speed
+    }
+
+    // Whether a ball that hit the paddle on `side` with its center at `pos`
+    // came off one of the paddle's ends.
+    pub fn hit_paddle_end(&self, side: Side, pos: &Vector) -> bool {
+        match self.paddle(side) {
+            Some(paddle) => {
+                let distance = pos.subtract(&paddle.center());
+                let distance = if paddle.horizontal { distance.x } else { distance.y };
+
+                self.ball_collides_with_paddle_extremity(distance.abs(), paddle)
+            },
+            None => false,
+        }
     }
 
-    fn ball_collides_with_paddle_extremity(&self, distance: f64) -> bool {
-        // Edge 2/6th of the paddle
-        distance as i32 > (self.constants.paddle_segment * 2)
+    fn ball_collides_with_paddle_extremity(&self, distance: f64, paddle: &Paddle) -> bool {
+        // Edge 2/6th of the paddle, scaled with the paddle if it was resized
+        let extremity = self.constants.paddle_segment * 2 * paddle.length() as i32 /
+            self.constants.paddle_height;
+
+        distance as i32 > extremity
+    }
+
+    // A ball that hits one of a paddle's ends while heading back across its
+    // center goes back the way it came along the paddle.
+    fn ball_flips_off_paddle_end(&self, distance: f64, ball: &Ball, paddle: &Paddle) -> bool {
+        let crossing = if paddle.horizontal {
+            self.ball_moves_into_right_half(ball, paddle) || self.ball_moves_into_left_half(ball, paddle)
+        } else {
+            self.ball_moves_into_bottom_half(ball, paddle) || self.ball_moves_into_top_half(ball, paddle)
+        };
+
+        self.ball_collides_with_paddle_extremity(distance, paddle) && crossing
+    }
+
+    fn ball_moves_into_bottom_half(&self, ball: &Ball, paddle: &Paddle) -> bool {
+        ball.is_moving_up() && ball.top_edge() > paddle.center().y as i32
+    }
+
+    fn ball_moves_into_top_half(&self, ball: &Ball, paddle: &Paddle) -> bool {
+        ball.is_moving_down() && ball.bottom_edge() < paddle.center().y as i32
+    }
+
+    fn ball_moves_into_right_half(&self, ball: &Ball, paddle: &Paddle) -> bool {
+        ball.is_moving_left() && ball.left_edge() > paddle.center().x as i32
+    }
+
+    fn ball_moves_into_left_half(&self, ball: &Ball, paddle: &Paddle) -> bool {
+        ball.is_moving_right() && ball.right_edge() < paddle.center().x as i32
     }
+}
+
+// The edges of `rect` as thin rects, moved by `origin`.
+fn outline(rect: &Rect, origin: &Vector, color: Color) -> Vec<(FRect, Color)> {
+    const LINE: f64 = 2.0;
+
+    let x = origin.x + rect.x() as f64;
+    let y = origin.y + rect.y() as f64;
+    let width = rect.width() as f64;
+    let height = rect.height() as f64;
+
+    vec![
+        (FRect::new(x, y, width, LINE), color.clone()),
+        (FRect::new(x, y + height - LINE, width, LINE), color.clone()),
+        (FRect::new(x, y, LINE, height), color.clone()),
+        (FRect::new(x + width - LINE, y, LINE, height), color),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use constants::{INNER_CONSTANTS, OUTER_CONSTANTS};
 
-    fn ball_moves_into_bottom_half(&self, paddle: &Paddle) -> bool {
-        self.ball.is_moving_up() && self.ball.top_edge() > paddle.center().y as i32
+    fn four_players() -> Constants {
+        let mut constants = OUTER_CONSTANTS;
+
+        constants.four_players = true;
+
+        return constants;
     }
 
-    fn ball_moves_into_top_half(&self, paddle: &Paddle) -> bool {
-        self.ball.is_moving_down() && self.ball.bottom_edge() < paddle.center().y as i32
+    #[test]
+    fn long_handicapped_paddles_fit_between_the_corners() {
+        let mut constants = four_players();
+
+        for handicap in constants.handicaps.iter_mut() {
+            handicap.paddle_length = 10.0;
+        }
+
+        let mut game = Game::new(constants, 1);
+
+        for side in game.sides() {
+            let (start, end) = game.paddle_limits(side);
+
+            for distance in [-1000.0, 1000.0, 0.0].iter() {
+                game.nudge_paddle(side, *distance);
+
+                let paddle = game.paddle(side).unwrap();
+                let pos = if paddle.horizontal { paddle.pos.x } else { paddle.pos.y };
+
+                assert!(pos >= start && pos + paddle.length() as f64 <= end, "{:?} at {}", side, pos);
+            }
+        }
+    }
+
+    #[test]
+    fn serves_even_when_the_top_speed_is_too_slow() {
+        for speed in [0.3, 0.2, 0.1, 0.0].iter() {
+            let mut constants = OUTER_CONSTANTS;
+
+            constants.max_ball_speed.y = *speed;
+
+            let mut game = Game::new(constants, 1);
+
+            game.spawn_ball();
+
+            let velocity = game.balls.last().unwrap().velocity.y.abs();
+
+            assert!(velocity <= speed * 0.5, "served at {} with a top speed of {}", velocity, speed);
+        }
+    }
+
+    #[test]
+    fn paddle_keys_move_their_own_paddle() {
+        for (i, side) in Side::all().iter().enumerate() {
+            for (key, way) in [(&PADDLE_KEYS[i].0, -1.0), (&PADDLE_KEYS[i].1, 1.0)].iter() {
+                let mut game = Game::new(four_players(), 1);
+
+                // Vertical paddles start against the top corner.
+                for side in Side::all().iter() {
+                    game.nudge_paddle(*side, 100.0);
+                }
+
+                let before: Vec<Vector> = Side::all().iter().map(|side| game.paddle(*side).unwrap().pos.clone()).collect();
+                let mut keys_pressed = HashMap::new();
+
+                game.start();
+                keys_pressed.insert(*key, true);
+                game.update(&keys_pressed, 100);
+
+                for (other, before) in Side::all().iter().zip(before.iter()) {
+                    let after = &game.paddle(*other).unwrap().pos;
+                    let moved = if other.is_horizontal() { after.x - before.x } else { after.y - before.y };
+
+                    if other == side {
+                        assert!(moved * way > 0.0, "{:?} moved {} for {:?}", side, moved, key);
+                    } else {
+                        assert_eq!(moved, 0.0, "{:?} moved for {:?}", other, key);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn full_meters_on_four_paddles_dont_overflow() {
+        let mut constants = four_players();
+
+        constants.meter.max_hits = 200;
+
+        let mut game = Game::new(constants, 1);
+
+        for side in game.sides() {
+            let paddle = game.paddle_mut(side).unwrap();
+            paddle.hits = paddle.max_hits;
+        }
+
+        assert_eq!(game.max_hits(), 800);
+        assert_eq!(game.hits(), 800);
+        assert!(game.level_up_ready());
+    }
+
+    #[test]
+    fn coupling_keeps_balls_under_their_limits() {
+        let mut game = Game::new(OUTER_CONSTANTS, 1);
+
+        game.spawn_ball();
+
+        let id = game.balls[0].id;
+        let max = game.constants.max_ball_speed.clone();
+
+        for _ in 0..100 {
+            game.speed_up_ball(id, 1.15);
+        }
+
+        let velocity = game.balls[0].velocity.clone();
+
+        assert!(velocity.x.abs() <= max.x * MAX_SPEED_UP && velocity.y.abs() <= max.y * MAX_SPEED_UP);
+
+        for _ in 0..100 {
+            game.jolt_balls(0.5);
+
+            assert!(game.balls[0].velocity.y.abs() <= max.y);
+        }
+    }
+
+    #[test]
+    fn spawns_extra_balls_up_to_the_limit() {
+        let mut constants = INNER_CONSTANTS;
+
+        constants.max_balls = 3;
+        constants.ball_spawn_interval_ms = 100;
+        constants.powerups.extra_ball = false;
+
+        let mut game = Game::new(constants, 1);
+        let keys_pressed = HashMap::new();
+        let mut most = 0;
+
+        game.spawn_ball();
+        game.start();
+
+        for _ in 0..500 {
+            game.update(&keys_pressed, 16);
+            most = most.max(game.balls.len());
+
+            assert!(game.balls.len() <= 3);
+        }
+
+        assert_eq!(most, 3);
     }
 }
@@ -4,10 +4,18 @@ use std::thread::sleep;
 
 extern crate sdl2;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+#[macro_use]
+extern crate serde_json;
+extern crate rhai;
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::mixer::{DEFAULT_CHANNELS, INIT_MP3, INIT_FLAC, INIT_MOD, INIT_OGG, AUDIO_S16LSB};
+use sdl2::image::{INIT_PNG};
 
 mod vector;
 mod component;
@@ -16,14 +24,499 @@ mod constants;
 mod render;
 mod textures;
 mod sounds;
+mod nest;
+mod options;
+mod powerup;
+mod arena;
+mod palette;
+mod sprites;
+mod events;
+mod fx;
+mod camera;
+mod display;
+mod coupling;
+mod text;
+mod stats;
+mod profiles;
+mod tournament;
+mod env;
+mod script;
+mod bot;
+mod external;
+mod modes;
+mod practice;
+mod ai;
+mod campaign;
+mod assist;
+mod dev;
+mod console;
+mod config;
+mod reload;
 
-use constants::{OUTER_CONSTANTS, INNER_CONSTANTS};
-use game::{Game};
+use arena::{Arena};
+use constants::{Color, Constants, OUTER_CONSTANTS, INNER_CONSTANTS};
+use nest::{Nest};
+use options::{Command, Options};
+use palette::{Palette, Themes};
 use render::render;
 use textures::init_textures;
+use sprites::{Sprites};
 use sounds::{Sounds};
+use fx::{FxSettings};
+use coupling::{CouplingSettings};
+use game::{GameStates};
+use stats::{MatchStats};
+use profiles::{Profiles};
+use tournament::{Format, Tournament};
+use text::{Panel, Text};
+use camera::{Camera};
+use display::{Display, default_window_size};
+use vector::{Vector};
+use component::{Side};
+use env::{EnvSettings, Envs};
+use bot::{ScriptBot};
+use external::{ExternalBot, play_match};
+use modes::{Mode, Rules};
+use practice::{Drill};
+use ai::{Ai};
+use campaign::{Campaign};
+use assist::{AimAssist, Handicap, OneButton};
+use dev::{DevMode, STEP_MS};
+use console::{Console};
+use config::{Bindings, Config};
+use reload::{Change, Reloader};
+
+// Overlays that can be shown on top of the game.
+enum Screen {
+    Summary,
+    Leaderboard(Vec<String>),
+    // The result of a match that has just finished.
+    Results(Vec<String>),
+    // The campaign's stages, with the one picked.
+    StageSelect(usize),
+}
+
+fn run_command(command: &Command) -> () {
+    let profiles = Profiles::new();
+
+    match *command {
+        Command::Leaderboard(ref mode) => {
+            for line in profiles.leaderboard_lines(mode.as_ref().map(|mode| mode.as_str())) {
+                println!("{}", line);
+            }
+        },
+        Command::Profile(ref name) => {
+            let profile = match profiles.load(name) {
+                Ok(profile) => profile,
+                Err(e) => {
+                    println!("Could not load profile {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            println!("{}, {} matches played", profile.name, profile.history.len());
+
+            for (mode, best) in profile.best.iter() {
+                println!(
+                    "  best {}: {} points, {} hits, longest rally {}",
+                    mode, best.points, best.hits, best.longest_rally
+                );
+            }
+
+            for record in profile.history.iter().rev().take(10) {
+                println!(
+                    "  {} {} vs {}: {} - {}",
+                    if record.won { "won " } else { "lost" },
+                    record.mode,
+                    record.opponents.join(", "),
+                    record.points,
+                    record.opponent_points
+                );
+            }
+        },
+        // These need the game settings, so they are run from `main`.
+        Command::Gym | Command::BotMatch(..) => {},
+        Command::Tournament(ref name) => {
+            match Tournament::load(name) {
+                Ok(tournament) => {
+                    for line in tournament.lines() {
+                        println!("{}", line);
+                    }
+                },
+                Err(e) => {
+                    println!("Could not load tournament {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+    }
+}
+
+// The side called `name`, exiting if there isn't one.
+fn side_or_exit(name: &str) -> Side {
+    Side::from_name(name).unwrap_or_else(|| {
+        println!("Unknown side {}, try left, right, top or bottom", name);
+        std::process::exit(1);
+    })
+}
+
+// Starts a new tournament if players and a format were given, otherwise
+// loads the saved one.
+fn open_tournament(name: &str, options: &Options) -> Result<Tournament, String> {
+    let format = match options.format {
+        Some(ref format) => Some(Format::parse(format).ok_or_else(|| {
+            format!("unknown format {}, try single, double or round-robin", format)
+        })?),
+        None => None,
+    };
+
+    if options.players.is_empty() {
+        return Tournament::load(name);
+    }
+
+    if Tournament::path(name)?.exists() {
+        return Err(format!("{} already exists, leave out --players to carry on with it", name));
+    }
+
+    let tournament = Tournament::new(name, format.unwrap_or(Format::SingleElimination), options.players.clone())?;
+    tournament.save()?;
+
+    Ok(tournament)
+}
+
+// Runs headless environments for training agents until stdin is closed.
+fn run_gym(levels: &[Constants], options: &Options) -> () {
+    let settings = match options.env_settings {
+        Some(ref path) => match EnvSettings::load(path) {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("Could not load environment settings {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => EnvSettings::default(),
+    };
+
+    let envs = Envs::new(levels, settings);
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+
+    if let Err(e) = env::serve(&envs, stdin.lock(), stdout.lock()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+// Plays two external bots against each other, swapping sides every match.
+fn run_bot_match(levels: &[Constants], first: &str, second: &str, options: &Options) -> () {
+    let matches = options.matches.unwrap_or(2);
+    let first_to = options.first_to.unwrap_or(5);
+    let timeout = Duration::from_millis(options.bot_timeout.unwrap_or(20));
+    let mut wins = (0, 0);
+
+    for i in 0..matches {
+        let (left, right) = if i % 2 == 0 { (first, second) } else { (second, first) };
+
+        let result = match play_match(levels, left, right, first_to, timeout, 10 * 60 * 1000, i as u64) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Could not play the match {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        println!(
+            "match {}: {} {} - {} {} ({} and {} timeouts, {} s)",
+            i + 1,
+            result.bots.0,
+            result.points.0,
+            result.points.1,
+            result.bots.1,
+            result.timeouts.0,
+            result.timeouts.1,
+            result.duration_ms / 1000
+        );
+
+        let first_points = if left == first { result.points.0 } else { result.points.1 };
+        let second_points = if left == first { result.points.1 } else { result.points.0 };
+
+        if first_points > second_points { wins.0 = wins.0 + 1; }
+        if second_points > first_points { wins.1 = wins.1 + 1; }
+    }
+
+    println!("{} won {}, {} won {}", first, wins.0, second, wins.1);
+}
+
+// Everything that moves paddles apart from the keyboard.
+struct Controllers {
+    one_button: OneButton,
+    bots: Vec<ScriptBot>,
+    external_bots: Vec<ExternalBot>,
+    // The computer player, in campaigns or from the console.
+    ai: Option<Ai>,
+    aim_assists: Vec<AimAssist>,
+}
+
+// One frame of the match: the paddles that aren't played from the keyboard,
+// the mode's rules, every game in the nest and the stats. Frame steps in dev
+// mode go through here too, so they play out like any other frame.
+fn play_frame(
+    nest: &mut Nest,
+    stats: &mut MatchStats,
+    rules: &mut Rules,
+    controllers: &mut Controllers,
+    keys_pressed: &HashMap<&Keycode, bool>,
+    delta_ms: u64
+) -> () {
+    controllers.one_button.control(nest, keys_pressed);
+
+    for bot in controllers.bots.iter_mut() {
+        bot.control(nest);
+    }
+
+    if nest.game.state == GameStates::Playing {
+        for bot in controllers.external_bots.iter_mut() {
+            bot.control(nest);
+        }
+
+        if let Some(ref mut ai) = controllers.ai {
+            ai.control(nest, delta_ms);
+        }
+
+        for assist in controllers.aim_assists.iter() {
+            assist.control(nest, delta_ms);
+        }
+
+        rules.update(nest, delta_ms);
+    }
+
+    nest.update(keys_pressed, delta_ms);
+    stats.update(nest, delta_ms);
+}
 
 fn main() {
+    let options = Options::from_args();
+
+    match options.command {
+        Some(Command::Gym) | Some(Command::BotMatch(..)) | None => {},
+        Some(ref command) => {
+            run_command(command);
+            return;
+        }
+    }
+
+    let mut tournament = match options.tournament {
+        Some(ref name) => match open_tournament(name, &options) {
+            Ok(tournament) => Some(tournament),
+            Err(e) => {
+                println!("Could not open tournament {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // Who is playing the current match.
+    let mut players = match tournament {
+        Some(ref tournament) => match tournament.next_match() {
+            Some(game) => game.players.clone(),
+            None => {
+                for line in tournament.lines() {
+                    println!("{}", line);
+                }
+                return;
+            }
+        },
+        None => options.players.clone(),
+    };
+
+    if tournament.is_none() && players.len() > 4 {
+        println!("There can be at most 4 players");
+        std::process::exit(1);
+    }
+
+    let first_to = options.first_to.or(if tournament.is_some() { Some(5) } else { None });
+    let mode_name = options.mode.clone().unwrap_or_else(|| "classic".to_string());
+    let mut mode = match Mode::parse(&mode_name, first_to, options.lives, options.time.map(|seconds| seconds * 1000)) {
+        Some(mode) => mode,
+        None => {
+            println!("Unknown mode {}, try one of: {}", mode_name, Mode::names().join(", "));
+            std::process::exit(1);
+        }
+    };
+
+    if let Mode::Practice { ref mut drills } = mode {
+        if let Some(ref path) = options.drills {
+            match Drill::load(path) {
+                Ok(loaded) => *drills = loaded,
+                Err(e) => {
+                    println!("Could not load drills {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    if tournament.is_some() && options.campaign.is_some() {
+        println!("A tournament can't be played as a campaign");
+        std::process::exit(1);
+    }
+
+    if tournament.is_some() && !mode.competitive() {
+        println!("A tournament needs a mode with a winner");
+        std::process::exit(1);
+    }
+
+    // What the match goes down as in the profiles.
+    let record_as = if tournament.is_some() {
+        "tournament"
+    } else if options.campaign.is_some() {
+        "campaign"
+    } else {
+        mode.name()
+    };
+
+    let mut levels = [OUTER_CONSTANTS, INNER_CONSTANTS];
+
+    // The command line wins over the config file.
+    if let Some(ref path) = options.config {
+        let applied = Config::load(path)
+            .and_then(|config| config.apply(&mut levels).map_err(|e| format!("{}: {}", path, e)));
+
+        if let Err(e) = applied {
+            println!("Could not load config {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let [mut outer_constants, mut inner_constants] = levels;
+
+    if let Some(balls) = options.balls {
+        outer_constants.max_balls = balls;
+        inner_constants.max_balls = balls;
+    }
+
+    if options.four_players {
+        outer_constants.four_players = true;
+        inner_constants.four_players = true;
+    }
+
+    for (side, spec) in options.handicaps.iter() {
+        let side = side_or_exit(side);
+
+        match Handicap::parse(spec) {
+            Ok(handicap) => {
+                outer_constants.handicaps[side as usize] = handicap.clone();
+                inner_constants.handicaps[side as usize] = handicap;
+            },
+            Err(e) => {
+                println!("Could not read handicap {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Mode::Practice { ref drills } = mode {
+        for constants in [&mut outer_constants, &mut inner_constants].iter_mut() {
+            constants.practice_wall = true;
+
+            // Drills fire one ball at a time, so nothing else gets served.
+            if !drills.is_empty() {
+                constants.ball_spawn_interval_ms = 0;
+                constants.spawn_ball_on_level_up = false;
+                constants.powerups.spawn_interval_ms = 0;
+            }
+        }
+    }
+
+    if let Some(ref rules) = options.rules {
+        match CouplingSettings::preset(rules) {
+            Some(coupling) => inner_constants.coupling = coupling,
+            None => {
+                println!("Unknown rules {}, try one of: {}", rules, CouplingSettings::names().join(", "));
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if options.no_effects {
+        outer_constants.effects = FxSettings::all(false);
+        inner_constants.effects = FxSettings::all(false);
+    }
+
+    for (constants, path) in [&mut outer_constants, &mut inner_constants].iter_mut().zip(options.arenas.iter()) {
+        match Arena::load(path) {
+            Ok(arena) => {
+                eprintln!("Loaded arena {}", arena.name);
+                constants.arena = arena;
+            },
+            Err(e) => {
+                println!("Could not load arena {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match options.command {
+        Some(Command::Gym) => {
+            run_gym(&[outer_constants, inner_constants], &options);
+            return;
+        },
+        Some(Command::BotMatch(ref first, ref second)) => {
+            run_bot_match(&[outer_constants, inner_constants], first, second, &options);
+            return;
+        },
+        _ => {}
+    }
+
+    let campaign = match options.campaign {
+        Some(ref path) => match Campaign::load(path, &outer_constants, &inner_constants) {
+            Ok(campaign) => Some(campaign),
+            Err(e) => {
+                println!("Could not load campaign {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // Campaign progress is kept in the first player's profile.
+    let player = players.first().cloned().unwrap_or_else(|| "player".to_string());
+    let profiles = Profiles::new();
+    let mut cleared = match campaign {
+        Some(ref campaign) => profiles.load(&player).map(|profile| profile.cleared(&campaign.name)).unwrap_or_else(|e| {
+            println!("Could not load campaign progress {}", e);
+            0
+        }),
+        None => 0,
+    };
+    // The campaign stage being played, or about to be.
+    let mut stage_index = campaign.as_ref().map_or(0, |campaign| cleared.min(campaign.stages.len() - 1));
+
+    // Files that are applied again when they are saved.
+    let mut reloader = Reloader::default();
+    let mut themes = Themes::new(Palette::builtin());
+
+    for path in options.palettes.iter() {
+        match Palette::load(path) {
+            Ok(palette) => {
+                reloader.watch(path, Change::Palette(themes.palettes.len()));
+                themes.palettes.push(palette);
+            },
+            Err(e) => {
+                println!("Could not load palette {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(ref theme) = options.theme {
+        if !themes.select(theme) {
+            println!("Unknown theme {}", theme);
+            std::process::exit(1);
+        }
+    }
+
     let sdl_context = sdl2::init().unwrap();
 
     let video_subsystem = sdl_context.video().unwrap();
@@ -39,16 +532,30 @@ fn main() {
         INIT_MP3 | INIT_FLAC | INIT_MOD | INIT_OGG
     ).unwrap();
 
-    let window = video_subsystem.window(
-        "Popongong",
-        OUTER_CONSTANTS.window_width as u32,
-        OUTER_CONSTANTS.window_height as u32
-    )
+    // The game is played in the outer field's coordinates whatever the
+    // size of the window.
+    let logical_size = Vector {
+        x: outer_constants.window_width as f64,
+        y: outer_constants.window_height as f64,
+    };
+
+    let (window_width, window_height) = options.window_size.unwrap_or_else(|| {
+        default_window_size(&logical_size, video_subsystem.display_bounds(0).ok())
+    });
+
+    let mut window_builder = video_subsystem.window("Popongong", window_width, window_height);
+
+    window_builder
         .position_centered()
-    //    .fullscreen()
-        .opengl()
-        .build()
-        .unwrap();
+        .resizable()
+        .allow_highdpi()
+        .opengl();
+
+    if options.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+
+    let window = window_builder.build().unwrap();
 
     // Canvas manages and owns a Window (or Surface).
     //
@@ -60,13 +567,125 @@ fn main() {
         .build()
         .unwrap();
 
+    let mut display = Display::new(logical_size.clone(), &canvas);
+
     let texture_creator = canvas.texture_creator();
-    let textures = init_textures(&texture_creator);
+    let mut textures = init_textures(&texture_creator, themes.current());
+
+    let _image_context = sdl2::image::init(INIT_PNG).unwrap();
+
+    // Without a sprite set everything is drawn in solid colors.
+    let mut sprites = match options.sprites {
+        Some(ref path) => match Sprites::load(&texture_creator, path) {
+            Ok(sprites) => {
+                println!("Loaded sprite set {}", sprites.name);
+                Some(sprites)
+            },
+            Err(e) => {
+                println!("Could not load sprites {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let mut sounds = match Sounds::load() {
+        Ok(sounds) => sounds,
+        Err(e) => {
+            println!("Could not load sounds {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut bindings = match options.keys {
+        Some(ref path) => match Bindings::load(path) {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                println!("Could not load key bindings {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => Bindings::default(),
+    };
+
+    if let Some(ref path) = options.config {
+        reloader.watch(path, Change::Config);
+    }
+    if let Some(ref path) = options.keys {
+        reloader.watch(path, Change::Bindings);
+    }
+    for path in Sounds::files() {
+        reloader.watch(&path, Change::Sounds);
+    }
 
-    let sounds = Sounds::new();
+    let mut bots = vec![];
+    let mut external_bots = vec![];
+    let one_button = OneButton { sides: options.one_button.iter().map(|side| side_or_exit(side)).collect() };
+    let aim_assists: Vec<AimAssist> = options.aim_assist.iter()
+        .map(|(side, strength)| AimAssist { side: side_or_exit(side), strength: *strength })
+        .collect();
+    // Scales the time that passes in the game, changed with - and =.
+    let mut game_speed = options.game_speed.unwrap_or(1.0).clamp(0.25, 2.0);
+    let mut dev = if options.dev { Some(DevMode::new()) } else { None };
+
+    for (side, path) in options.bots.iter() {
+        let side = match Side::from_name(side) {
+            Some(side) => side,
+            None => {
+                println!("Unknown side {}, try left, right, top or bottom", side);
+                std::process::exit(1);
+            }
+        };
+
+        match ScriptBot::load(side, path) {
+            Ok(bot) => {
+                reloader.watch(path, Change::Bot(bots.len()));
+                bots.push(bot);
+            },
+            Err(e) => {
+                println!("Could not load bot {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    for (sides, command) in options.bot_processes.iter() {
+        let sides: Vec<Side> = sides.split(',').map(|name| {
+            Side::from_name(name).unwrap_or_else(|| {
+                println!("Unknown side {}, try left, right, top or bottom", name);
+                std::process::exit(1);
+            })
+        }).collect();
+        let timeout = Duration::from_millis(options.bot_timeout.unwrap_or(20));
+
+        match ExternalBot::connect(command, sides, timeout) {
+            Ok(bot) => external_bots.push(bot),
+            Err(e) => {
+                println!("Could not start bot {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut effects = outer_constants.effects.any();
+    let mut levels = vec![outer_constants, inner_constants];
+    let mut controllers = Controllers {
+        one_button: one_button,
+        bots: bots,
+        external_bots: external_bots,
+        ai: None,
+        aim_assists: aim_assists,
+    };
+
+    if let Some(ref campaign) = campaign {
+        let stage = &campaign.stages[stage_index];
+
+        levels = stage.levels.clone();
+        mode = stage.mode.clone();
+    }
 
-    let mut outer_game = Game::new(OUTER_CONSTANTS, &sounds);
-    let mut inner_game = Game::new(INNER_CONSTANTS, &sounds);
+    let mut nest = Nest::new(&levels);
+    let mut camera = Camera::new(nest.game.size());
 
     // Get a reference to the SDL "event pump".
     //
@@ -80,31 +699,23 @@ fn main() {
     // state of the hash map and not directly modify a game element.
     let mut keys_pressed = HashMap::new();
 
-    fn handle_key_press_events(event: sdl2::event::Event, keys_pressed: &mut HashMap<&Keycode, bool>) -> () {
+    // Bound keys are stored as the usual key for the same paddle and
+    // direction, which is what the games listen for.
+    fn handle_key_press_events(
+        event: sdl2::event::Event,
+        keys_pressed: &mut HashMap<&Keycode, bool>,
+        bindings: &Bindings
+    ) -> () {
         match event {
-            Event::KeyDown { keycode: Some(Keycode::Up), .. } => {
-                keys_pressed.insert(&Keycode::Up, true);
-            },
-            Event::KeyUp { keycode: Some(Keycode::Up), .. } => {
-                keys_pressed.remove(&Keycode::Up);
-            },
-            Event::KeyDown { keycode: Some(Keycode::Down), .. } => {
-                keys_pressed.insert(&Keycode::Down, true);
-            },
-            Event::KeyUp { keycode: Some(Keycode::Down), .. } => {
-                keys_pressed.remove(&Keycode::Down);
-            },
-            Event::KeyDown { keycode: Some(Keycode::W), .. } => {
-                keys_pressed.insert(&Keycode::W, true);
-            },
-            Event::KeyUp { keycode: Some(Keycode::W), .. } => {
-                keys_pressed.remove(&Keycode::W);
-            },
-            Event::KeyDown { keycode: Some(Keycode::S), .. } => {
-                keys_pressed.insert(&Keycode::S, true);
+            Event::KeyDown { keycode: Some(keycode), .. } => {
+                if let Some(key) = bindings.translate(keycode) {
+                    keys_pressed.insert(key, true);
+                }
             },
-            Event::KeyUp { keycode: Some(Keycode::S), .. } => {
-                keys_pressed.remove(&Keycode::S);
+            Event::KeyUp { keycode: Some(keycode), .. } => {
+                if let Some(key) = bindings.translate(keycode) {
+                    keys_pressed.remove(key);
+                }
             },
             _ => {}
         }
@@ -114,12 +725,26 @@ fn main() {
         duration.as_secs() * 1000 + duration.subsec_millis() as u64
     }
 
-    render(&mut outer_game, &mut inner_game, &textures, &mut canvas);
+    let mut stats = MatchStats::new();
+    let mut screen = if campaign.is_some() { Some(Screen::StageSelect(stage_index)) } else { None };
+    // Whether the current match has been saved to the profiles.
+    let mut recorded = false;
+    let mut rules = Rules::new(mode.clone());
+    let mut console = Console::default();
+
+    if let Some(ref path) = options.console_script {
+        console.run(&format!("exec {}", path), &mut nest, &mut levels, &mut controllers.ai);
+    }
+
+    render(&mut nest, &camera, &display, &[], &textures, &mut sprites, &mut canvas);
 
     // Sleep for loading time here
     sleep(Duration::new(5, 0));
-    outer_game.start();
-    inner_game.start();
+
+    // Campaigns wait for a stage to be picked.
+    if campaign.is_none() {
+        nest.start();
+    }
 
     let mut delta_ms: u64;
     let mut prev_time = Instant::now();
@@ -133,28 +758,344 @@ fn main() {
         // Grab lastest events and iterate over them
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                Event::Quit {..} => {
                     break 'main
                 },
+                // While the console is open it gets all the typing.
+                Event::KeyDown { keycode: Some(Keycode::Backquote), .. } if dev.is_some() => {
+                    console.toggle();
+                },
+                Event::TextInput { ref text, .. } if console.open => {
+                    console.type_text(text);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } if console.open => {
+                    console.toggle();
+                },
+                Event::KeyDown { keycode: Some(keycode), .. } if console.open => {
+                    if let Some(line) = console.key(keycode) {
+                        console.run(&line, &mut nest, &mut levels, &mut controllers.ai);
+                    }
+                },
+                // The first Esc pauses and shows how the match went, the
+                // second one quits.
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    if let Some(Screen::Summary) = screen {
+                        break 'main
+                    }
+
+                    if nest.game.state == GameStates::Playing {
+                        nest.play_pause();
+                    }
+                    screen = Some(Screen::Summary);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Tab), .. } => {
+                    screen = match screen {
+                        Some(Screen::Summary) => None,
+                        _ => Some(Screen::Summary),
+                    };
+                },
+                Event::KeyDown { keycode: Some(Keycode::L), .. } => {
+                    screen = match screen {
+                        Some(Screen::Leaderboard(_)) => None,
+                        _ => Some(Screen::Leaderboard(profiles.leaderboard_lines(None))),
+                    };
+                },
+                Event::KeyDown { keycode: Some(Keycode::Up), .. } |
+                Event::KeyDown { keycode: Some(Keycode::Down), .. }
+                    if matches!(screen, Some(Screen::StageSelect(_))) => {
+
+                    if let (Some(&mut Screen::StageSelect(ref mut selected)), Some(campaign)) = (screen.as_mut(), campaign.as_ref()) {
+                        *selected = match event {
+                            Event::KeyDown { keycode: Some(Keycode::Up), .. } => selected.saturating_sub(1),
+                            _ => (*selected + 1).min(campaign.stages.len() - 1),
+                        };
+                    }
+                },
+                // Play the picked campaign stage, go back to the stage
+                // select after one, or carry on to the next match of the
+                // tournament or a rematch.
+                Event::KeyDown { keycode: Some(Keycode::Return), .. } => {
+                    let restart = match (screen.as_ref(), campaign.as_ref()) {
+                        (Some(&Screen::StageSelect(selected)), Some(campaign)) if selected <= cleared => {
+                            let stage = &campaign.stages[selected];
+
+                            stage_index = selected;
+                            levels = stage.levels.clone();
+                            mode = stage.mode.clone();
+                            controllers.ai = Some(Ai::new(Side::Two, stage.ai.clone()));
+                            true
+                        },
+                        (Some(&Screen::Results(_)), Some(campaign)) => {
+                            screen = Some(Screen::StageSelect(cleared.min(campaign.stages.len() - 1)));
+                            false
+                        },
+                        (Some(&Screen::Results(_)), None) => {
+                            if let Some(ref tournament) = tournament {
+                                match tournament.next_match() {
+                                    Some(game) => players = game.players.clone(),
+                                    None => break 'main,
+                                }
+                            }
+
+                            true
+                        },
+                        _ => false,
+                    };
+
+                    if restart {
+                        nest = Nest::new(&levels);
+                        nest.set_effects(&FxSettings::all(effects));
+                        stats = MatchStats::new();
+                        rules = Rules::new(mode.clone());
+                        recorded = false;
+                        screen = None;
+                        nest.start();
+
+                        if let Some(ref mut dev) = dev {
+                            dev.clear();
+                        }
+                    }
+                },
                 Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
-                    outer_game.play_pause();
-                    inner_game.play_pause();
+                    nest.play_pause();
+                },
+                Event::KeyDown { keycode: Some(Keycode::T), .. } => {
+                    textures = init_textures(&texture_creator, themes.next());
+                },
+                Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                    display.toggle_fullscreen(&mut canvas);
+                },
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                    display.resize(&canvas);
+                },
+                Event::KeyDown { keycode: Some(Keycode::C), .. } => {
+                    camera.next_mode();
+                },
+                Event::KeyDown { keycode: Some(Keycode::Minus), .. } => {
+                    game_speed = (game_speed - 0.05).max(0.25);
+                },
+                Event::KeyDown { keycode: Some(Keycode::Equals), .. } => {
+                    game_speed = (game_speed + 0.05).min(2.0);
+                },
+                // Developer keys, only with `--dev`. Frame stepping and
+                // rewinding work while paused.
+                Event::KeyDown { keycode: Some(Keycode::LeftBracket), .. } if dev.is_some() => {
+                    if let Some(ref mut dev) = dev { dev.slower(); }
+                },
+                Event::KeyDown { keycode: Some(Keycode::RightBracket), .. } if dev.is_some() => {
+                    if let Some(ref mut dev) = dev { dev.faster(); }
+                },
+                Event::KeyDown { keycode: Some(Keycode::H), .. } if dev.is_some() => {
+                    if let Some(ref mut dev) = dev { dev.hitboxes = !dev.hitboxes; }
+                },
+                Event::KeyDown { keycode: Some(Keycode::N), .. }
+                    if dev.is_some() && nest.game.state == GameStates::Paused => {
+
+                    if let Some(ref mut dev) = dev {
+                        dev.record(&nest, &stats, &rules);
+                    }
+
+                    nest.play_pause();
+                    play_frame(&mut nest, &mut stats, &mut rules, &mut controllers, &keys_pressed, STEP_MS);
+                    nest.play_pause();
+                },
+                Event::KeyDown { keycode: Some(Keycode::B), .. }
+                    if dev.is_some() && nest.game.state == GameStates::Paused && !recorded => {
+
+                    if let Some((previous, previous_stats, previous_rules)) = dev.as_mut().and_then(|dev| dev.rewind()) {
+                        nest = previous;
+                        stats = previous_stats;
+                        rules = previous_rules;
+
+                        if nest.game.state == GameStates::Playing {
+                            nest.play_pause();
+                        }
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F), .. } => {
+                    effects = !effects;
+                    nest.set_effects(&FxSettings::all(effects));
                 },
                 Event::KeyDown { .. } | Event::KeyUp { .. } => {
-                    handle_key_press_events(event, &mut keys_pressed);
+                    handle_key_press_events(event, &mut keys_pressed, &bindings);
                 }
                 _ => {}
             }
         }
 
-        outer_game.update(&keys_pressed, delta_ms);
-        inner_game.update(&keys_pressed, delta_ms);
+        // Apply any files that have been saved, keeping the match going.
+        for (change, path) in reloader.check(delta_ms) {
+            let result = match change {
+                Change::Config => Config::load(&path).and_then(|config| {
+                    config.apply(&mut levels).map_err(|e| format!("{}: {}", path, e))?;
+                    nest.set_levels(&levels);
+
+                    Ok(())
+                }),
+                Change::Bindings => Bindings::load(&path).map(|loaded| {
+                    bindings = loaded;
+                    keys_pressed.clear();
+                }),
+                Change::Palette(index) => Palette::load(&path).map(|palette| {
+                    themes.palettes[index] = palette;
 
-        if outer_game.hits() == 10 && inner_game.hits() == 10 {
-            outer_game.next_color();
-            inner_game.next_color();
+                    if themes.current == index {
+                        textures = init_textures(&texture_creator, themes.current());
+                    }
+                }),
+                Change::Sounds => Sounds::load().map(|loaded| sounds = loaded),
+                Change::Bot(index) => controllers.bots[index].reload(),
+            };
+
+            reloader.report(&path, result);
         }
 
-        render(&mut outer_game, &mut inner_game, &textures, &mut canvas);
+        let time_scale = dev.as_ref().map(|dev| dev.time_scale).unwrap_or(1.0);
+        let game_delta_ms = (delta_ms as f64 * game_speed * time_scale).round() as u64;
+
+        if let Some(ref mut dev) = dev {
+            if nest.game.state == GameStates::Playing {
+                dev.record(&nest, &stats, &rules);
+            }
+
+            dev.apply(&mut nest);
+        }
+
+        play_frame(&mut nest, &mut stats, &mut rules, &mut controllers, &keys_pressed, game_delta_ms);
+        camera.update(delta_ms);
+
+        for (_, event) in nest.events() {
+            sounds.play_event(event);
+        }
+
+        let sides = nest.game.sides();
+
+        if !recorded && rules.over(&stats, &sides) {
+            if nest.game.state == GameStates::Playing {
+                nest.play_pause();
+            }
+
+            if !players.is_empty() {
+                if let Err(e) = profiles.record(&players, &stats, record_as) {
+                    println!("Could not save the result {}", e);
+                }
+            }
+            recorded = true;
+
+            let names: Vec<String> = sides.iter().enumerate()
+                .map(|(i, side)| players.get(i).cloned().unwrap_or_else(|| side.name().to_string()))
+                .collect();
+            let mut lines = rules.results(&stats, &sides, &names);
+
+            if let Some(ref mut tournament) = tournament {
+                let scores = rules.scores(&stats, &sides);
+                tournament.record((scores[0], scores[1]));
+
+                if let Err(e) = tournament.save() {
+                    println!("Could not save the tournament {}", e);
+                }
+
+                lines.push(String::new());
+                lines.extend(tournament.lines());
+            }
+
+            if let Some(ref campaign) = campaign {
+                let stage = &campaign.stages[stage_index];
+
+                lines.push(String::new());
+
+                if stage.won(&rules, &stats, &sides) {
+                    lines.push(format!("{} cleared", stage.name));
+
+                    if stage_index >= cleared {
+                        cleared = stage_index + 1;
+
+                        if let Err(e) = profiles.clear_stage(&player, &campaign.name, stage_index) {
+                            println!("Could not save campaign progress {}", e);
+                        }
+                    }
+                } else {
+                    lines.push(format!("{} lost, try again", stage.name));
+                }
+            }
+
+            lines.push(String::new());
+            lines.push("press enter to continue".to_string());
+            screen = Some(Screen::Results(lines));
+        }
+
+        let panel = match screen {
+            Some(Screen::Summary) => Some(Panel::new("match summary", &stats.summary(), &logical_size)),
+            Some(Screen::Leaderboard(ref lines)) => Some(Panel::new("leaderboard", lines, &logical_size)),
+            Some(Screen::Results(ref lines)) => Some(Panel::new(rules.mode.name(), lines, &logical_size)),
+            Some(Screen::StageSelect(selected)) => campaign.as_ref().map(|campaign| {
+                Panel::new(&campaign.name, &campaign.lines(cleared, selected), &logical_size)
+            }),
+            None => None,
+        };
+
+        // The clock or lives left, for the modes that have them, and the game
+        // speed when it isn't normal.
+        let speed_percent = (game_speed * 100.0).round() as u32;
+        let mut status: Vec<String> = rules.status(&stats, &nest.game.sides()).into_iter().collect();
+
+        if speed_percent != 100 {
+            status.push(format!("speed {}%", speed_percent));
+        }
+
+        if let Some(ref dev) = dev {
+            status.push(dev.status());
+        }
+
+        status.extend(reloader.status());
+
+        let hud = if status.is_empty() {
+            None
+        } else {
+            let lines = vec![status.join("  ")];
+            let scale = 2.0;
+            let size = Text::size(&lines, scale);
+
+            Some(Text::new(&lines, &Vector { x: (logical_size.x - size.x) / 2.0, y: size.y }, scale, Color::Border))
+        };
+
+        let mut overlay = vec![];
+        if let Some(ref hud) = hud {
+            overlay.append(&mut hud.components());
+        }
+        if let Some(ref panel) = panel {
+            overlay.append(&mut panel.components());
+        }
+
+        let console_panel = if console.open {
+            Some(Panel::new("console", &console.lines(), &logical_size))
+        } else {
+            None
+        };
+        if let Some(ref panel) = console_panel {
+            overlay.append(&mut panel.components());
+        }
+
+        render(&mut nest, &camera, &display, &overlay, &textures, &mut sprites, &mut canvas);
+    }
+
+    // Unfinished tournament matches and campaign stages are played again
+    // next time.
+    if !recorded && tournament.is_none() && campaign.is_none() && !players.is_empty() {
+        match profiles.record(&players, &stats, record_as) {
+            Ok(()) => println!("Saved the result for {}", players.join(", ")),
+            Err(e) => println!("Could not save the result {}", e),
+        }
+    }
+
+    for bot in controllers.external_bots.iter_mut() {
+        bot.end(&stats);
+    }
+
+    for path in options.stats_files.iter() {
+        match stats.export(path) {
+            Ok(()) => println!("Wrote stats to {}", path),
+            Err(e) => println!("Could not write stats {}", e),
+        }
     }
 }
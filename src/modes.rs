@@ -0,0 +1,318 @@
+use component::{Side};
+
+use nest::{Nest};
+
+use practice::{Drill, Practice};
+
+use stats::{MatchStats, minutes};
+
+// How often survival mode speeds the balls up, and by how much.
+const SURVIVAL_INTERVAL_MS: u64 = 5000;
+const SURVIVAL_SPEED_UP: f64 = 1.1;
+
+// The rule sets a match can be played with, picked with `--mode`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mode {
+    // Rallies go on forever, or until someone has `first_to` points.
+    Classic { first_to: Option<u32> },
+    // As many hits as possible before the time runs out.
+    TimeAttack { time_ms: u64 },
+    // The balls keep speeding up and each side can only miss `lives` times.
+    Survival { lives: u32 },
+    // The first miss in any game, nested or not, loses.
+    SuddenDeath,
+    // Everyone on the same side, keeping every ball in play for as long as
+    // possible with `lives` misses between them.
+    Coop { lives: u32 },
+    // Alone against a wall, working through `drills` if there are any or
+    // just rallying for as long as you like.
+    Practice { drills: Vec<Drill> },
+}
+
+impl Mode {
+    // `first_to`, `lives` and `time_ms` override the defaults for the modes
+    // that use them.
+    pub fn parse(name: &str, first_to: Option<u32>, lives: Option<u32>, time_ms: Option<u64>) -> Option<Mode> {
+        match name {
+            "classic" => Some(Mode::Classic { first_to: first_to }),
+            "time-attack" => Some(Mode::TimeAttack { time_ms: time_ms.unwrap_or(60_000) }),
+            "survival" => Some(Mode::Survival { lives: lives.unwrap_or(3) }),
+            "sudden-death" => Some(Mode::SuddenDeath),
+            "coop" => Some(Mode::Coop { lives: lives.unwrap_or(5) }),
+            "practice" => Some(Mode::Practice { drills: vec![] }),
+            _ => None,
+        }
+    }
+
+    pub fn names() -> &'static [&'static str] {
+        &["classic", "time-attack", "survival", "sudden-death", "coop", "practice"]
+    }
+
+    // What the mode is called in profiles and on the leaderboard.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Mode::Classic { .. } => "classic",
+            Mode::TimeAttack { .. } => "time-attack",
+            Mode::Survival { .. } => "survival",
+            Mode::SuddenDeath => "sudden-death",
+            Mode::Coop { .. } => "coop",
+            Mode::Practice { .. } => "practice",
+        }
+    }
+
+    // Whether there is a winner at the end, rather than one shared score.
+    pub fn competitive(&self) -> bool {
+        !matches!(*self, Mode::Coop { .. } | Mode::Practice { .. })
+    }
+}
+
+// A mode being played, with whatever it needs to keep track of.
+#[derive(Debug, Clone)]
+pub struct Rules {
+    pub mode: Mode,
+    speed_up_timer_ms: u64,
+    practice: Option<Practice>,
+}
+
+impl Rules {
+    pub fn new(mode: Mode) -> Rules {
+        let practice = match mode {
+            Mode::Practice { ref drills } => Some(Practice::new(drills.clone())),
+            _ => None,
+        };
+
+        Rules { mode: mode, speed_up_timer_ms: 0, practice: practice }
+    }
+
+    // Call before every update of the nest while it is being played.
+    pub fn update(&mut self, nest: &mut Nest, delta_ms: u64) -> () {
+        if let Some(ref mut practice) = self.practice {
+            practice.update(nest, delta_ms);
+        }
+
+        if let Mode::Survival { .. } = self.mode {
+            self.speed_up_timer_ms = self.speed_up_timer_ms + delta_ms;
+
+            if self.speed_up_timer_ms >= SURVIVAL_INTERVAL_MS {
+                self.speed_up_timer_ms = 0;
+
+                for (_, game) in nest.games_mut() {
+                    let ids: Vec<u32> = game.balls.iter().map(|ball| ball.id).collect();
+
+                    for id in ids {
+                        game.speed_up_ball(id, SURVIVAL_SPEED_UP);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn over(&self, stats: &MatchStats, sides: &[Side]) -> bool {
+        let misses: Vec<u32> = sides.iter().map(|side| stats.totals(*side).1).collect();
+
+        match self.mode {
+            Mode::Classic { first_to: Some(first_to) } => {
+                sides.iter().any(|side| stats.points(*side) >= first_to)
+            },
+            Mode::Classic { first_to: None } => false,
+            Mode::TimeAttack { time_ms } => stats.duration_ms >= time_ms,
+            Mode::Survival { lives } => misses.iter().any(|misses| *misses >= lives),
+            Mode::SuddenDeath => misses.iter().any(|misses| *misses > 0),
+            Mode::Coop { lives } => misses.iter().sum::<u32>() >= lives,
+            Mode::Practice { ref drills } => {
+                !drills.is_empty() && self.practice.as_ref().is_some_and(|practice| practice.finished())
+            },
+        }
+    }
+
+    // Each side's score, higher being better: points, or hits in time
+    // attack. Everyone shares the hits in co-op.
+    pub fn scores(&self, stats: &MatchStats, sides: &[Side]) -> Vec<u32> {
+        match self.mode {
+            Mode::TimeAttack { .. } => sides.iter().map(|side| stats.totals(*side).0).collect(),
+            Mode::Coop { .. } => {
+                let hits = sides.iter().map(|side| stats.totals(*side).0).sum();
+                sides.iter().map(|_| hits).collect()
+            },
+            _ => sides.iter().map(|side| stats.points(*side)).collect(),
+        }
+    }
+
+    // A line to show while playing, for modes with a clock or lives.
+    pub fn status(&self, stats: &MatchStats, sides: &[Side]) -> Option<String> {
+        let misses: Vec<u32> = sides.iter().map(|side| stats.totals(*side).1).collect();
+
+        match self.mode {
+            Mode::TimeAttack { time_ms } => {
+                let hits: Vec<String> = self.scores(stats, sides).iter().map(|hits| hits.to_string()).collect();
+
+                Some(format!("{}  hits {}", minutes(time_ms.saturating_sub(stats.duration_ms)), hits.join(" - ")))
+            },
+            Mode::Survival { lives } => {
+                let left: Vec<String> = misses.iter().map(|misses| lives.saturating_sub(*misses).to_string()).collect();
+
+                Some(format!("{}  lives {}", minutes(stats.duration_ms), left.join(" - ")))
+            },
+            Mode::Coop { lives } => {
+                Some(format!(
+                    "{}  lives {}  rally {}",
+                    minutes(stats.duration_ms),
+                    lives.saturating_sub(misses.iter().sum()),
+                    stats.longest_rally()
+                ))
+            },
+            Mode::SuddenDeath => Some("sudden death".to_string()),
+            Mode::Practice { .. } => {
+                let (hits, misses) = stats.totals(Side::One);
+
+                self.practice.as_ref()
+                    .and_then(|practice| practice.status())
+                    .or_else(|| Some(format!("hits {}  misses {}  rally {}", hits, misses, stats.longest_rally())))
+            },
+            Mode::Classic { .. } => None,
+        }
+    }
+
+    // Lines for the results screen, `names` being the players in side
+    // order.
+    pub fn results(&self, stats: &MatchStats, sides: &[Side], names: &[String]) -> Vec<String> {
+        let scores = self.scores(stats, sides);
+        let misses: Vec<u32> = sides.iter().map(|side| stats.totals(*side).1).collect();
+        let best = scores.iter().cloned().max().unwrap_or(0);
+        let leaders: Vec<&String> = names.iter().zip(scores.iter())
+            .filter(|(_, score)| **score == best)
+            .map(|(name, _)| name)
+            .collect();
+
+        let winner = if leaders.len() == 1 {
+            format!("{} wins", leaders[0])
+        } else {
+            "it's a draw".to_string()
+        };
+
+        let mut lines = vec![];
+
+        match self.mode {
+            Mode::Classic { .. } => {
+                let scores: Vec<String> = scores.iter().map(|score| score.to_string()).collect();
+
+                lines.push(format!("{}  {}", names.join(" - "), scores.join(" - ")));
+                lines.push(winner);
+            },
+            Mode::TimeAttack { .. } => {
+                lines.push("time's up".to_string());
+
+                for (name, hits) in names.iter().zip(scores.iter()) {
+                    lines.push(format!("  {:<16} {} hits", name, hits));
+                }
+
+                lines.push(winner);
+            },
+            Mode::Survival { lives } => {
+                lines.push(format!("survived {}", minutes(stats.duration_ms)));
+
+                for (name, misses) in names.iter().zip(misses.iter()) {
+                    lines.push(format!("  {:<16} missed {} of {}", name, misses, lives));
+                }
+
+                lines.push(winner);
+            },
+            Mode::SuddenDeath => {
+                for (game, name) in stats.games.iter()
+                    .flat_map(|game| game.players.iter().filter(|player| player.misses > 0).map(move |player| (game, player)))
+                    .filter_map(|(game, player)| {
+                        sides.iter().position(|side| *side == player.side).map(|i| (game, &names[i]))
+                    }) {
+
+                    lines.push(match game.depth {
+                        0 => format!("{} missed in the outer game", name),
+                        depth => format!("{} missed {} games deep", name, depth),
+                    });
+                }
+
+                lines.push(winner);
+            },
+            Mode::Coop { .. } => {
+                lines.push(format!("kept going for {}", minutes(stats.duration_ms)));
+                lines.push(format!("{} hits together, longest rally {}", best, stats.longest_rally()));
+            },
+            Mode::Practice { .. } => {
+                if let Some(ref practice) = self.practice {
+                    lines.extend(practice.results());
+                }
+            },
+        }
+
+        return lines;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use stats::{GameStats, PlayerStats};
+
+    const SIDES: [Side; 2] = [Side::One, Side::Two];
+
+    // A match where each side has missed the given number of times.
+    fn stats(misses: &[u32], duration_ms: u64) -> MatchStats {
+        let mut stats = MatchStats::new();
+
+        stats.duration_ms = duration_ms;
+        stats.games.push(GameStats {
+            depth: 0,
+            players: SIDES.iter().zip(misses.iter()).map(|(side, misses)| PlayerStats {
+                side: *side,
+                hits: 0,
+                misses: *misses,
+                blocked: 0,
+                max_ball_speed: 0.0,
+                reaction_times_ms: vec![],
+            }).collect(),
+            longest_rally: 0,
+            max_ball_speed: 0.0,
+            time_at_level_ms: vec![],
+        });
+
+        return stats;
+    }
+
+    #[test]
+    fn parses_modes_with_defaults() {
+        assert_eq!(Mode::parse("classic", None, None, None), Some(Mode::Classic { first_to: None }));
+        assert_eq!(Mode::parse("classic", Some(7), Some(2), None), Some(Mode::Classic { first_to: Some(7) }));
+        assert_eq!(Mode::parse("time-attack", None, None, None), Some(Mode::TimeAttack { time_ms: 60_000 }));
+        assert_eq!(Mode::parse("time-attack", None, None, Some(5000)), Some(Mode::TimeAttack { time_ms: 5000 }));
+        assert_eq!(Mode::parse("survival", None, None, None), Some(Mode::Survival { lives: 3 }));
+        assert_eq!(Mode::parse("coop", None, Some(1), None), Some(Mode::Coop { lives: 1 }));
+        assert_eq!(Mode::parse("sudden-death", Some(3), None, None), Some(Mode::SuddenDeath));
+        assert_eq!(Mode::parse("practice", None, None, None), Some(Mode::Practice { drills: vec![] }));
+        assert_eq!(Mode::parse("Classic", None, None, None), None);
+        assert_eq!(Mode::parse("", None, None, None), None);
+    }
+
+    #[test]
+    fn every_name_parses_back_to_itself() {
+        for name in Mode::names() {
+            assert_eq!(Mode::parse(name, None, None, None).unwrap().name(), *name);
+        }
+    }
+
+    #[test]
+    fn modes_end_on_their_own_terms() {
+        let over = |mode: Mode, stats: &MatchStats| Rules::new(mode).over(stats, &SIDES);
+
+        assert!(!over(Mode::Classic { first_to: None }, &stats(&[50, 50], 0)));
+        assert!(!over(Mode::Classic { first_to: Some(3) }, &stats(&[2, 0], 0)));
+        assert!(over(Mode::Classic { first_to: Some(3) }, &stats(&[3, 0], 0)));
+        assert!(!over(Mode::TimeAttack { time_ms: 1000 }, &stats(&[0, 0], 999)));
+        assert!(over(Mode::TimeAttack { time_ms: 1000 }, &stats(&[0, 0], 1000)));
+        assert!(!over(Mode::Survival { lives: 2 }, &stats(&[1, 1], 0)));
+        assert!(over(Mode::Survival { lives: 2 }, &stats(&[0, 2], 0)));
+        assert!(!over(Mode::SuddenDeath, &stats(&[0, 0], 0)));
+        assert!(over(Mode::SuddenDeath, &stats(&[0, 1], 0)));
+        assert!(!over(Mode::Coop { lives: 3 }, &stats(&[1, 1], 0)));
+        assert!(over(Mode::Coop { lives: 3 }, &stats(&[1, 2], 0)));
+    }
+}
@@ -0,0 +1,254 @@
+use rand::{thread_rng, Rng};
+
+use sdl2::keyboard::Keycode;
+
+use std::collections::HashMap;
+
+use constants::{Constants};
+use events::{GameEvent};
+use fx::{FxSettings};
+use game::{Game, GameStates};
+
+// A game together with the games nested inside its balls. Every ball in
+// `game` hosts its own inner nest, built from the next set of constants in
+// `levels`, so a game with three balls has three independent inner games.
+#[derive(Debug, Clone)]
+pub struct Nest {
+    pub game: Game,
+    // Inner nests keyed by the id of the ball they are drawn on.
+    pub inner: Vec<(u32, Nest)>,
+    levels: Vec<Constants>,
+}
+
+impl Nest {
+    // `levels` is ordered from the outermost game inwards.
+    pub fn new(levels: &[Constants]) -> Nest {
+        Nest::seeded(levels, thread_rng().gen())
+    }
+
+    // The same seed gives the same games, inner ones included.
+    pub fn seeded(levels: &[Constants], seed: u64) -> Nest {
+        let mut nest = Nest {
+            game: Game::new(levels[0].clone(), seed),
+            inner: vec![],
+            levels: levels[1..].to_vec(),
+        };
+
+        nest.sync_inner();
+
+        return nest;
+    }
+
+    pub fn start(&mut self) -> () {
+        self.game.start();
+
+        for (_, inner) in self.inner.iter_mut() {
+            inner.start();
+        }
+    }
+
+    pub fn play_pause(&mut self) -> () {
+        self.game.play_pause();
+
+        for (_, inner) in self.inner.iter_mut() {
+            inner.play_pause();
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        keys_pressed: &HashMap<&Keycode, bool>,
+        delta_ms: u64
+    ) -> () {
+        self.update_games(keys_pressed, delta_ms);
+        self.couple();
+
+        // Games that level up together only do so once all of them are
+        // ready, the rest level up as soon as they reach their own threshold.
+        let together_ready = self.games().iter()
+            .filter(|game| game.levels_up_together())
+            .all(|game| game.level_up_ready());
+
+        self.level_up(together_ready);
+    }
+
+    // Switch every game to new constants without restarting, see
+    // `Game::set_constants`. The effects stay as they are, those are set
+    // with `set_effects`.
+    pub fn set_levels(&mut self, levels: &[Constants]) -> () {
+        if levels.is_empty() { return; }
+
+        let mut constants = levels[0].clone();
+        constants.effects = self.game.fx.settings.clone();
+        self.game.set_constants(constants);
+
+        let effects: Vec<FxSettings> = self.levels.iter().map(|level| level.effects.clone()).collect();
+
+        self.levels = levels[1..].to_vec();
+
+        for (level, effects) in self.levels.iter_mut().zip(effects) {
+            level.effects = effects;
+        }
+
+        let inner_levels = self.levels.clone();
+
+        for (_, inner) in self.inner.iter_mut() {
+            inner.set_levels(&inner_levels);
+        }
+    }
+
+    fn update_games(
+        &mut self,
+        keys_pressed: &HashMap<&Keycode, bool>,
+        delta_ms: u64
+    ) -> () {
+        self.game.update(keys_pressed, delta_ms);
+        self.sync_inner();
+
+        for (_, inner) in self.inner.iter_mut() {
+            inner.update_games(keys_pressed, delta_ms);
+        }
+    }
+
+    // Apply the coupling rules between each game and the games nested in
+    // its balls, see `CouplingSettings`.
+    fn couple(&mut self) -> () {
+        if self.levels.is_empty() { return; }
+
+        let coupling = self.levels[0].coupling.clone();
+
+        if coupling.host_hit_jolt > 0.0 {
+            let hit: Vec<u32> = self.game.events.iter().filter_map(|event| match *event {
+                GameEvent::PaddleHit { ball, .. } => Some(ball),
+                _ => None,
+            }).collect();
+
+            for (id, inner) in self.inner.iter_mut() {
+                if hit.contains(id) {
+                    inner.game.jolt_balls(coupling.host_hit_jolt);
+                }
+            }
+        }
+
+        let mut misses = vec![];
+
+        for (id, inner) in self.inner.iter() {
+            for event in inner.game.events.iter() {
+                if let GameEvent::Miss { side, .. } = *event {
+                    misses.push((*id, side));
+                }
+            }
+        }
+
+        for (id, side) in misses {
+            if coupling.miss_speed_up != 1.0 {
+                self.game.speed_up_ball(id, coupling.miss_speed_up);
+            }
+
+            if coupling.miss_ends_host_rally {
+                self.game.end_rally(id, side);
+            }
+        }
+
+        for (_, inner) in self.inner.iter_mut() {
+            inner.couple();
+        }
+    }
+
+    // Every game in the nest, outermost first.
+    pub fn games(&self) -> Vec<&Game> {
+        let mut games = vec![&self.game];
+
+        for (_, inner) in self.inner.iter() {
+            games.append(&mut inner.games());
+        }
+
+        return games;
+    }
+
+    // Every game in the nest along with its path, as for `events`,
+    // outermost first.
+    pub fn games_mut(&mut self) -> Vec<(Vec<u32>, &mut Game)> {
+        let mut games = vec![(vec![], &mut self.game)];
+
+        for (id, inner) in self.inner.iter_mut() {
+            for (mut path, game) in inner.games_mut() {
+                path.insert(0, *id);
+                games.push((path, game));
+            }
+        }
+
+        return games;
+    }
+
+    // The events from the last update of every game in the nest, tagged
+    // with the path to the game: the ids of the balls it is nested in,
+    // outermost first. The outer game's path is empty.
+    pub fn events(&self) -> Vec<(Vec<u32>, &GameEvent)> {
+        let mut events: Vec<(Vec<u32>, &GameEvent)> = self.game.events.iter()
+            .map(|event| (vec![], event))
+            .collect();
+
+        for (id, inner) in self.inner.iter() {
+            for (mut path, event) in inner.events() {
+                path.insert(0, *id);
+                events.push((path, event));
+            }
+        }
+
+        return events;
+    }
+
+    // Turn the visual effects on or off for every game, including the ones
+    // that are yet to be created.
+    pub fn set_effects(&mut self, settings: &FxSettings) -> () {
+        self.game.fx.settings = settings.clone();
+
+        for level in self.levels.iter_mut() {
+            level.effects = settings.clone();
+        }
+
+        for (_, inner) in self.inner.iter_mut() {
+            inner.set_effects(settings);
+        }
+    }
+
+    fn level_up(&mut self, together_ready: bool) -> () {
+        let ready = if self.game.levels_up_together() {
+            together_ready
+        } else {
+            self.game.level_up_ready()
+        };
+
+        if ready {
+            self.game.next_color();
+            self.sync_inner();
+        }
+
+        for (_, inner) in self.inner.iter_mut() {
+            inner.level_up(together_ready);
+        }
+    }
+
+    // Keep one inner nest per ball. Balls that have left play take their
+    // inner game with them and newly served balls get a fresh one.
+    fn sync_inner(&mut self) -> () {
+        if self.levels.is_empty() { return; }
+
+        let balls: Vec<u32> = self.game.balls.iter().map(|ball| ball.id).collect();
+
+        self.inner.retain(|(id, _)| balls.contains(id));
+
+        for ball in balls {
+            if self.inner.iter().any(|(id, _)| *id == ball) { continue; }
+
+            let mut inner = Nest::seeded(&self.levels, self.game.seed());
+
+            if self.game.state == GameStates::Playing {
+                inner.start();
+            }
+
+            self.inner.push((ball, inner));
+        }
+    }
+}
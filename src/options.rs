@@ -0,0 +1,275 @@
+use std::env;
+
+// Things to do instead of playing.
+#[derive(Debug)]
+pub enum Command {
+    // Print the rankings, for one mode or all of them.
+    Leaderboard(Option<String>),
+    // Print a player's profile.
+    Profile(String),
+    // Print a tournament's bracket and standings.
+    Tournament(String),
+    // Serve training environments over stdin and stdout, see `env::serve`.
+    Gym,
+    // Play two external bots against each other without a window.
+    BotMatch(String, String),
+}
+
+// Command line options. Anything not given falls back to the values in the
+// constants profiles.
+#[derive(Debug, Default)]
+pub struct Options {
+    // Maximum number of balls per game for multi ball mode.
+    pub balls: Option<usize>,
+    // Arena files, one per nesting level starting with the outer game.
+    pub arenas: Vec<String>,
+    // Paddles on all four sides of both games.
+    pub four_players: bool,
+    // Extra palette files, added to the built in themes.
+    pub palettes: Vec<String>,
+    // Name of the theme to start with.
+    pub theme: Option<String>,
+    // Sprite set file to draw the ball, paddles and field with.
+    pub sprites: Option<String>,
+    // Start with trails, flashes, particles and screen shake turned off.
+    pub no_effects: bool,
+    // Initial window size, otherwise the field size or whatever fits on
+    // the screen.
+    pub window_size: Option<(u32, u32)>,
+    pub fullscreen: bool,
+    // Coupling rule set between the inner game and the outer ball.
+    pub rules: Option<String>,
+    // Files to write the match stats to when the game is closed, as JSON
+    // or CSV depending on the extension.
+    pub stats_files: Vec<String>,
+    pub command: Option<Command>,
+    // Profile names in side order, left, right, top and bottom. Their
+    // profiles get the result when the match is over.
+    pub players: Vec<String>,
+    // Tournament to play. A new one is started when players and a format
+    // are given, otherwise the saved one is picked up where it was left.
+    pub tournament: Option<String>,
+    // single, double or round-robin.
+    pub format: Option<String>,
+    // Points needed to win a match, matches go on forever without it.
+    pub first_to: Option<u32>,
+    // Rule set to play by, see `modes.rs`.
+    pub mode: Option<String>,
+    // Misses allowed in survival and co-op.
+    pub lives: Option<u32>,
+    // How long time attack lasts, in seconds.
+    pub time: Option<u64>,
+    // Drills to work through in practice mode, see `practice.rs`.
+    pub drills: Option<String>,
+    // Campaign file to play through against the computer, see
+    // `campaign.rs`.
+    pub campaign: Option<String>,
+    // Handicaps as side name and `Handicap::parse` spec.
+    pub handicaps: Vec<(String, String)>,
+    // Sides played with a single key.
+    pub one_button: Vec<String>,
+    // Sides with aim assist, and how strong it is.
+    pub aim_assist: Vec<(String, f64)>,
+    // How fast the whole game runs, 1.0 being normal speed.
+    pub game_speed: Option<f64>,
+    // Turn on the developer keys and console, see `dev.rs` and
+    // `console.rs`.
+    pub dev: bool,
+    // Console commands to run at startup.
+    pub console_script: Option<String>,
+    // Changes to the constants, see `config.rs`. Picked up again whenever
+    // the file is saved, like palettes, key bindings and sounds.
+    pub config: Option<String>,
+    // Which keys move the paddles, see `Bindings`.
+    pub keys: Option<String>,
+    // Paddles played by bot scripts, as side name and script file.
+    pub bots: Vec<(String, String)>,
+    // Paddles played by other programs, as side names and the command to
+    // start the program, see `external.rs`.
+    pub bot_processes: Vec<(String, String)>,
+    // How long to wait for an external bot every tick, in ms.
+    pub bot_timeout: Option<u64>,
+    // Matches to play in a bot match.
+    pub matches: Option<u32>,
+    // Settings file for the training environments.
+    pub env_settings: Option<String>,
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        let mut options = Options::default();
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "leaderboard" => {
+                    options.command = Some(Command::Leaderboard(args.next()));
+                },
+                "profile" => {
+                    match args.next() {
+                        Some(name) => options.command = Some(Command::Profile(name)),
+                        None => println!("profile needs a player name"),
+                    }
+                },
+                "tournament" => {
+                    match args.next() {
+                        Some(name) => options.command = Some(Command::Tournament(name)),
+                        None => println!("tournament needs a tournament name"),
+                    }
+                },
+                "gym" => {
+                    options.command = Some(Command::Gym);
+                },
+                "bot-match" => {
+                    match (args.next(), args.next()) {
+                        (Some(left), Some(right)) => options.command = Some(Command::BotMatch(left, right)),
+                        _ => println!("bot-match needs two bot commands"),
+                    }
+                },
+                "--players" => {
+                    options.players = args.next()
+                        .map(|players| players.split(',').map(|name| name.trim().to_string()).collect())
+                        .unwrap_or_default();
+                },
+                "--balls" => {
+                    options.balls = args.next().and_then(|n| n.parse().ok());
+                },
+                "--arena" => {
+                    options.arenas.extend(args.next());
+                },
+                "--four-players" => {
+                    options.four_players = true;
+                },
+                "--palette" => {
+                    options.palettes.extend(args.next());
+                },
+                "--theme" => {
+                    options.theme = args.next();
+                },
+                "--sprites" => {
+                    options.sprites = args.next();
+                },
+                "--no-effects" => {
+                    options.no_effects = true;
+                },
+                "--window-size" => {
+                    options.window_size = args.next().and_then(|size| parse_size(&size));
+                },
+                "--fullscreen" => {
+                    options.fullscreen = true;
+                },
+                "--rules" => {
+                    options.rules = args.next();
+                },
+                "--export-stats" => {
+                    options.stats_files.extend(args.next());
+                },
+                "--tournament" => {
+                    options.tournament = args.next();
+                },
+                "--format" => {
+                    options.format = args.next();
+                },
+                "--bot" => {
+                    match args.next().as_ref().and_then(|bot| bot.split_once('=')) {
+                        Some((side, path)) => options.bots.push((side.to_string(), path.to_string())),
+                        None => println!("--bot needs a side and a script, like right=bots/follow.bot"),
+                    }
+                },
+                "--bot-process" => {
+                    match args.next().as_ref().and_then(|bot| bot.split_once('=')) {
+                        Some((sides, command)) => options.bot_processes.push((sides.to_string(), command.to_string())),
+                        None => println!("--bot-process needs sides and a command, like right=bots/reference.py"),
+                    }
+                },
+                "--bot-timeout" => {
+                    options.bot_timeout = args.next().and_then(|ms| ms.parse().ok());
+                },
+                "--matches" => {
+                    options.matches = args.next().and_then(|n| n.parse().ok());
+                },
+                "--env-settings" => {
+                    options.env_settings = args.next();
+                },
+                "--first-to" => {
+                    options.first_to = args.next().and_then(|n| n.parse().ok());
+                },
+                "--mode" => {
+                    options.mode = args.next();
+                },
+                "--lives" => {
+                    options.lives = args.next().and_then(|n| n.parse().ok());
+                },
+                "--time" => {
+                    options.time = args.next().and_then(|n| n.parse().ok());
+                },
+                "--drills" => {
+                    options.drills = args.next();
+                },
+                "--campaign" => {
+                    options.campaign = args.next();
+                },
+                "--handicap" => {
+                    match args.next().as_ref().and_then(|handicap| handicap.split_once('=')) {
+                        Some((side, spec)) => options.handicaps.push((side.to_string(), spec.to_string())),
+                        None => println!("--handicap needs a side and handicaps, like left=paddle:1.5,speed:1.2"),
+                    }
+                },
+                "--one-button" => {
+                    match args.next() {
+                        Some(side) => options.one_button.push(side),
+                        None => println!("--one-button needs a side"),
+                    }
+                },
+                "--aim-assist" => {
+                    match args.next() {
+                        Some(assist) => {
+                            let (side, strength) = match assist.split_once('=') {
+                                Some((side, strength)) => (side.to_string(), strength.parse().unwrap_or(0.5)),
+                                None => (assist, 0.5),
+                            };
+
+                            options.aim_assist.push((side, strength));
+                        },
+                        None => println!("--aim-assist needs a side"),
+                    }
+                },
+                "--game-speed" => {
+                    options.game_speed = args.next().and_then(|n| n.parse().ok());
+                },
+                "--dev" => {
+                    options.dev = true;
+                },
+                "--console-script" => {
+                    options.console_script = args.next();
+                },
+                "--config" => {
+                    options.config = args.next();
+                },
+                "--keys" => {
+                    options.keys = args.next();
+                },
+                _ => {
+                    println!("Ignoring unknown argument: {}", arg);
+                }
+            }
+        }
+
+        return options;
+    }
+}
+
+// Parses sizes like `1280x720`.
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let mut parts = size.split('x');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(width), Some(height), None) => {
+            match (width.parse(), height.parse()) {
+                (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width, height)),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
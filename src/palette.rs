@@ -0,0 +1,215 @@
+use std::fs;
+
+use toml;
+
+use constants::{Color};
+
+pub type Rgba = [u8; 4];
+
+// Maps each color role to an actual color. Palette files only need to set
+// the roles they want to change, anything missing comes from the default
+// palette.
+//
+//     name = "sunset"
+//     background = [20, 10, 30, 255]
+//     levels = [[255, 94, 58, 255], [255, 149, 0, 255], [255, 204, 0, 255], [255, 255, 255, 255]]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    pub name: String,
+    pub background: Rgba,
+    pub ball: Rgba,
+    pub border: Rgba,
+    pub portal: Rgba,
+    pub bonus: Rgba,
+    pub penalty: Rgba,
+    pub speed_up: Rgba,
+    pub slow_down: Rgba,
+    // Paddle colors for each level, see `Color::Level`.
+    pub levels: Vec<Rgba>,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            name: "default".to_string(),
+            background: [0, 13, 40, 255],
+            ball: [253, 184, 119, 255],
+            border: [255, 255, 255, 255],
+            portal: [253, 184, 119, 255],
+            bonus: [70, 184, 135, 255],
+            penalty: [221, 83, 134, 255],
+            speed_up: [253, 184, 119, 255],
+            slow_down: [7, 137, 199, 255],
+            levels: vec![
+                [221, 83, 134, 255],
+                [70, 184, 135, 255],
+                [7, 137, 199, 255],
+            ],
+        }
+    }
+}
+
+impl Palette {
+    pub fn load(path: &str) -> Result<Palette, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        let palette: Palette = toml::from_str(&contents)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        if palette.levels.is_empty() {
+            return Err(format!("{}: a palette needs at least one level color", path));
+        }
+
+        Ok(palette)
+    }
+
+    // Built from the Okabe-Ito colors, which stay distinguishable with the
+    // common forms of color blindness.
+    pub fn colorblind() -> Palette {
+        Palette {
+            name: "colorblind".to_string(),
+            background: [0, 0, 0, 255],
+            ball: [240, 228, 66, 255],
+            border: [255, 255, 255, 255],
+            portal: [204, 121, 167, 255],
+            bonus: [0, 158, 115, 255],
+            penalty: [213, 94, 0, 255],
+            speed_up: [230, 159, 0, 255],
+            slow_down: [86, 180, 233, 255],
+            levels: vec![
+                [230, 159, 0, 255],
+                [86, 180, 233, 255],
+                [204, 121, 167, 255],
+            ],
+        }
+    }
+
+    pub fn high_contrast() -> Palette {
+        Palette {
+            name: "high-contrast".to_string(),
+            background: [0, 0, 0, 255],
+            ball: [255, 255, 255, 255],
+            border: [255, 255, 255, 255],
+            portal: [255, 0, 255, 255],
+            bonus: [0, 255, 0, 255],
+            penalty: [255, 0, 0, 255],
+            speed_up: [255, 255, 0, 255],
+            slow_down: [0, 255, 255, 255],
+            levels: vec![
+                [255, 255, 0, 255],
+                [0, 255, 255, 255],
+                [255, 0, 255, 255],
+            ],
+        }
+    }
+
+    pub fn builtin() -> Vec<Palette> {
+        vec![Palette::default(), Palette::colorblind(), Palette::high_contrast()]
+    }
+
+    pub fn rgba(&self, color: &Color) -> Rgba {
+        match color {
+            Color::Background => self.background,
+            Color::Ball => self.ball,
+            Color::Border => self.border,
+            Color::Portal => self.portal,
+            Color::Bonus => self.bonus,
+            Color::Penalty => self.penalty,
+            Color::SpeedUp => self.speed_up,
+            Color::SlowDown => self.slow_down,
+            Color::Level(level) => self.levels[level % self.levels.len()],
+        }
+    }
+}
+
+// The palettes that can be switched between while playing.
+#[derive(Debug)]
+pub struct Themes {
+    pub palettes: Vec<Palette>,
+    pub current: usize,
+}
+
+impl Themes {
+    pub fn new(palettes: Vec<Palette>) -> Themes {
+        Themes { palettes: palettes, current: 0 }
+    }
+
+    pub fn current(&self) -> &Palette {
+        &self.palettes[self.current]
+    }
+
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.palettes.iter().position(|palette| palette.name == name) {
+            Some(index) => {
+                self.current = index;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn next(&mut self) -> &Palette {
+        self.current = (self.current + 1) % self.palettes.len();
+
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    fn load(name: &str, contents: &str) -> Result<Palette, String> {
+        let path = env::temp_dir().join(format!("popongong-{}-{}.toml", std::process::id(), name));
+
+        fs::write(&path, contents).unwrap();
+
+        let palette = Palette::load(path.to_str().unwrap());
+
+        fs::remove_file(&path).unwrap();
+
+        return palette;
+    }
+
+    #[test]
+    fn fills_in_missing_roles_from_the_default() {
+        let palette = Palette::load("palettes/sunset.toml").unwrap();
+
+        assert_eq!(palette.name, "sunset");
+        assert_eq!(palette.border, [255, 236, 210, 200]);
+        assert_eq!(palette.bonus, Palette::default().bonus);
+        assert_eq!(palette.levels.len(), 5);
+    }
+
+    #[test]
+    fn rejects_broken_palettes() {
+        assert!(load("no-levels", "levels = []\n").is_err());
+        assert!(load("short", "ball = [1, 2, 3]\n").is_err());
+        assert!(load("too-bright", "ball = [256, 0, 0, 255]\n").is_err());
+        assert!(load("fine", "ball = [255, 0, 0, 255]\n").is_ok());
+    }
+
+    #[test]
+    fn levels_wrap_around() {
+        let palette = Palette::default();
+
+        assert_eq!(palette.rgba(&Color::Level(0)), palette.levels[0]);
+        assert_eq!(palette.rgba(&Color::Level(4)), palette.levels[1]);
+    }
+
+    #[test]
+    fn themes_cycle_and_select_by_name() {
+        let mut themes = Themes::new(Palette::builtin());
+
+        assert_eq!(themes.next().name, "colorblind");
+        assert_eq!(themes.next().name, "high-contrast");
+        assert_eq!(themes.next().name, "default");
+        assert!(themes.select("high-contrast"));
+        assert!(!themes.select("missing"));
+        assert_eq!(themes.current().name, "high-contrast");
+    }
+}
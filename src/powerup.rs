@@ -0,0 +1,214 @@
+use rand::{Rng};
+
+use vector::Vector;
+
+use component::{Component, FRect, Part, Side};
+
+use constants::{Color};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PowerUpKind {
+    // Collector's paddle grows.
+    Grow,
+    // Opponent's paddle shrinks.
+    Shrink,
+    // All balls in the game speed up or slow down.
+    SpeedUp,
+    SlowDown,
+    // A temporary extra ball is served.
+    ExtraBall,
+    // Opponent's up/down controls are swapped.
+    ReverseControls,
+    // Collector's wall absorbs one miss.
+    Shield,
+}
+
+impl PowerUpKind {
+    pub fn color(&self) -> Color {
+        match self {
+            PowerUpKind::Grow => Color::Bonus,
+            PowerUpKind::Shrink => Color::Penalty,
+            PowerUpKind::SpeedUp => Color::SpeedUp,
+            PowerUpKind::SlowDown => Color::SlowDown,
+            PowerUpKind::ExtraBall => Color::Bonus,
+            PowerUpKind::ReverseControls => Color::Penalty,
+            PowerUpKind::Shield => Color::Bonus,
+        }
+    }
+
+    // The paddle an effect applies to, given who collected it.
+    pub fn target(&self, collector: Side) -> Side {
+        match self {
+            PowerUpKind::Shrink | PowerUpKind::ReverseControls => collector.opponent(),
+            _ => collector,
+        }
+    }
+}
+
+// Per profile power up settings. Each kind can be switched on or off and
+// nothing spawns when `spawn_interval_ms` is 0.
+#[derive(Debug, Clone)]
+pub struct PowerUpSettings {
+    pub grow: bool,
+    pub shrink: bool,
+    pub speed_up: bool,
+    pub slow_down: bool,
+    pub extra_ball: bool,
+    pub reverse_controls: bool,
+    pub shield: bool,
+    pub spawn_interval_ms: u64,
+    pub duration_ms: u64,
+    pub max_on_field: usize,
+    pub size: u32,
+}
+
+impl PowerUpSettings {
+    pub fn enabled(&self) -> Vec<PowerUpKind> {
+        let mut kinds = vec![];
+
+        if self.grow { kinds.push(PowerUpKind::Grow); }
+        if self.shrink { kinds.push(PowerUpKind::Shrink); }
+        if self.speed_up { kinds.push(PowerUpKind::SpeedUp); }
+        if self.slow_down { kinds.push(PowerUpKind::SlowDown); }
+        if self.extra_ball { kinds.push(PowerUpKind::ExtraBall); }
+        if self.reverse_controls { kinds.push(PowerUpKind::ReverseControls); }
+        if self.shield { kinds.push(PowerUpKind::Shield); }
+
+        return kinds;
+    }
+}
+
+// A power up waiting in the field to be collected.
+#[derive(Debug, Clone)]
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+    pub pos: Vector,
+    pub rect: FRect,
+    pub color: Color,
+}
+
+impl PowerUp {
+    // Spawn a random enabled power up somewhere in the middle half of the
+    // field, away from the paddles.
+    pub fn random<R: Rng>(rng: &mut R, settings: &PowerUpSettings, field_width: i32, field_height: i32) -> Option<PowerUp> {
+        let kinds = settings.enabled();
+        let kind = match rng.choose(&kinds) {
+            Some(kind) => *kind,
+            None => return None,
+        };
+
+        let size = settings.size as i32;
+        let x = rng.gen_range(field_width / 4, (field_width * 3 / 4 - size).max(field_width / 4 + 1));
+        let y = rng.gen_range(0, (field_height - size).max(1));
+
+        Some(PowerUp {
+            kind: kind,
+            pos: Vector { x: x as f64, y: y as f64 },
+            rect: FRect::new(x as f64, y as f64, settings.size as f64, settings.size as f64),
+            color: kind.color(),
+        })
+    }
+
+    pub fn center(&self) -> Vector {
+        Vector {
+            x: self.pos.x + self.rect.width() / 2.0,
+            y: self.pos.y + self.rect.height() / 2.0,
+        }
+    }
+
+    pub fn components(&mut self, origin: &Vector) -> Vec<Component<'_>> {
+        self.rect.set_x(self.pos.x + origin.x);
+        self.rect.set_y(self.pos.y + origin.y);
+
+        return vec![
+            (&self.rect, &self.color, Part::Plain),
+        ];
+    }
+}
+
+// A collected power up that is currently in effect.
+#[derive(Debug, Clone)]
+pub struct Effect {
+    pub kind: PowerUpKind,
+    pub side: Side,
+    pub duration_ms: u64,
+    pub remaining_ms: u64,
+    // The temporary ball served by an `ExtraBall` effect.
+    pub ball_id: Option<u32>,
+    pub timer: FRect,
+    // Drawn along the wall while a `Shield` is up.
+    pub wall: FRect,
+    pub color: Color,
+}
+
+impl Effect {
+    pub fn new(kind: PowerUpKind, side: Side, duration_ms: u64) -> Effect {
+        Effect {
+            kind: kind,
+            side: side,
+            duration_ms: duration_ms,
+            remaining_ms: duration_ms,
+            ball_id: None,
+            timer: FRect::new(0.0, 0.0, 1.0, 1.0),
+            wall: FRect::new(0.0, 0.0, 1.0, 1.0),
+            color: kind.color(),
+        }
+    }
+
+    pub fn update(&mut self, delta_ms: u64) -> () {
+        self.remaining_ms = self.remaining_ms.saturating_sub(delta_ms);
+    }
+
+    pub fn expired(&self) -> bool {
+        self.remaining_ms == 0
+    }
+
+    // The timer is a bar along the top of the affected paddle's half of the
+    // field that shrinks as the effect runs out. `slot` stacks the bars of
+    // several effects on the same side.
+    pub fn components(
+        &mut self,
+        origin: &Vector,
+        field_width: i32,
+        field_height: i32,
+        bar_height: u32,
+        slot: usize
+    ) -> Vec<Component<'_>> {
+        let max_width = (field_width / 2 - 20).max(1) as f64;
+        let width = (max_width * self.remaining_ms as f64 / self.duration_ms.max(1) as f64).max(1.0);
+
+        let x = match self.side {
+            Side::One | Side::Three => 10.0,
+            Side::Two | Side::Four => field_width as f64 - 10.0 - width,
+        };
+        let y = 5.0 + (slot as u32 * (bar_height + 2)) as f64;
+
+        self.timer.set_x(x + origin.x);
+        self.timer.set_y(y + origin.y);
+        self.timer.set_width(width);
+        self.timer.set_height(bar_height as f64);
+
+        let mut components = vec![
+            (&self.timer, &self.color, Part::Plain),
+        ];
+
+        if self.kind == PowerUpKind::Shield {
+            let thickness = bar_height as i32;
+            let (x, y, width, height) = match self.side {
+                Side::One => (0, 0, thickness, field_height),
+                Side::Two => (field_width - thickness, 0, thickness, field_height),
+                Side::Three => (0, 0, field_width, thickness),
+                Side::Four => (0, field_height - thickness, field_width, thickness),
+            };
+
+            self.wall.set_x(x as f64 + origin.x);
+            self.wall.set_y(y as f64 + origin.y);
+            self.wall.set_width(width as f64);
+            self.wall.set_height(height as f64);
+
+            components.push((&self.wall, &self.color, Part::Plain));
+        }
+
+        return components;
+    }
+}
@@ -0,0 +1,328 @@
+use std::fs;
+
+use toml;
+
+use vector::{Vector};
+
+use component::{Side};
+
+use events::{GameEvent};
+
+use game::{Game};
+
+use nest::{Nest};
+
+// Shots that are neither returned nor missed by then, say a ball stuck
+// bouncing between obstacles, count as missed.
+const SHOT_TIMEOUT_MS: u64 = 10_000;
+
+// A set of shots fired from the practice wall at the left paddle, loaded
+// from a toml file. Angles and heights are used in turn, one per ball.
+//
+//     [[drills]]
+//     name = "steep returns"
+//     balls = 10
+//     speed = 1.0            # times the top ball speed
+//     angles = [-30, 30]     # degrees off horizontal, down being positive
+//     heights = [0.2, 0.8]   # where on the wall, 0 is the top and 1 the bottom
+//     interval_ms = 1500
+//     depth = 0              # 0 for the outer game, 1 for the one inside it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Drill {
+    pub name: String,
+    pub balls: u32,
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    #[serde(default = "default_angles")]
+    pub angles: Vec<f64>,
+    #[serde(default = "default_heights")]
+    pub heights: Vec<f64>,
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default)]
+    pub depth: usize,
+}
+
+fn default_speed() -> f64 { 1.0 }
+
+fn default_angles() -> Vec<f64> { vec![0.0] }
+
+fn default_heights() -> Vec<f64> { vec![0.5] }
+
+fn default_interval_ms() -> u64 { 1500 }
+
+#[derive(Debug, Deserialize)]
+struct DrillFile {
+    drills: Vec<Drill>,
+}
+
+impl Drill {
+    pub fn load(path: &str) -> Result<Vec<Drill>, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let file: DrillFile = toml::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+
+        if file.drills.iter().any(|drill| drill.angles.is_empty() || drill.heights.is_empty()) {
+            return Err(format!("{}: every drill needs at least one angle and height", path));
+        }
+
+        Ok(file.drills)
+    }
+}
+
+// How a drill went. Only returns off either end of the paddle count towards
+// the accuracy, since those are the ones the drills are there to train.
+#[derive(Debug, Clone, Default)]
+pub struct DrillScore {
+    pub fired: u32,
+    pub returned: u32,
+    pub off_end: u32,
+}
+
+impl DrillScore {
+    // Percentage of the balls fired that came back off an end.
+    pub fn accuracy(&self) -> u32 {
+        if self.fired == 0 { return 0; }
+
+        self.off_end * 100 / self.fired
+    }
+}
+
+// Runs the drills one after another, firing the next ball once the last one
+// has been returned or missed.
+#[derive(Debug, Clone)]
+pub struct Practice {
+    drills: Vec<Drill>,
+    scores: Vec<DrillScore>,
+    current: usize,
+    // The ball fired for the current shot, until it is returned or missed.
+    in_flight: Option<u32>,
+    timer_ms: u64,
+}
+
+impl Practice {
+    pub fn new(drills: Vec<Drill>) -> Practice {
+        Practice {
+            scores: vec![DrillScore::default(); drills.len()],
+            drills: drills,
+            current: 0,
+            in_flight: None,
+            timer_ms: 0,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.current >= self.drills.len()
+    }
+
+    // Call before every update of the nest while it is being played.
+    pub fn update(&mut self, nest: &mut Nest, delta_ms: u64) -> () {
+        if self.finished() { return; }
+
+        let depth = self.drills[self.current].depth;
+        let game = match nest.games_mut().into_iter().find(|(path, _)| path.len() == depth) {
+            Some((_, game)) => game,
+            None => return,
+        };
+
+        self.timer_ms = self.timer_ms + delta_ms;
+
+        if let Some(id) = self.in_flight {
+            self.score_shot(game, id);
+            return;
+        }
+
+        if self.timer_ms < self.drills[self.current].interval_ms { return; }
+
+        self.timer_ms = 0;
+
+        if self.scores[self.current].fired >= self.drills[self.current].balls {
+            self.current = self.current + 1;
+            return;
+        }
+
+        self.fire(game);
+    }
+
+    fn score_shot(&mut self, game: &Game, id: u32) -> () {
+        let mut outcome = None;
+
+        for event in game.events.iter() {
+            match *event {
+                GameEvent::PaddleHit { side: Side::One, ball, ref pos, .. } if ball == id => {
+                    outcome = Some(Some(game.hit_paddle_end(Side::One, pos)));
+                },
+                GameEvent::Miss { side: Side::One, ball, .. } if ball == id => {
+                    outcome = Some(None);
+                },
+                _ => {}
+            }
+        }
+
+        if outcome.is_none() && self.timer_ms >= SHOT_TIMEOUT_MS {
+            outcome = Some(None);
+        }
+
+        let score = &mut self.scores[self.current];
+
+        match outcome {
+            Some(Some(off_end)) => {
+                score.returned = score.returned + 1;
+                if off_end { score.off_end = score.off_end + 1; }
+            },
+            Some(None) => {},
+            None => return,
+        }
+
+        self.in_flight = None;
+        self.timer_ms = 0;
+    }
+
+    // Fire the game's first ball from the wall, at the next angle and height
+    // of the current drill.
+    fn fire(&mut self, game: &mut Game) -> () {
+        let drill = &self.drills[self.current];
+        let shot = self.scores[self.current].fired as usize;
+        let angle = drill.angles[shot % drill.angles.len()].to_radians();
+        let height = drill.heights[shot % drill.heights.len()].clamp(0.0, 1.0);
+
+        let size = game.size();
+        let wall = game.constants().paddle_width as f64;
+        let max_speed = game.constants().max_ball_speed.clone();
+        let speed = max_speed.x * drill.speed;
+
+        let ball = match game.balls.first_mut() {
+            Some(ball) => ball,
+            None => return,
+        };
+
+        ball.pos = Vector {
+            x: size.x - wall - ball.width as f64 - 1.0,
+            y: (size.y * height - ball.height as f64 / 2.0).max(0.0).min(size.y - ball.height as f64),
+        };
+        ball.velocity = Vector { x: -speed, y: if angle < 0.0 { -1.0 } else { 1.0 } };
+        ball.set_velocity_y_magnitude((speed * angle.tan()).abs().min(max_speed.y));
+        ball.last_hit = None;
+
+        self.in_flight = Some(ball.id);
+        self.scores[self.current].fired = self.scores[self.current].fired + 1;
+    }
+
+    // Where the drills are up to, for the HUD.
+    pub fn status(&self) -> Option<String> {
+        let drill = self.drills.get(self.current)?;
+        let score = &self.scores[self.current];
+
+        Some(format!(
+            "drill {}/{} {}  {}/{}  on the ends {}",
+            self.current + 1,
+            self.drills.len(),
+            drill.name,
+            score.fired,
+            drill.balls,
+            score.off_end
+        ))
+    }
+
+    // A line per drill for the results screen.
+    pub fn results(&self) -> Vec<String> {
+        let mut lines = vec![];
+
+        for (drill, score) in self.drills.iter().zip(self.scores.iter()) {
+            lines.push(format!(
+                "{:<16} returned {:>2}/{:<2} off the ends {:>2}  {:>3}%",
+                drill.name,
+                score.returned,
+                score.fired,
+                score.off_end,
+                score.accuracy()
+            ));
+        }
+
+        let fired: u32 = self.scores.iter().map(|score| score.fired).sum();
+        let off_end: u32 = self.scores.iter().map(|score| score.off_end).sum();
+
+        if let Some(accuracy) = (off_end * 100).checked_div(fired) {
+            lines.push(String::new());
+            lines.push(format!("overall accuracy {}%", accuracy));
+        }
+
+        return lines;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use constants::{OUTER_CONSTANTS};
+
+    fn load(name: &str, contents: &str) -> Result<Vec<Drill>, String> {
+        let path = env::temp_dir().join(format!("popongong-{}-{}.toml", std::process::id(), name));
+
+        fs::write(&path, contents).unwrap();
+
+        let drills = Drill::load(path.to_str().unwrap());
+
+        fs::remove_file(&path).unwrap();
+
+        return drills;
+    }
+
+    #[test]
+    fn loads_drills_with_defaults() {
+        assert!(!Drill::load("drills/ends.toml").unwrap().is_empty());
+
+        let drills = load("defaults", "[[drills]]\nname = \"plain\"\nballs = 3\n").unwrap();
+
+        assert_eq!(drills[0].speed, 1.0);
+        assert_eq!(drills[0].angles, vec![0.0]);
+        assert_eq!(drills[0].heights, vec![0.5]);
+        assert_eq!(drills[0].depth, 0);
+    }
+
+    #[test]
+    fn rejects_drills_with_nothing_to_fire() {
+        assert!(load("no-angles", "[[drills]]\nname = \"a\"\nballs = 3\nangles = []\n").is_err());
+        assert!(load("no-heights", "[[drills]]\nname = \"a\"\nballs = 3\nheights = []\n").is_err());
+        assert!(load("no-balls", "[[drills]]\nname = \"a\"\n").is_err());
+        assert!(load("no-drills", "").is_err());
+    }
+
+    #[test]
+    fn accuracy_counts_returns_off_the_ends() {
+        assert_eq!(DrillScore::default().accuracy(), 0);
+        assert_eq!(DrillScore { fired: 4, returned: 3, off_end: 1 }.accuracy(), 25);
+    }
+
+    #[test]
+    fn fires_each_ball_of_a_drill_at_the_paddle() {
+        let drill = Drill {
+            name: "steep".to_string(),
+            balls: 1,
+            speed: 1.0,
+            angles: vec![-30.0],
+            heights: vec![0.25],
+            interval_ms: 100,
+            depth: 0,
+        };
+        let mut constants = OUTER_CONSTANTS;
+
+        constants.practice_wall = true;
+
+        let mut nest = Nest::new(&[constants]);
+        let mut practice = Practice::new(vec![drill]);
+
+        nest.start();
+        practice.update(&mut nest, 100);
+
+        let ball = &nest.game.balls[0];
+
+        assert!(ball.velocity.x < 0.0 && ball.velocity.y < 0.0);
+        assert!(ball.center().y < nest.game.size().y / 2.0);
+        assert_eq!(practice.status().unwrap(), "drill 1/1 steep  1/1  on the ends 0");
+        assert!(!practice.finished());
+    }
+}
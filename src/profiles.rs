@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+use component::{Side};
+
+use stats::{MatchStats};
+
+// One finished match from a player's point of view.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub mode: String,
+    // Seconds since the unix epoch.
+    pub played_at: u64,
+    pub opponents: Vec<String>,
+    // A point is scored every time another player misses.
+    pub points: u32,
+    pub opponent_points: u32,
+    pub won: bool,
+    pub hits: u32,
+    pub misses: u32,
+    pub longest_rally: u32,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Best {
+    pub points: u32,
+    pub hits: u32,
+    pub longest_rally: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub history: Vec<MatchRecord>,
+    // Best results per mode.
+    #[serde(default)]
+    pub best: HashMap<String, Best>,
+    // Stages cleared per campaign, by campaign name.
+    #[serde(default)]
+    pub campaigns: HashMap<String, usize>,
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Profile {
+        Profile { name: name.to_string(), history: vec![], best: HashMap::new(), campaigns: HashMap::new() }
+    }
+
+    // How many stages of `campaign` have been cleared, which is also the
+    // index of the first one still to play.
+    pub fn cleared(&self, campaign: &str) -> usize {
+        self.campaigns.get(campaign).cloned().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, record: MatchRecord) -> () {
+        {
+            let best = self.best.entry(record.mode.clone()).or_default();
+
+            best.points = best.points.max(record.points);
+            best.hits = best.hits.max(record.hits);
+            best.longest_rally = best.longest_rally.max(record.longest_rally);
+        }
+
+        self.history.push(record);
+    }
+
+    fn records<'a>(&'a self, mode: Option<&'a str>) -> impl Iterator<Item = &'a MatchRecord> {
+        self.history.iter().filter(move |record| mode.is_none_or(|mode| record.mode == mode))
+    }
+}
+
+// A row on the leaderboard.
+#[derive(Debug)]
+pub struct Ranking {
+    pub name: String,
+    pub played: u32,
+    pub wins: u32,
+    pub best_points: u32,
+    pub longest_rally: u32,
+}
+
+// Player profiles, one JSON file each in the `profiles` directory under
+// `$XDG_DATA_HOME/popongong`, or `~/.local/share/popongong` if that isn't
+// set.
+#[derive(Debug)]
+pub struct Profiles {
+    pub dir: PathBuf,
+}
+
+impl Profiles {
+    pub fn new() -> Profiles {
+        Profiles { dir: data_dir().join("profiles") }
+    }
+
+    // Loads the profile called `name`, or starts a new one if there isn't
+    // one yet.
+    pub fn load(&self, name: &str) -> Result<Profile, String> {
+        let path = self.path(name)?;
+
+        if !path.exists() {
+            return Ok(Profile::new(name));
+        }
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, profile: &Profile) -> Result<(), String> {
+        let path = self.path(&profile.name)?;
+
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("{}: {}", self.dir.display(), e))?;
+
+        fs::write(&path, serde_json::to_string_pretty(profile).unwrap())
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn all(&self) -> Vec<Profile> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+
+        let mut profiles = vec![];
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+
+            let profile = fs::read_to_string(&path).ok()
+                .and_then(|contents| serde_json::from_str::<Profile>(&contents).ok());
+
+            match profile {
+                Some(profile) => profiles.push(profile),
+                None => println!("Skipping unreadable profile {}", path.display()),
+            }
+        }
+
+        return profiles;
+    }
+
+    // Adds the match to the profile of everyone who played. `players` are
+    // in side order, left, right, top and bottom.
+    pub fn record(&self, players: &[String], stats: &MatchStats, mode: &str) -> Result<(), String> {
+        let sides: Vec<Side> = players.iter().enumerate().map(|(i, _)| Side::all()[i]).collect();
+        let played_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+
+        for (name, side) in players.iter().zip(sides.iter()) {
+            let points = stats.points(*side);
+            let opponent_points = sides.iter()
+                .filter(|other| *other != side)
+                .map(|other| stats.points(*other))
+                .max()
+                .unwrap_or(0);
+            let (hits, misses) = stats.totals(*side);
+
+            let mut profile = self.load(name)?;
+
+            profile.add(MatchRecord {
+                mode: mode.to_string(),
+                played_at: played_at,
+                opponents: players.iter().filter(|other| *other != name).cloned().collect(),
+                points: points,
+                opponent_points: opponent_points,
+                won: points > opponent_points,
+                hits: hits,
+                misses: misses,
+                longest_rally: stats.longest_rally(),
+                duration_ms: stats.duration_ms,
+            });
+
+            self.save(&profile)?;
+        }
+
+        Ok(())
+    }
+
+    // Unlocks the stage after `stage` in `campaign` for the player called
+    // `name`.
+    pub fn clear_stage(&self, name: &str, campaign: &str, stage: usize) -> Result<(), String> {
+        let mut profile = self.load(name)?;
+
+        {
+            let cleared = profile.campaigns.entry(campaign.to_string()).or_insert(0);
+            *cleared = (*cleared).max(stage + 1);
+        }
+
+        self.save(&profile)
+    }
+
+    // Everyone who has played `mode`, or anything if it is `None`, most
+    // wins first.
+    pub fn leaderboard(&self, mode: Option<&str>) -> Vec<Ranking> {
+        let mut rankings: Vec<Ranking> = self.all().iter().filter_map(|profile| {
+            let records: Vec<&MatchRecord> = profile.records(mode).collect();
+
+            if records.is_empty() { return None; }
+
+            Some(Ranking {
+                name: profile.name.clone(),
+                played: records.len() as u32,
+                wins: records.iter().filter(|record| record.won).count() as u32,
+                best_points: records.iter().map(|record| record.points).max().unwrap_or(0),
+                longest_rally: records.iter().map(|record| record.longest_rally).max().unwrap_or(0),
+            })
+        }).collect();
+
+        rankings.sort_by(|a, b| {
+            b.wins.cmp(&a.wins)
+                .then(a.played.cmp(&b.played))
+                .then(b.best_points.cmp(&a.best_points))
+                .then(a.name.cmp(&b.name))
+        });
+
+        return rankings;
+    }
+
+    pub fn leaderboard_lines(&self, mode: Option<&str>) -> Vec<String> {
+        let rankings = self.leaderboard(mode);
+
+        if rankings.is_empty() {
+            return vec!["no matches played yet".to_string()];
+        }
+
+        let mut lines = vec![format!(
+            "{:<4}{:<16}{:>7}{:>7}{:>7}{:>7}",
+            "", "player", "played", "wins", "best", "rally"
+        )];
+
+        for (i, ranking) in rankings.iter().enumerate() {
+            lines.push(format!(
+                "{:<4}{:<16}{:>7}{:>7}{:>7}{:>7}",
+                format!("{}.", i + 1),
+                ranking.name,
+                ranking.played,
+                ranking.wins,
+                ranking.best_points,
+                ranking.longest_rally
+            ));
+        }
+
+        return lines;
+    }
+
+    // Profile names end up in file names, so only allow the safe ones.
+    fn path(&self, name: &str) -> Result<PathBuf, String> {
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+        if !valid {
+            return Err(format!("{}: profile names can only use letters, numbers, - and _", name));
+        }
+
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+}
+
+pub fn data_dir() -> PathBuf {
+    let base = match env::var_os("XDG_DATA_HOME") {
+        Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+            home.join(".local").join("share")
+        }
+    };
+
+    base.join("popongong")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A profiles directory of its own for each test, removed afterwards.
+    fn profiles(test: &str) -> Profiles {
+        let dir = env::temp_dir().join(format!("popongong-{}-{}", std::process::id(), test));
+
+        let _ = fs::remove_dir_all(&dir);
+
+        Profiles { dir: dir }
+    }
+
+    fn record(mode: &str, points: u32, won: bool) -> MatchRecord {
+        MatchRecord {
+            mode: mode.to_string(),
+            played_at: 0,
+            opponents: vec![],
+            points: points,
+            opponent_points: 0,
+            won: won,
+            hits: points * 2,
+            misses: 0,
+            longest_rally: points,
+            duration_ms: 1000,
+        }
+    }
+
+    #[test]
+    fn loads_what_was_saved() {
+        let profiles = profiles("saved");
+
+        let mut profile = profiles.load("ann").unwrap();
+        assert!(profile.history.is_empty());
+
+        profile.add(record("classic", 5, true));
+        profile.add(record("classic", 3, false));
+        profiles.save(&profile).unwrap();
+
+        let loaded = profiles.load("ann").unwrap();
+
+        assert_eq!(loaded.history.len(), 2);
+        assert_eq!(loaded.best["classic"].points, 5);
+        assert_eq!(loaded.best["classic"].hits, 10);
+
+        fs::remove_dir_all(&profiles.dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unsafe_names_and_broken_files() {
+        let profiles = profiles("unsafe");
+
+        assert!(profiles.load("../ann").is_err());
+        assert!(profiles.load("").is_err());
+        assert!(profiles.save(&Profile::new("a/b")).is_err());
+
+        fs::create_dir_all(&profiles.dir).unwrap();
+        fs::write(profiles.dir.join("bob.json"), "{ not json").unwrap();
+
+        assert!(profiles.load("bob").is_err());
+        // The leaderboard skips it instead.
+        assert!(profiles.all().is_empty());
+
+        fs::remove_dir_all(&profiles.dir).unwrap();
+    }
+
+    #[test]
+    fn clearing_an_earlier_stage_keeps_progress() {
+        let profiles = profiles("stages");
+
+        profiles.clear_stage("ann", "nested", 2).unwrap();
+        profiles.clear_stage("ann", "nested", 0).unwrap();
+
+        let profile = profiles.load("ann").unwrap();
+
+        assert_eq!(profile.cleared("nested"), 3);
+        assert_eq!(profile.cleared("other"), 0);
+
+        fs::remove_dir_all(&profiles.dir).unwrap();
+    }
+
+    #[test]
+    fn leaderboard_ranks_by_wins_then_fewest_games() {
+        let profiles = profiles("leaderboard");
+
+        let players = [
+            ("ann", vec![record("classic", 5, true), record("classic", 1, false)]),
+            ("bob", vec![record("classic", 5, true)]),
+            ("cat", vec![record("survival", 9, true), record("survival", 9, true)]),
+        ];
+
+        for (name, records) in players.iter() {
+            let mut profile = Profile::new(name);
+
+            for record in records.iter() {
+                profile.add(record.clone());
+            }
+
+            profiles.save(&profile).unwrap();
+        }
+
+        let names = |mode: Option<&str>| -> Vec<String> {
+            profiles.leaderboard(mode).into_iter().map(|ranking| ranking.name).collect()
+        };
+
+        assert_eq!(names(Some("classic")), vec!["bob", "ann"]);
+        assert_eq!(names(None), vec!["cat", "bob", "ann"]);
+        assert_eq!(names(Some("coop")), Vec::<String>::new());
+
+        fs::remove_dir_all(&profiles.dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::time::SystemTime;
+
+// How often the watched files are looked at, in ms.
+const CHECK_INTERVAL_MS: u64 = 500;
+// How long the result of a reload stays on screen, in ms.
+const MESSAGE_MS: u64 = 5000;
+
+// What to load again when a watched file is saved.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Config,
+    Bindings,
+    // A palette, by where it is in `Themes::palettes`.
+    Palette(usize),
+    Sounds,
+    // A bot script, by where it is in `Controllers::bots`.
+    Bot(usize),
+}
+
+#[derive(Debug)]
+struct Watched {
+    path: String,
+    change: Change,
+    modified: Option<SystemTime>,
+}
+
+// Watches the files the game was started with, so changes to them can be
+// applied without restarting.
+#[derive(Debug, Default)]
+pub struct Reloader {
+    watched: Vec<Watched>,
+    timer_ms: u64,
+    // What happened with the last reload and how much longer to show it.
+    message: Option<(String, u64)>,
+}
+
+impl Reloader {
+    pub fn watch(&mut self, path: &str, change: Change) -> () {
+        self.watched.push(Watched { path: path.to_string(), change: change, modified: modified(path) });
+    }
+
+    // The files saved since the last check, once for each kind of change.
+    pub fn check(&mut self, delta_ms: u64) -> Vec<(Change, String)> {
+        self.message = self.message.take()
+            .map(|(message, left_ms)| (message, left_ms.saturating_sub(delta_ms)))
+            .filter(|(_, left_ms)| *left_ms > 0);
+
+        self.timer_ms = self.timer_ms + delta_ms;
+
+        if self.timer_ms < CHECK_INTERVAL_MS { return vec![]; }
+
+        self.timer_ms = 0;
+
+        let mut changes: Vec<(Change, String)> = vec![];
+
+        for watched in self.watched.iter_mut() {
+            let modified = modified(&watched.path);
+
+            if modified == watched.modified { continue; }
+
+            watched.modified = modified;
+
+            if !changes.iter().any(|(change, _)| *change == watched.change) {
+                changes.push((watched.change.clone(), watched.path.clone()));
+            }
+        }
+
+        return changes;
+    }
+
+    // Show how reloading `path` went for a few seconds. Whatever was loaded
+    // before stays in use when it didn't work.
+    pub fn report(&mut self, path: &str, result: Result<(), String>) -> () {
+        let message = match result {
+            Ok(()) => format!("reloaded {}", path),
+            Err(e) => format!("could not reload {}", e),
+        };
+
+        println!("{}", message);
+        self.message = Some((message, MESSAGE_MS));
+    }
+
+    pub fn status(&self) -> Option<String> {
+        self.message.as_ref().map(|(message, _)| message.clone())
+    }
+}
+
+// When the file at `path` was last saved, if it can be read.
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
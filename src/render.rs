@@ -1,40 +1,310 @@
 extern crate sdl2;
 
-use std::collections::HashMap;
+use sdl2::rect::Rect;
+use sdl2::render::Texture;
+
 use vector::{Vector};
+use camera::{Camera, Transform};
+use display::{Display};
+use nest::{Nest};
+use component::{Component, Part};
 use constants::{Color};
-use game::{Game};
+use palette::{Palette};
+use textures::{Textures};
+use sprites::{Sprites};
 
 pub fn render(
-    outer_game: &mut Game,
-    inner_game: &mut Game,
-    textures: &HashMap<Color, sdl2::render::Texture<'_>>,
+    nest: &mut Nest,
+    camera: &Camera,
+    display: &Display,
+    // Drawn over the field in screen coordinates, like a panel or the HUD.
+    overlay: &[Component],
+    textures: &Textures,
+    sprites: &mut Option<Sprites>,
     canvas: &mut sdl2::render::Canvas<sdl2::video::Window>
 ) -> () {
+    // Clear the letterbox bars too, then keep everything inside the field.
+    canvas.set_clip_rect(None);
     canvas.clear();
+    canvas.set_clip_rect(display.viewport());
+
+    let screen = display.transform();
 
     let outer_origin = Vector { x: 0.0, y: 0.0 };
 
-    for component in outer_game.components(&outer_origin).iter_mut() {
-        canvas.copy(
-            &textures.get(&component.1).unwrap(),
-            None,
-            *component.0
-        ).unwrap();
-    }
+    // The inner game the camera looks at, where it is in the outer game and
+    // how big it is.
+    let focus = nest.inner.first().and_then(|(id, inner)| {
+        nest.game.ball(*id).map(|ball| {
+            let size = inner.game.size();
+
+            (camera.inner_origin(ball, &size), size)
+        })
+    });
+
+    let view = camera.view(focus).then(&screen);
 
-    let inner_origin = Vector {
-        x: outer_game.ball.pos.x + 5.0,
-        y: outer_game.ball.pos.y + 5.0,
-    };
+    render_nest(nest, &outer_origin, camera, &view, textures, sprites, canvas);
 
-    for component in inner_game.components(&inner_origin).iter_mut() {
-        canvas.copy(
-            &textures.get(&component.1).unwrap(),
-            None,
-            *component.0
-        ).unwrap();
+    if let Some((_, inner)) = nest.inner.first_mut() {
+        if let Some(transform) = camera.picture_in_picture(&inner.game.size()) {
+            let transform = transform.then(&screen);
+
+            render_nest(inner, &outer_origin, camera, &transform, textures, sprites, canvas);
+        }
+    }
+
+    for component in overlay.iter() {
+        draw_component(component, &screen, textures, sprites, canvas);
     }
 
     canvas.present();
 }
+
+fn render_nest(
+    nest: &mut Nest,
+    origin: &Vector,
+    camera: &Camera,
+    transform: &Transform,
+    textures: &Textures,
+    sprites: &mut Option<Sprites>,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>
+) -> () {
+    layout_nest(nest, origin, 0, camera, &mut |component, _| {
+        draw_component(component, transform, textures, sprites, canvas);
+    });
+}
+
+// Draws the whole outer game into an RGB buffer of `width` by `height`
+// pixels, row by row, without going through SDL. Sprites are left out and
+// everything is drawn in its palette color.
+pub fn render_pixels(nest: &mut Nest, palette: &Palette, width: u32, height: u32) -> Vec<u8> {
+    let mut pixels = vec![0; (width * height * 3) as usize];
+
+    let origin = Vector { x: 0.0, y: 0.0 };
+    let size = nest.game.size();
+    let camera = Camera::new(size.clone());
+    let transform = Transform::fit(&origin, &size, &origin, &Vector { x: width as f64, y: height as f64 });
+
+    layout_nest(nest, &origin, 0, &camera, &mut |component, _| {
+        let (rect, color, _) = *component;
+        let rect = transform.apply(rect);
+        let rgba = palette.rgba(color);
+        let alpha = rgba[3] as u32;
+
+        let left = rect.left().max(0).min(width as i32) as u32;
+        let right = rect.right().max(0).min(width as i32) as u32;
+        let top = rect.top().max(0).min(height as i32) as u32;
+        let bottom = rect.bottom().max(0).min(height as i32) as u32;
+
+        for y in top..bottom {
+            for x in left..right {
+                let i = ((y * width + x) * 3) as usize;
+
+                for channel in 0..3 {
+                    let under = pixels[i + channel] as u32;
+                    pixels[i + channel] = ((rgba[channel] as u32 * alpha + under * (255 - alpha)) / 255) as u8;
+                }
+            }
+        }
+    });
+
+    return pixels;
+}
+
+// Hands every component in the nest to `draw`, outer game first, along with
+// how deeply nested its game is. Positions stay in floats all the way down
+// so inner games sit exactly where their ball is.
+fn layout_nest<F>(
+    nest: &mut Nest,
+    origin: &Vector,
+    depth: usize,
+    camera: &Camera,
+    draw: &mut F
+) -> () where F: FnMut(&Component, usize) {
+    // A shaking game takes the games nested inside it along with it.
+    let mut origin = origin.clone();
+    origin.add_mut(&nest.game.fx.shake_offset());
+
+    for component in nest.game.components(&origin).iter() {
+        draw(component, depth);
+    }
+
+    for (id, inner) in nest.inner.iter_mut() {
+        let mut inner_origin = match nest.game.ball(*id) {
+            Some(ball) => camera.inner_origin(ball, &inner.game.size()),
+            None => continue,
+        };
+        inner_origin.add_mut(&origin);
+
+        layout_nest(inner, &inner_origin, depth + 1, camera, draw);
+    }
+}
+
+// Draw with the sprite for the component's part if there is one, otherwise
+// as a solid palette color.
+fn draw_component(
+    component: &Component,
+    transform: &Transform,
+    textures: &Textures,
+    sprites: &mut Option<Sprites>,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>
+) -> () {
+    let (rect, color, part) = *component;
+    let rect = &transform.apply(rect);
+
+    if let Some(ref mut sprites) = *sprites {
+        let border_slice = sprites.border_slice;
+
+        if let Some(texture) = sprites.get_mut(part) {
+            // Paddle sprites are tinted with the level color so you can
+            // still tell which level you are on.
+            match *color {
+                Color::Level(_) => {
+                    let rgba = textures.rgba(color);
+
+                    texture.set_color_mod(rgba[0], rgba[1], rgba[2]);
+                    texture.set_alpha_mod(rgba[3]);
+                },
+                _ => {
+                    texture.set_color_mod(255, 255, 255);
+                    texture.set_alpha_mod(255);
+                }
+            }
+
+            if part == Part::PaddleBorder && border_slice > 0 {
+                draw_nine_slice(texture, *rect, border_slice, canvas);
+            } else {
+                canvas.copy(texture, None, *rect).unwrap();
+            }
+
+            return;
+        }
+    }
+
+    canvas.copy(textures.get(color), None, *rect).unwrap();
+}
+
+// Draw `texture` stretched over `dest` while keeping its corners `slice`
+// pixels square, so borders don't smear when a paddle grows or shrinks.
+fn draw_nine_slice(
+    texture: &Texture,
+    dest: Rect,
+    slice: u32,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>
+) -> () {
+    let query = texture.query();
+    let src_slice = slice.min(query.width / 2).min(query.height / 2);
+    let dest_slice = slice.min(dest.width() / 2).min(dest.height() / 2);
+
+    let src_columns = slices(0, query.width, src_slice);
+    let src_rows = slices(0, query.height, src_slice);
+    let dest_columns = slices(dest.x(), dest.width(), dest_slice);
+    let dest_rows = slices(dest.y(), dest.height(), dest_slice);
+
+    for row in 0..3 {
+        for column in 0..3 {
+            let (src_x, src_width) = src_columns[column];
+            let (src_y, src_height) = src_rows[row];
+            let (dest_x, dest_width) = dest_columns[column];
+            let (dest_y, dest_height) = dest_rows[row];
+
+            if src_width == 0 || src_height == 0 || dest_width == 0 || dest_height == 0 {
+                continue;
+            }
+
+            canvas.copy(
+                texture,
+                Rect::new(src_x, src_y, src_width, src_height),
+                Rect::new(dest_x, dest_y, dest_width, dest_height)
+            ).unwrap();
+        }
+    }
+}
+
+// Split a span into (start, length) pairs for the two fixed size ends and
+// the stretched middle.
+fn slices(start: i32, length: u32, end: u32) -> [(i32, u32); 3] {
+    [
+        (start, end),
+        (start + end as i32, length - end * 2),
+        (start + (length - end) as i32, end),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use constants::{OUTER_CONSTANTS, INNER_CONSTANTS};
+    use fx::{FxSettings};
+
+    // Screen rects of the outer ball, the inner field and the inner ball.
+    fn nested_rects(nest: &mut Nest, transform: &Transform) -> (Rect, Rect, Rect) {
+        let camera = Camera::new(nest.game.size());
+        let mut ball = None;
+        let mut field = None;
+        let mut inner_ball = None;
+
+        layout_nest(nest, &Vector { x: 0.0, y: 0.0 }, 0, &camera, &mut |component, depth| {
+            let (rect, _, part) = *component;
+            let rect = Some(transform.apply(rect));
+
+            match (depth, part) {
+                (0, Part::Ball) => ball = rect,
+                (1, Part::Field) => field = rect,
+                (1, Part::Ball) => inner_ball = rect,
+                _ => {}
+            }
+        });
+
+        (ball.unwrap(), field.unwrap(), inner_ball.unwrap())
+    }
+
+    fn close_to(pixels: i32, expected: f64) -> bool {
+        (pixels as f64 - expected).abs() <= 0.5
+    }
+
+    #[test]
+    fn nested_components_follow_the_outer_ball() {
+        let mut outer = OUTER_CONSTANTS;
+        let mut inner = INNER_CONSTANTS;
+        outer.effects = FxSettings::all(false);
+        inner.effects = FxSettings::all(false);
+
+        let mut nest = Nest::new(&[outer, inner]);
+
+        let inset = Vector {
+            x: (OUTER_CONSTANTS.ball_width - INNER_CONSTANTS.window_width) as f64 / 2.0,
+            y: (OUTER_CONSTANTS.ball_height - INNER_CONSTANTS.window_height) as f64 / 2.0,
+        };
+
+        // Unscaled, and scaled by an awkward amount like a resized window.
+        let transforms = [
+            Transform::identity(),
+            Transform { scale: 0.731, offset: Vector { x: 13.4, y: 7.9 } },
+        ];
+
+        for transform in transforms.iter() {
+            for step in 0..200 {
+                let pos = Vector { x: 100.0 + step as f64 * 0.37, y: 50.0 + step as f64 * 0.23 };
+                nest.game.balls[0].pos = pos.clone();
+
+                let inner_ball_pos = nest.inner[0].1.game.balls[0].pos.clone();
+                let (ball, field, inner_ball) = nested_rects(&mut nest, transform);
+
+                let expected_x = |x: f64| x * transform.scale + transform.offset.x;
+                let expected_y = |y: f64| y * transform.scale + transform.offset.y;
+
+                assert!(close_to(ball.x(), expected_x(pos.x)));
+                assert!(close_to(ball.y(), expected_y(pos.y)));
+
+                assert!(close_to(field.x(), expected_x(pos.x + inset.x)));
+                assert!(close_to(field.y(), expected_y(pos.y + inset.y)));
+
+                assert!(close_to(inner_ball.x(), expected_x(pos.x + inset.x + inner_ball_pos.x)));
+                assert!(close_to(inner_ball.y(), expected_y(pos.y + inset.y + inner_ball_pos.y)));
+            }
+        }
+    }
+}
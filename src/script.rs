@@ -0,0 +1,218 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST, FLOAT, INT};
+
+// Bot scripts are written in Rhai (https://rhai.rs/book), run in a sandbox.
+// The engine has no way to reach files, the network or anything else
+// outside, the game state is pushed as constants the script can't change,
+// and every run is cut short once it goes past its `Limits`.
+//
+//     // Comments start with two slashes.
+//     let target = field.height / 2.0;
+//
+//     if ball.velocity.x > 0.0 {
+//         target = ball.pos.y;
+//     }
+//
+//     if target < paddle.pos.y { return "up"; }
+//     "stay"
+
+// How much a single run of a script may do.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    // Rhai operations, roughly one per expression or statement.
+    pub max_operations: u64,
+    pub max_time: Duration,
+    // How deep expressions, and expressions inside functions, can nest.
+    pub max_depth: (usize, usize),
+    pub max_call_levels: usize,
+    // Longest string in bytes and most items in an array or map.
+    pub max_string_size: usize,
+    pub max_items: usize,
+}
+
+#[derive(Debug)]
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    max_time: Duration,
+    // When the current run started, for the time limit.
+    started: Rc<Cell<Instant>>,
+}
+
+impl Script {
+    pub fn parse(source: &str, limits: &Limits) -> Result<Script, String> {
+        let started = Rc::new(Cell::new(Instant::now()));
+        let mut engine = Engine::new();
+
+        engine.set_max_operations(limits.max_operations);
+        engine.set_max_expr_depths(limits.max_depth.0, limits.max_depth.1);
+        engine.set_max_call_levels(limits.max_call_levels);
+        engine.set_max_string_size(limits.max_string_size);
+        engine.set_max_array_size(limits.max_items);
+        engine.set_max_map_size(limits.max_items);
+        // Scripts run every tick, so `print` and `debug` go nowhere rather
+        // than flooding the terminal.
+        engine.on_print(|_| {});
+        engine.on_debug(|_, _, _| {});
+
+        let max_time = limits.max_time;
+        let run_started = started.clone();
+
+        engine.on_progress(move |_| {
+            if run_started.get().elapsed() > max_time {
+                return Some(Dynamic::UNIT);
+            }
+
+            None
+        });
+
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+
+        Ok(Script {
+            engine: engine,
+            ast: ast,
+            max_time: max_time,
+            started: started,
+        })
+    }
+
+    // Run the script with `scope` and return whatever it returns.
+    pub fn run(&self, scope: &mut Scope) -> Result<Dynamic, String> {
+        self.started.set(Instant::now());
+
+        self.engine.eval_ast_with_scope::<Dynamic>(scope, &self.ast).map_err(|e| {
+            match *e {
+                EvalAltResult::ErrorTerminated(_, position) => {
+                    format!("took longer than {} ms ({})", self.max_time.as_millis(), position)
+                },
+                ref e => e.to_string(),
+            }
+        })
+    }
+}
+
+pub fn number(n: f64) -> Dynamic {
+    Dynamic::from_float(n as FLOAT)
+}
+
+pub fn count(n: u64) -> Dynamic {
+    Dynamic::from_int(n as INT)
+}
+
+pub fn map(entries: Vec<(&str, Dynamic)>) -> Dynamic {
+    let map: Map = entries.into_iter().map(|(key, value)| (key.into(), value)).collect();
+
+    Dynamic::from_map(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        max_operations: 10_000,
+        max_time: Duration::from_millis(50),
+        max_depth: (32, 16),
+        max_call_levels: 8,
+        max_string_size: 1024,
+        max_items: 256,
+    };
+
+    fn run(source: &str) -> Result<Dynamic, String> {
+        let script = Script::parse(source, &LIMITS)?;
+
+        script.run(&mut Scope::new())
+    }
+
+    fn run_number(source: &str) -> f64 {
+        run(source).unwrap().as_float().unwrap()
+    }
+
+    #[test]
+    fn precedence_and_unary_operators() {
+        assert_eq!(run("1 + 2 * 3").unwrap().as_int(), Ok(7));
+        assert_eq!(run("(1 + 2) * 3").unwrap().as_int(), Ok(9));
+        assert_eq!(run("-2 * 3 + 10").unwrap().as_int(), Ok(4));
+        assert_eq!(run("!true || 1 < 2 && 2 < 1").unwrap().as_bool(), Ok(false));
+        assert_eq!(run_number("10.0 - -2.5 / 2.0"), 11.25);
+        assert_eq!(run_number("-(1.5 + 0.5) * 2"), -4.0);
+    }
+
+    #[test]
+    fn reads_game_state_but_cant_change_it() {
+        let mut scope = Scope::new();
+
+        scope.push_constant("ball", map(vec![
+            ("pos", map(vec![("x", number(10.0)), ("y", number(20.0))])),
+            ("velocity", map(vec![("x", number(-0.5)), ("y", number(0.25))])),
+        ]));
+        scope.push_constant("paddle", map(vec![("hits", count(3))]));
+        scope.push_constant("level", count(2));
+
+        let script = Script::parse(
+            "ball.pos.y + ball.velocity.x * 10 + paddle.hits * level",
+            &LIMITS,
+        ).unwrap();
+
+        assert_eq!(script.run(&mut scope).unwrap().as_float(), Ok(21.0));
+
+        let script = Script::parse("ball.pos.y = 0.0;", &LIMITS).unwrap();
+        let error = script.run(&mut scope).unwrap_err();
+
+        assert!(error.contains("constant"), "{}", error);
+    }
+
+    #[test]
+    fn too_many_operations() {
+        let error = run("let n = 0; loop { n += 1; }").unwrap_err();
+
+        assert!(error.contains("Too many operations"), "{}", error);
+    }
+
+    #[test]
+    fn too_slow() {
+        let limits = Limits { max_operations: 0, ..LIMITS };
+        let script = Script::parse("let n = 0; loop { n += 1; }", &limits).unwrap();
+        let error = script.run(&mut Scope::new()).unwrap_err();
+
+        assert!(error.contains("took longer than 50 ms"), "{}", error);
+    }
+
+    #[test]
+    fn print_and_debug_do_nothing() {
+        assert_eq!(run("print(\"hi\"); debug(1); 2").unwrap().as_int(), Ok(2));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Script::parse("let = 3;", &LIMITS).is_err());
+        assert!(Script::parse("if x { 1", &LIMITS).is_err());
+        assert!(Script::parse("1 + * 2", &LIMITS).is_err());
+        assert!(Script::parse("\"unterminated", &LIMITS).is_err());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let source = format!("{}1{}", "(".repeat(1000), ")".repeat(1000));
+
+        assert!(Script::parse(&source, &LIMITS).is_err());
+
+        let error = run("fn f(n) { f(n + 1) } f(0)").unwrap_err();
+
+        assert!(error.contains("Stack overflow"), "{}", error);
+    }
+
+    #[test]
+    fn big_strings_and_arrays() {
+        let error = run("let s = \"ab\"; loop { s += s; }").unwrap_err();
+
+        assert!(error.contains("too large"), "{}", error);
+
+        let error = run("let a = [0]; loop { a += a; }").unwrap_err();
+
+        assert!(error.contains("too large"), "{}", error);
+    }
+}
@@ -1,5 +1,7 @@
 use sdl2;
 
+use events::{GameEvent};
+
 use std::path::Path;
 use std::collections::HashMap;
 
@@ -9,35 +11,86 @@ pub struct Sounds<'a> {
 }
 
 impl<'a> Sounds<'a> {
-    pub fn new() -> Sounds<'a> {
+    pub fn load() -> Result<Sounds<'a>, String> {
         let mut sounds: HashMap<String, sdl2::mixer::Music> = HashMap::new();
 
+        for name in Sounds::names() {
+            let path = Sounds::path(&name);
+            let music = sdl2::mixer::Music::from_file(Path::new(&path))
+                .map_err(|e| format!("{}: {}", path, e))?;
+
+            sounds.insert(name, music);
+        }
+
+        Ok(Sounds { sounds: sounds })
+    }
+
+    fn names() -> Vec<String> {
+        let mut names = vec![];
+
         for level in 1..4 {
             for sound in 1..6 {
-                sounds.insert(
-                    format!("paddle_{}_{}", level, sound).to_string(),
-                    sdl2::mixer::Music::from_file(
-                        Path::new(&format!("./sounds/paddle_{}_{}.wav", level, sound))
-                    ).unwrap()
-                );
+                names.push(format!("paddle_{}_{}", level, sound));
             }
         }
 
-        sounds.insert(
-            "left_right_edge_collision".to_string(),
-            sdl2::mixer::Music::from_file(
-                Path::new("./sounds/left_right_edge_collision.wav")
-            ).unwrap()
-        );
+        for sound in ["left_right_edge_collision", "ping", "pong"].iter() {
+            names.push(sound.to_string());
+        }
 
-        Sounds { sounds: sounds }
+        return names;
+    }
+
+    fn path(name: &str) -> String {
+        format!("./sounds/{}.wav", name)
+    }
+
+    // Every sound file, to watch for changes.
+    pub fn files() -> Vec<String> {
+        Sounds::names().iter().map(|name| Sounds::path(name)).collect()
     }
 
     pub fn play(&self, sound: String) -> () {
+        let music = match self.sounds.get(&sound) {
+            Some(music) => music,
+            None => {
+                println!("Unknown sound: {}", sound);
+                return;
+            }
+        };
+
         // 1 is the number of loops
-        match self.sounds.get(&sound).unwrap().play(1) {
+        match music.play(1) {
             Err(e) => println!("Error playing sound: {:?}", e),
             _ => ()
         };
     }
+
+    pub fn play_event(&self, event: &GameEvent) -> () {
+        match *event {
+            GameEvent::PaddleHit { hits, max_hits, level, .. } => {
+                self.play(paddle_sound(hits, max_hits, level));
+            },
+            GameEvent::Miss { .. } => {
+                self.play("left_right_edge_collision".to_string());
+            },
+            GameEvent::Blocked { .. } |
+            GameEvent::BallCollision { .. } |
+            GameEvent::PowerUp { .. } => {
+                self.play("ping".to_string());
+            },
+            GameEvent::Teleport { .. } => {
+                self.play("pong".to_string());
+            },
+            GameEvent::Serve { .. } | GameEvent::LevelUp { .. } => {}
+        }
+    }
+}
+
+// There are paddle sounds for 3 colors and 5 meter levels, meters with a
+// different cap or extra colors are mapped onto those.
+fn paddle_sound(hits: u8, max_hits: u8, color_index: usize) -> String {
+    let level = (hits as u32 * 5 + max_hits as u32 - 1) / max_hits.max(1) as u32;
+
+    format!("paddle_{}_{}", color_index % 3 + 1, level.clamp(1, 5))
 }
@@ -0,0 +1,93 @@
+extern crate sdl2;
+
+use sdl2::image::LoadTexture;
+use sdl2::render::{BlendMode, Texture, TextureCreator};
+use sdl2::video::WindowContext;
+
+use std::fs;
+use std::path::Path;
+
+use toml;
+
+use component::{Part};
+
+// A sprite set file lists a PNG for each part that should be drawn with a
+// sprite, relative to the file itself. Parts without a sprite keep their
+// solid palette color.
+//
+//     name = "chalk"
+//     ball = "ball.png"
+//     paddle_border = "paddle_border.png"
+//     paddle_segment = "paddle_segment.png"
+//     background = "background.png"
+//     border_slice = 6
+#[derive(Debug, Deserialize)]
+struct SpriteSetFile {
+    #[serde(default)]
+    name: String,
+    ball: Option<String>,
+    paddle_border: Option<String>,
+    paddle_segment: Option<String>,
+    background: Option<String>,
+    // Size in pixels of the corners of the paddle border sprite, which are
+    // drawn unscaled when the paddle is nine-sliced.
+    #[serde(default)]
+    border_slice: u32,
+}
+
+pub struct Sprites<'a> {
+    pub name: String,
+    pub border_slice: u32,
+    ball: Option<Texture<'a>>,
+    paddle_border: Option<Texture<'a>>,
+    paddle_segment: Option<Texture<'a>>,
+    background: Option<Texture<'a>>,
+}
+
+impl<'a> Sprites<'a> {
+    pub fn load(
+        creator: &'a TextureCreator<WindowContext>,
+        path: &str
+    ) -> Result<Sprites<'a>, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        let file: SpriteSetFile = toml::from_str(&contents)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+
+        let load = |sprite: &Option<String>| -> Result<Option<Texture<'a>>, String> {
+            match *sprite {
+                Some(ref file) => {
+                    let mut texture = creator.load_texture(dir.join(file))
+                        .map_err(|e| format!("{}: {}", file, e))?;
+
+                    texture.set_blend_mode(BlendMode::Blend);
+
+                    Ok(Some(texture))
+                },
+                None => Ok(None),
+            }
+        };
+
+        Ok(Sprites {
+            name: file.name.clone(),
+            border_slice: file.border_slice,
+            ball: load(&file.ball)?,
+            paddle_border: load(&file.paddle_border)?,
+            paddle_segment: load(&file.paddle_segment)?,
+            background: load(&file.background)?,
+        })
+    }
+
+    pub fn get_mut(&mut self, part: Part) -> Option<&mut Texture<'a>> {
+        match part {
+            Part::Ball => self.ball.as_mut(),
+            Part::PaddleBorder => self.paddle_border.as_mut(),
+            Part::PaddleSegment => self.paddle_segment.as_mut(),
+            Part::Field => self.background.as_mut(),
+            Part::Plain => None,
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use serde_json;
+
+use component::{Side};
+
+use events::{GameEvent};
+
+use game::{GameStates};
+
+use nest::{Nest};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerStats {
+    pub side: Side,
+    pub hits: u32,
+    pub misses: u32,
+    // Misses a shield saved.
+    pub blocked: u32,
+    // Fastest ball this player returned, in pixels per second.
+    pub max_ball_speed: f64,
+    // Time from each serve to this player being the first to touch the ball.
+    pub reaction_times_ms: Vec<u64>,
+}
+
+impl PlayerStats {
+    fn new(side: Side) -> PlayerStats {
+        PlayerStats {
+            side: side,
+            hits: 0,
+            misses: 0,
+            blocked: 0,
+            max_ball_speed: 0.0,
+            reaction_times_ms: vec![],
+        }
+    }
+
+    pub fn average_reaction_ms(&self) -> Option<u64> {
+        if self.reaction_times_ms.is_empty() { return None; }
+
+        Some(self.reaction_times_ms.iter().sum::<u64>() / self.reaction_times_ms.len() as u64)
+    }
+}
+
+// Stats for one nesting level. The games inside each of the outer balls
+// are all counted together.
+#[derive(Debug, Clone, Serialize)]
+pub struct GameStats {
+    // 0 is the outer game, 1 the games inside its balls and so on.
+    pub depth: usize,
+    pub players: Vec<PlayerStats>,
+    pub longest_rally: u32,
+    // In pixels per second.
+    pub max_ball_speed: f64,
+    // Time spent playing at each color level, indexed by level.
+    pub time_at_level_ms: Vec<u64>,
+}
+
+impl GameStats {
+    fn new(depth: usize) -> GameStats {
+        GameStats {
+            depth: depth,
+            players: vec![],
+            longest_rally: 0,
+            max_ball_speed: 0.0,
+            time_at_level_ms: vec![],
+        }
+    }
+
+    fn player(&mut self, side: Side) -> &mut PlayerStats {
+        match self.players.iter().position(|player| player.side == side) {
+            Some(index) => &mut self.players[index],
+            None => {
+                self.players.push(PlayerStats::new(side));
+                self.players.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+// A ball in a particular game, the path to the game followed by the ball id.
+type BallKey = (Vec<u32>, u32);
+
+// Everything worth knowing about how a match went, built up from the
+// nest's events as it is played.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchStats {
+    pub duration_ms: u64,
+    pub games: Vec<GameStats>,
+    // Hits so far in the current rally of each ball.
+    #[serde(skip)]
+    rallies: HashMap<BallKey, u32>,
+    // When each ball still waiting for its first touch was served.
+    #[serde(skip)]
+    serves: HashMap<BallKey, u64>,
+    #[serde(skip)]
+    seen: HashSet<BallKey>,
+}
+
+impl MatchStats {
+    pub fn new() -> MatchStats {
+        MatchStats {
+            duration_ms: 0,
+            games: vec![],
+            rallies: HashMap::new(),
+            serves: HashMap::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    // Call after every update of the nest.
+    pub fn update(&mut self, nest: &Nest, delta_ms: u64) -> () {
+        if nest.game.state == GameStates::Playing {
+            self.duration_ms = self.duration_ms + delta_ms;
+        }
+
+        self.walk(nest, vec![], &mut vec![], delta_ms);
+
+        for (path, event) in nest.events() {
+            self.record(path, event);
+        }
+    }
+
+    // Time at each level is taken from the first game at each depth, they
+    // level up together unless the constants say otherwise. Balls that have
+    // not been seen before are counted as served now, as the first ball in
+    // a game is served before anything is listening for its events.
+    fn walk(&mut self, nest: &Nest, path: Vec<u32>, counted: &mut Vec<usize>, delta_ms: u64) -> () {
+        let depth = path.len();
+
+        if nest.game.state == GameStates::Playing && !counted.contains(&depth) {
+            counted.push(depth);
+
+            let level = nest.game.color_index;
+            let time = &mut self.game(depth).time_at_level_ms;
+
+            if time.len() <= level {
+                time.resize(level + 1, 0);
+            }
+            time[level] = time[level] + delta_ms;
+        }
+
+        for ball in nest.game.balls.iter() {
+            let key = (path.clone(), ball.id);
+
+            if !self.seen.contains(&key) {
+                self.serves.insert(key.clone(), self.duration_ms);
+                self.seen.insert(key);
+            }
+        }
+
+        for (id, inner) in nest.inner.iter() {
+            let mut inner_path = path.clone();
+            inner_path.push(*id);
+
+            self.walk(inner, inner_path, counted, delta_ms);
+        }
+    }
+
+    fn record(&mut self, path: Vec<u32>, event: &GameEvent) -> () {
+        let now = self.duration_ms;
+        let game = path.len();
+
+        match *event {
+            GameEvent::Serve { ball } => {
+                self.seen.insert((path.clone(), ball));
+                self.serves.insert((path.clone(), ball), now);
+                self.rallies.remove(&(path, ball));
+            },
+            GameEvent::PaddleHit { side, ball, speed, .. } => {
+                let key = (path, ball);
+                let rally = self.rallies.get(&key).cloned().unwrap_or(0) + 1;
+                let reaction = self.serves.remove(&key).map(|served| now - served);
+                self.rallies.insert(key, rally);
+
+                let stats = self.game(game);
+                stats.longest_rally = stats.longest_rally.max(rally);
+                stats.max_ball_speed = stats.max_ball_speed.max(speed * 1000.0);
+
+                let player = stats.player(side);
+                player.hits = player.hits + 1;
+                player.max_ball_speed = player.max_ball_speed.max(speed * 1000.0);
+                player.reaction_times_ms.extend(reaction);
+            },
+            GameEvent::Miss { side, ball, speed, .. } => {
+                self.rallies.remove(&(path, ball));
+
+                let stats = self.game(game);
+                stats.max_ball_speed = stats.max_ball_speed.max(speed * 1000.0);
+
+                let player = stats.player(side);
+                player.misses = player.misses + 1;
+            },
+            GameEvent::Blocked { side, .. } => {
+                let player = self.game(game).player(side);
+                player.blocked = player.blocked + 1;
+            },
+            _ => {}
+        }
+    }
+
+    fn game(&mut self, depth: usize) -> &mut GameStats {
+        while self.games.len() <= depth {
+            let next = self.games.len();
+            self.games.push(GameStats::new(next));
+        }
+
+        &mut self.games[depth]
+    }
+
+    // Points for `side`, one for every miss by any other player.
+    pub fn points(&self, side: Side) -> u32 {
+        self.games.iter()
+            .flat_map(|game| game.players.iter())
+            .filter(|player| player.side != side)
+            .map(|player| player.misses)
+            .sum()
+    }
+
+    // Hits and misses for `side` across all the games.
+    pub fn totals(&self, side: Side) -> (u32, u32) {
+        self.games.iter()
+            .flat_map(|game| game.players.iter())
+            .filter(|player| player.side == side)
+            .fold((0, 0), |(hits, misses), player| (hits + player.hits, misses + player.misses))
+    }
+
+    pub fn longest_rally(&self) -> u32 {
+        self.games.iter().map(|game| game.longest_rally).max().unwrap_or(0)
+    }
+
+    // Lines for the summary screen.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![format!("match time {}", minutes(self.duration_ms))];
+
+        for game in self.games.iter() {
+            lines.push(String::new());
+            lines.push(match game.depth {
+                0 => "outer game".to_string(),
+                1 => "inner game".to_string(),
+                depth => format!("game at depth {}", depth),
+            });
+
+            for player in game.players.iter() {
+                lines.push(format!(
+                    "  {:<6} hits {:<4} misses {:<4} blocked {:<3} fastest {:<5} reaction {}",
+                    player.side.name(),
+                    player.hits,
+                    player.misses,
+                    player.blocked,
+                    player.max_ball_speed.round(),
+                    match player.average_reaction_ms() {
+                        Some(ms) => format!("{} ms", ms),
+                        None => "-".to_string(),
+                    }
+                ));
+            }
+
+            lines.push(format!(
+                "  longest rally {}  fastest ball {} px/s",
+                game.longest_rally,
+                game.max_ball_speed.round()
+            ));
+
+            let levels: Vec<String> = game.time_at_level_ms.iter().enumerate()
+                .map(|(level, ms)| format!("{}: {}", level + 1, minutes(*ms)))
+                .collect();
+            lines.push(format!("  time at level  {}", levels.join("  ")));
+        }
+
+        return lines;
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    // One row per player per nesting level.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "depth,side,hits,misses,blocked,max_ball_speed,average_reaction_ms,longest_rally,game_max_ball_speed,time_at_level_ms\n"
+        );
+
+        for game in self.games.iter() {
+            let levels: Vec<String> = game.time_at_level_ms.iter().map(|ms| ms.to_string()).collect();
+
+            for player in game.players.iter() {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{:.1},{},{},{:.1},{}\n",
+                    game.depth,
+                    player.side.name(),
+                    player.hits,
+                    player.misses,
+                    player.blocked,
+                    player.max_ball_speed,
+                    player.average_reaction_ms().map(|ms| ms.to_string()).unwrap_or_default(),
+                    game.longest_rally,
+                    game.max_ball_speed,
+                    levels.join(";")
+                ));
+            }
+        }
+
+        return csv;
+    }
+
+    // Writes JSON or CSV depending on the file extension.
+    pub fn export(&self, path: &str) -> Result<(), String> {
+        let contents = match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+            Some("json") => self.to_json(),
+            Some("csv") => self.to_csv(),
+            _ => return Err(format!("{}: stats can only be exported as .json or .csv", path)),
+        };
+
+        fs::write(path, contents).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+// A duration as minutes and seconds, like 2:05.
+pub fn minutes(ms: u64) -> String {
+    format!("{}:{:02}", ms / 60_000, ms / 1000 % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    fn player(side: Side, hits: u32, misses: u32, reaction_times_ms: Vec<u64>) -> PlayerStats {
+        PlayerStats {
+            side: side,
+            hits: hits,
+            misses: misses,
+            blocked: 0,
+            max_ball_speed: 0.0,
+            reaction_times_ms: reaction_times_ms,
+        }
+    }
+
+    // An outer game and one game inside its ball, with one miss inside.
+    fn nested() -> MatchStats {
+        let mut stats = MatchStats::new();
+
+        stats.game(0).players = vec![
+            player(Side::One, 4, 2, vec![100, 201]),
+            player(Side::Two, 3, 1, vec![]),
+        ];
+        stats.game(0).longest_rally = 5;
+        stats.game(0).time_at_level_ms = vec![1000, 2500];
+        stats.game(1).players = vec![
+            player(Side::One, 1, 0, vec![]),
+            player(Side::Two, 2, 1, vec![]),
+        ];
+        stats.game(1).longest_rally = 7;
+
+        return stats;
+    }
+
+    #[test]
+    fn minutes_pads_the_seconds() {
+        assert_eq!(minutes(0), "0:00");
+        assert_eq!(minutes(5999), "0:05");
+        assert_eq!(minutes(125_000), "2:05");
+        assert_eq!(minutes(3_600_000), "60:00");
+    }
+
+    #[test]
+    fn adds_up_every_nesting_level() {
+        let stats = nested();
+
+        assert_eq!(stats.points(Side::One), 2);
+        assert_eq!(stats.points(Side::Two), 2);
+        assert_eq!(stats.totals(Side::One), (5, 2));
+        assert_eq!(stats.totals(Side::Two), (5, 2));
+        assert_eq!(stats.totals(Side::Three), (0, 0));
+        assert_eq!(stats.longest_rally(), 7);
+        assert_eq!(MatchStats::new().longest_rally(), 0);
+        assert_eq!(stats.games[0].players[0].average_reaction_ms(), Some(150));
+        assert_eq!(stats.games[0].players[1].average_reaction_ms(), None);
+    }
+
+    #[test]
+    fn exports_a_row_per_player_per_level() {
+        let csv = nested().to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1], format!("0,{},4,2,0,0.0,150,5,0.0,1000;2500", Side::One.name()));
+        assert_eq!(lines[4], format!("1,{},2,1,0,0.0,,7,0.0,", Side::Two.name()));
+
+        let json: serde_json::Value = serde_json::from_str(&nested().to_json()).unwrap();
+
+        assert_eq!(json["games"][1]["longest_rally"], 7);
+        assert!(json.get("rallies").is_none());
+    }
+
+    #[test]
+    fn exports_only_json_and_csv() {
+        let path = env::temp_dir().join(format!("popongong-{}-stats.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        nested().export(path).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), nested().to_csv());
+        fs::remove_file(path).unwrap();
+
+        assert!(nested().export("stats.txt").is_err());
+        assert!(nested().export("stats").is_err());
+    }
+}
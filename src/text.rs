@@ -0,0 +1,200 @@
+use vector::Vector;
+
+use component::{Component, FRect, Part};
+
+use constants::{Color};
+
+// A tiny 5x7 pixel font drawn as rects, so text works without SDL_ttf or
+// any font files. Each glyph is 7 rows of 5 bits, most significant bit on
+// the left. Lower case is drawn as upper case and anything unknown as a
+// blank.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '/' => [0x01, 0x01, 0x02, 0x04, 0x08, 0x10, 0x10],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '?' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '\'' => [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '"' => [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00],
+        _ => [0; 7],
+    }
+}
+
+// Width of a character cell and height of a line, in font pixels.
+const CELL_WIDTH: f64 = 6.0;
+const LINE_HEIGHT: f64 = 9.0;
+
+// A block of text laid out as rects. `scale` is the size of one font pixel.
+#[derive(Debug, Clone)]
+pub struct Text {
+    rects: Vec<FRect>,
+    color: Color,
+}
+
+impl Text {
+    pub fn new(lines: &[String], pos: &Vector, scale: f64, color: Color) -> Text {
+        let mut rects = vec![];
+
+        for (row, line) in lines.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let x = pos.x + column as f64 * CELL_WIDTH * scale;
+                let y = pos.y + row as f64 * LINE_HEIGHT * scale;
+
+                for (i, bits) in glyph(c).iter().enumerate() {
+                    // Merge runs of pixels in a row into one rect.
+                    let mut bit = 0;
+                    while bit < 5 {
+                        if bits & (0x10 >> bit) == 0 {
+                            bit = bit + 1;
+                            continue;
+                        }
+
+                        let start = bit;
+                        while bit < 5 && bits & (0x10 >> bit) != 0 {
+                            bit = bit + 1;
+                        }
+
+                        rects.push(FRect::new(
+                            x + start as f64 * scale,
+                            y + i as f64 * scale,
+                            (bit - start) as f64 * scale,
+                            scale
+                        ));
+                    }
+                }
+            }
+        }
+
+        Text { rects: rects, color: color }
+    }
+
+    // The size `lines` take up at `scale`.
+    pub fn size(lines: &[String], scale: f64) -> Vector {
+        let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+
+        Vector {
+            x: columns as f64 * CELL_WIDTH * scale,
+            y: lines.len() as f64 * LINE_HEIGHT * scale,
+        }
+    }
+
+    pub fn components(&self) -> Vec<Component<'_>> {
+        self.rects.iter().map(|rect| (rect, &self.color, Part::Plain)).collect()
+    }
+}
+
+// A box of text drawn over the middle of the screen, used for menus and
+// summaries. The text is scaled to fit the screen.
+#[derive(Debug)]
+pub struct Panel {
+    background: FRect,
+    background_color: Color,
+    border: FRect,
+    border_color: Color,
+    title: Text,
+    body: Text,
+}
+
+impl Panel {
+    pub fn new(title: &str, lines: &[String], screen: &Vector) -> Panel {
+        let title = vec![title.to_string()];
+        let margin = screen.x.min(screen.y) * 0.05;
+
+        // Largest whole font pixel size where the text still fits, titles
+        // being drawn twice as large as the body.
+        let body_size = Text::size(lines, 1.0);
+        let title_size = Text::size(&title, 2.0);
+        let width = body_size.x.max(title_size.x).max(1.0);
+        let height = (body_size.y + title_size.y + LINE_HEIGHT).max(1.0);
+        let scale = ((screen.x - margin * 4.0) / width)
+            .min((screen.y - margin * 4.0) / height)
+            .min(4.0)
+            .floor()
+            .max(1.0);
+
+        let size = Vector { x: width * scale + margin * 2.0, y: height * scale + margin * 2.0 };
+        let pos = Vector { x: (screen.x - size.x) / 2.0, y: (screen.y - size.y) / 2.0 };
+        let border = scale;
+
+        Panel {
+            border: FRect::new(pos.x - border, pos.y - border, size.x + border * 2.0, size.y + border * 2.0),
+            border_color: Color::Border,
+            background: FRect::new(pos.x, pos.y, size.x, size.y),
+            background_color: Color::Background,
+            title: Text::new(
+                &title,
+                &Vector { x: pos.x + margin, y: pos.y + margin },
+                scale * 2.0,
+                Color::Ball
+            ),
+            body: Text::new(
+                lines,
+                &Vector { x: pos.x + margin, y: pos.y + margin + (title_size.y + LINE_HEIGHT) * scale },
+                scale,
+                Color::Border
+            ),
+        }
+    }
+
+    pub fn components(&self) -> Vec<Component<'_>> {
+        let mut components = vec![
+            (&self.border, &self.border_color, Part::Plain),
+            (&self.background, &self.background_color, Part::Plain),
+        ];
+
+        components.append(&mut self.title.components());
+        components.append(&mut self.body.components());
+
+        return components;
+    }
+}
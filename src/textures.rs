@@ -1,47 +1,82 @@
 extern crate sdl2;
 
 use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::BlendMode;
 
 use std::collections::HashMap;
 
-use constants;
+use constants::{Color};
+use palette::{Palette, Rgba};
+
+// One solid color texture per color role in the palette.
+pub struct Textures<'a> {
+    textures: HashMap<Color, sdl2::render::Texture<'a>>,
+    levels: usize,
+    palette: Palette,
+}
+
+impl<'a> Textures<'a> {
+    pub fn get(&self, color: &Color) -> &sdl2::render::Texture<'a> {
+        match color {
+            Color::Level(level) => &self.textures[&Color::Level(level % self.levels)],
+            _ => &self.textures[color],
+        }
+    }
+
+    pub fn rgba(&self, color: &Color) -> Rgba {
+        self.palette.rgba(color)
+    }
+}
 
 pub fn init_textures<'a>(
-    creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>
-) -> HashMap<constants::Color, sdl2::render::Texture<'a>> {
-    let black = create_texture(&creator, 0, 13, 40);
-    let white = create_texture(&creator, 255, 255, 255);
-    let red = create_texture(&creator, 221, 83, 134);
-    let green = create_texture(&creator, 70, 184, 135);
-    let blue = create_texture(&creator, 7, 137, 199);
-    let purple = create_texture(&creator, 253, 184, 119); // not purple
+    creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
+    palette: &Palette
+) -> Textures<'a> {
+    let mut roles = vec![
+        Color::Background,
+        Color::Ball,
+        Color::Border,
+        Color::Portal,
+        Color::Bonus,
+        Color::Penalty,
+        Color::SpeedUp,
+        Color::SlowDown,
+    ];
+
+    for level in 0..palette.levels.len() {
+        roles.push(Color::Level(level));
+    }
 
     let mut textures = HashMap::new();
 
-    textures.insert(constants::Color::Black, black);
-    textures.insert(constants::Color::White, white);
-    textures.insert(constants::Color::Red, red);
-    textures.insert(constants::Color::Green, green);
-    textures.insert(constants::Color::Blue, blue);
-    textures.insert(constants::Color::Purple, purple);
+    for role in roles {
+        let texture = create_texture(&creator, palette.rgba(&role));
 
-    return textures;
+        textures.insert(role, texture);
+    }
+
+    return Textures {
+        textures: textures,
+        levels: palette.levels.len(),
+        palette: palette.clone(),
+    };
 }
 
 fn create_texture(
     creator: &sdl2::render::TextureCreator<sdl2::video::WindowContext>,
-    r: u8,
-    g: u8,
-    b: u8
-) -> sdl2::render::Texture {
+    rgba: Rgba
+) -> sdl2::render::Texture<'_> {
     let mut texture = creator
         .create_texture_streaming(PixelFormatEnum::RGB24, 1, 1).unwrap();
 
     texture.with_lock(None, |buffer: &mut [u8], _| {
-        buffer[0] = r;
-        buffer[1] = g;
-        buffer[2] = b;
+        buffer[0] = rgba[0];
+        buffer[1] = rgba[1];
+        buffer[2] = rgba[2];
     }).unwrap();
 
+    texture.set_blend_mode(BlendMode::Blend);
+    texture.set_alpha_mod(rgba[3]);
+
     return texture;
 }
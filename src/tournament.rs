@@ -0,0 +1,469 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde_json;
+
+use profiles::{data_dir};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    SingleElimination,
+    DoubleElimination,
+    RoundRobin,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "single" | "single-elimination" => Some(Format::SingleElimination),
+            "double" | "double-elimination" => Some(Format::DoubleElimination),
+            "round-robin" => Some(Format::RoundRobin),
+            _ => None,
+        }
+    }
+
+    // How many losses knock a player out.
+    fn lives(&self) -> u32 {
+        match *self {
+            Format::SingleElimination => 1,
+            Format::DoubleElimination => 2,
+            Format::RoundRobin => u32::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TournamentMatch {
+    pub round: u32,
+    // Two players, or just one for a bye.
+    pub players: Vec<String>,
+    pub points: Option<(u32, u32)>,
+    pub winner: Option<String>,
+}
+
+impl TournamentMatch {
+    fn new(round: u32, players: Vec<String>) -> TournamentMatch {
+        // A bye goes straight through.
+        let winner = if players.len() == 1 { Some(players[0].clone()) } else { None };
+
+        TournamentMatch { round: round, players: players, points: None, winner: winner }
+    }
+
+    pub fn played(&self) -> bool {
+        self.winner.is_some()
+    }
+
+    fn loser(&self) -> Option<&String> {
+        match self.winner {
+            Some(ref winner) if self.players.len() == 2 => self.players.iter().find(|player| *player != winner),
+            _ => None,
+        }
+    }
+}
+
+// A tournament between local profiles, saved after every match so it can
+// be picked up again later.
+//
+// Elimination brackets are drawn one round at a time from each player's
+// losses. In double elimination players who haven't lost yet play each
+// other, as do players with one loss, so when the unbeaten player loses the
+// final the two of them play again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tournament {
+    pub name: String,
+    pub format: Format,
+    // In seeding order.
+    pub players: Vec<String>,
+    pub matches: Vec<TournamentMatch>,
+}
+
+impl Tournament {
+    pub fn new(name: &str, format: Format, players: Vec<String>) -> Result<Tournament, String> {
+        Tournament::path(name)?;
+
+        if players.len() < 2 {
+            return Err("a tournament needs at least two players".to_string());
+        }
+
+        for (i, player) in players.iter().enumerate() {
+            if players[..i].contains(player) {
+                return Err(format!("{} is in the tournament twice", player));
+            }
+        }
+
+        let mut tournament = Tournament {
+            name: name.to_string(),
+            format: format,
+            players: players,
+            matches: vec![],
+        };
+
+        if format == Format::RoundRobin {
+            tournament.schedule_round_robin();
+        } else {
+            tournament.schedule_round();
+        }
+
+        Ok(tournament)
+    }
+
+    // Tournament names end up in file names, so only allow the safe ones.
+    pub fn path(name: &str) -> Result<PathBuf, String> {
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+        if !valid {
+            return Err(format!("{}: tournament names can only use letters, numbers, - and _", name));
+        }
+
+        Ok(data_dir().join("tournaments").join(format!("{}.json", name)))
+    }
+
+    pub fn load(name: &str) -> Result<Tournament, String> {
+        let path = Tournament::path(name)?;
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Tournament::path(&self.name)?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+
+        fs::write(&path, serde_json::to_string_pretty(self).unwrap())
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // The next match to play, if the tournament isn't over.
+    pub fn next_match(&self) -> Option<&TournamentMatch> {
+        self.matches.iter().find(|game| !game.played())
+    }
+
+    // Record the result of the next match, `points` being in the order of
+    // its players, and draw the next round if that was the last match of
+    // this one. Draws are replayed.
+    pub fn record(&mut self, points: (u32, u32)) -> () {
+        if points.0 == points.1 { return; }
+
+        let index = match self.matches.iter().position(|game| !game.played()) {
+            Some(index) => index,
+            None => return,
+        };
+
+        {
+            let game = &mut self.matches[index];
+            let winner = if points.0 > points.1 { 0 } else { 1 };
+
+            game.points = Some(points);
+            game.winner = Some(game.players[winner].clone());
+        }
+
+        if self.format != Format::RoundRobin && self.next_match().is_none() {
+            self.schedule_round();
+        }
+    }
+
+    pub fn champion(&self) -> Option<String> {
+        if self.next_match().is_some() { return None; }
+
+        match self.format {
+            Format::RoundRobin => self.standings().first().map(|standing| standing.0.clone()),
+            _ => {
+                let alive = self.alive();
+
+                if alive.len() == 1 { Some(alive[0].clone()) } else { None }
+            }
+        }
+    }
+
+    fn losses(&self, player: &str) -> u32 {
+        self.matches.iter().filter(|game| game.loser().is_some_and(|loser| loser == player)).count() as u32
+    }
+
+    fn alive(&self) -> Vec<String> {
+        let lives = self.format.lives();
+
+        self.players.iter().filter(|player| self.losses(player) < lives).cloned().collect()
+    }
+
+    // How many byes `player` has had.
+    fn byes(&self, player: &str) -> usize {
+        self.matches.iter().filter(|game| game.players.len() == 1 && game.players[0] == player).count()
+    }
+
+    // Pair up the players still in, keeping those with the same number of
+    // losses together. In a group with an odd number of players the lowest
+    // seed of those with the fewest byes sits the round out.
+    fn schedule_round(&mut self) -> () {
+        let alive = self.alive();
+
+        if alive.len() < 2 { return; }
+
+        let round = self.matches.last().map_or(1, |game| game.round + 1);
+        let mut groups: Vec<Vec<String>> = vec![];
+
+        for losses in 0..self.format.lives() {
+            let group: Vec<String> = alive.iter()
+                .filter(|player| self.losses(player) == losses)
+                .cloned()
+                .collect();
+
+            if !group.is_empty() { groups.push(group); }
+        }
+
+        // The last unbeaten player and the last player with a loss meet in
+        // the final.
+        if groups.iter().all(|group| group.len() == 1) {
+            groups = vec![alive];
+        }
+
+        for group in groups {
+            let mut players = group.clone();
+
+            if players.len() % 2 == 1 {
+                let fewest = players.iter().map(|player| self.byes(player)).min().unwrap_or(0);
+                let bye = players.iter().rposition(|player| self.byes(player) == fewest).unwrap();
+
+                let player = players.remove(bye);
+                self.matches.push(TournamentMatch::new(round, vec![player]));
+            }
+
+            // Highest seed plays lowest seed.
+            while players.len() >= 2 {
+                let first = players.remove(0);
+                let last = players.pop().unwrap();
+
+                self.matches.push(TournamentMatch::new(round, vec![first, last]));
+            }
+        }
+
+        // A round of only byes, go straight on to the next one.
+        if self.next_match().is_none() {
+            self.schedule_round();
+        }
+    }
+
+    // Everyone plays everyone once, using the circle method so each round
+    // has every player in at most one match.
+    fn schedule_round_robin(&mut self) -> () {
+        let mut seats: Vec<Option<String>> = self.players.iter().cloned().map(Some).collect();
+
+        if seats.len() % 2 == 1 {
+            seats.push(None);
+        }
+
+        let count = seats.len();
+
+        for round in 0..count - 1 {
+            for i in 0..count / 2 {
+                if let (Some(a), Some(b)) = (seats[i].clone(), seats[count - 1 - i].clone()) {
+                    self.matches.push(TournamentMatch::new(round as u32 + 1, vec![a, b]));
+                }
+            }
+
+            let last = seats.pop().unwrap();
+            seats.insert(1, last);
+        }
+    }
+
+    // Name, wins, losses and points difference, best first.
+    pub fn standings(&self) -> Vec<(String, u32, u32, i64)> {
+        let mut standings: Vec<(String, u32, u32, i64)> = self.players.iter().map(|player| {
+            let mut wins = 0;
+            let mut difference = 0;
+
+            for game in self.matches.iter().filter(|game| game.players.len() == 2) {
+                if let (Some(winner), Some((a, b))) = (game.winner.as_ref(), game.points) {
+                    if game.players[0] == *player {
+                        difference = difference + a as i64 - b as i64;
+                    } else if game.players[1] == *player {
+                        difference = difference + b as i64 - a as i64;
+                    } else {
+                        continue;
+                    }
+
+                    if winner == player { wins = wins + 1; }
+                }
+            }
+
+            (player.clone(), wins, self.losses(player), difference)
+        }).collect();
+
+        standings.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)).then(b.3.cmp(&a.3)));
+
+        return standings;
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![];
+        let mut round = 0;
+
+        for game in self.matches.iter() {
+            if game.round != round {
+                round = game.round;
+                lines.push(format!("round {}", round));
+            }
+
+            lines.push(match (game.players.len(), game.points) {
+                (1, _) => format!("  {} - bye", game.players[0]),
+                (_, Some((a, b))) => format!("  {} {} - {} {}", game.players[0], a, b, game.players[1]),
+                _ => format!("  {} vs {}", game.players[0], game.players[1]),
+            });
+        }
+
+        lines.push(String::new());
+
+        for (i, (player, wins, losses, difference)) in self.standings().iter().enumerate() {
+            lines.push(format!("{:>3}. {:<16} won {:<3} lost {:<3} {:+}", i + 1, player, wins, losses, difference));
+        }
+
+        lines.push(String::new());
+        lines.push(match (self.champion(), self.next_match()) {
+            (Some(champion), _) => format!("{} wins the tournament", champion),
+            (None, Some(game)) => format!("next up: {} vs {}", game.players[0], game.players[1]),
+            (None, None) => String::new(),
+        });
+
+        return lines;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn players(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn seed(tournament: &Tournament, player: &str) -> usize {
+        tournament.players.iter().position(|other| other == player).unwrap()
+    }
+
+    // Play every match to the end, `upset` saying whether the lower seed
+    // wins the match with the given index.
+    fn play_out(tournament: &mut Tournament, upset: &dyn Fn(usize) -> bool) -> () {
+        let mut played = 0;
+
+        while let Some(game) = tournament.next_match().cloned() {
+            let favourite_first = seed(tournament, &game.players[0]) < seed(tournament, &game.players[1]);
+            let first_wins = favourite_first != upset(played);
+
+            tournament.record(if first_wins { (3, 1) } else { (1, 3) });
+            played = played + 1;
+        }
+    }
+
+    #[test]
+    fn rejects_unsafe_names_and_duplicate_players() {
+        assert!(Tournament::new("../../foo", Format::SingleElimination, players(&["a", "b"])).is_err());
+        assert!(Tournament::new("", Format::SingleElimination, players(&["a", "b"])).is_err());
+        assert!(Tournament::new("cup", Format::SingleElimination, players(&["a", "b", "a"])).is_err());
+        assert!(Tournament::new("cup", Format::SingleElimination, players(&["a"])).is_err());
+        assert!(Tournament::new("cup_2", Format::SingleElimination, players(&["a", "b"])).is_ok());
+    }
+
+    #[test]
+    fn lowest_seed_gets_the_bye() {
+        let tournament = Tournament::new("cup", Format::SingleElimination, players(&["a", "b", "c"])).unwrap();
+
+        let round: Vec<Vec<String>> = tournament.matches.iter().map(|game| game.players.clone()).collect();
+
+        assert_eq!(round, vec![players(&["c"]), players(&["a", "b"])]);
+        assert_eq!(tournament.next_match().unwrap().players, players(&["a", "b"]));
+    }
+
+    #[test]
+    fn byes_rotate() {
+        let mut tournament = Tournament::new("cup", Format::DoubleElimination, players(&["a", "b", "c"])).unwrap();
+
+        play_out(&mut tournament, &|_| false);
+
+        // Nobody sits out twice while someone else hasn't sat out at all.
+        let counts: Vec<usize> = ["a", "b", "c"].iter().map(|player| tournament.byes(player)).collect();
+
+        assert!(counts.iter().max().unwrap() - counts.iter().min().unwrap() <= 1, "{:?}", counts);
+    }
+
+    #[test]
+    fn single_elimination_champion() {
+        let mut tournament = Tournament::new("cup", Format::SingleElimination, players(&["a", "b", "c", "d"])).unwrap();
+
+        assert_eq!(tournament.champion(), None);
+
+        play_out(&mut tournament, &|_| false);
+
+        assert_eq!(tournament.matches.len(), 3);
+        assert_eq!(tournament.champion(), Some("a".to_string()));
+        assert!(tournament.next_match().is_none());
+    }
+
+    #[test]
+    fn double_elimination_replays_a_lost_grand_final() {
+        let mut tournament = Tournament::new("cup", Format::DoubleElimination, players(&["a", "b"])).unwrap();
+
+        // b wins the first match, then a wins the final and the rematch.
+        play_out(&mut tournament, &|played| played == 0);
+
+        assert_eq!(tournament.matches.len(), 3);
+        assert!(tournament.matches.iter().all(|game| {
+            let mut names = game.players.clone();
+            names.sort();
+            names == players(&["a", "b"])
+        }));
+        assert_eq!(tournament.champion(), Some("a".to_string()));
+    }
+
+    #[test]
+    fn double_elimination_unbeaten_player_wins_the_final() {
+        let mut tournament = Tournament::new("cup", Format::DoubleElimination, players(&["a", "b", "c", "d"])).unwrap();
+
+        play_out(&mut tournament, &|_| false);
+
+        assert_eq!(tournament.champion(), Some("a".to_string()));
+        assert_eq!(tournament.losses("a"), 0);
+        assert!(["b", "c", "d"].iter().all(|player| tournament.losses(player) == 2));
+    }
+
+    #[test]
+    fn round_robin_plays_everyone_once() {
+        let names = ["a", "b", "c", "d", "e"];
+        let mut tournament = Tournament::new("league", Format::RoundRobin, players(&names)).unwrap();
+
+        assert_eq!(tournament.matches.len(), 10);
+
+        for (i, a) in names.iter().enumerate() {
+            for b in names[i + 1..].iter() {
+                let meetings = tournament.matches.iter()
+                    .filter(|game| game.players.contains(&a.to_string()) && game.players.contains(&b.to_string()))
+                    .count();
+
+                assert_eq!(meetings, 1, "{} and {}", a, b);
+            }
+        }
+
+        // Nobody plays twice in a round.
+        for round in 1..6 {
+            let mut seen: Vec<&String> = tournament.matches.iter()
+                .filter(|game| game.round == round)
+                .flat_map(|game| game.players.iter())
+                .collect();
+            let count = seen.len();
+
+            seen.sort();
+            seen.dedup();
+            assert_eq!(seen.len(), count);
+        }
+
+        play_out(&mut tournament, &|_| false);
+
+        assert_eq!(tournament.champion(), Some("a".to_string()));
+        assert_eq!(tournament.standings()[0], ("a".to_string(), 4, 0, 8));
+    }
+}